@@ -0,0 +1,357 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::anyhow;
+use object::{BinaryFormat, Object, ObjectSection, ObjectSymbol};
+
+use crate::build::CargoCommand;
+use crate::cli::cli_format_path;
+use crate::workspace::CargoContext;
+
+/// Properties of a built binary that BOLT depends on.
+#[derive(Debug, PartialEq)]
+struct ElfInfo {
+    /// The binary contains a (non-dynamic) symbol table.
+    has_symbols: bool,
+    /// The binary contains static relocations of its code, emitted by the linker with `-q`.
+    has_relocations: bool,
+    /// The binary defines the `_fini` finalizer, which the linker references from `DT_FINI`.
+    has_fini: bool,
+}
+
+/// Strip setting of a Cargo profile that removes symbols needed by BOLT.
+#[derive(Debug, PartialEq)]
+struct StripSetting {
+    /// Name of the profile in which the setting was found.
+    profile: String,
+    value: String,
+    /// Where the setting was found, e.g. a path to `Cargo.toml`.
+    source: String,
+}
+
+/// Returns the Cargo profile that will be used to build the binaries for `command`.
+fn get_cargo_profile(command: CargoCommand, cargo_args: &[String]) -> String {
+    let mut args = cargo_args.iter().take_while(|arg| *arg != "--");
+    let mut profile = None;
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            profile = args.next().cloned();
+        } else if let Some(value) = arg.strip_prefix("--profile=") {
+            profile = Some(value.to_string());
+        }
+    }
+    match profile {
+        Some(profile) => profile,
+        None => match command {
+            // `cargo-pgo` does not pass `--release` to `cargo bench`
+            CargoCommand::Bench => "bench".to_string(),
+            _ => "release".to_string(),
+        },
+    }
+}
+
+/// Checks that the Cargo profile used for the build does not strip symbols, which BOLT needs.
+pub(crate) fn check_cargo_profile(
+    ctx: &CargoContext,
+    command: CargoCommand,
+    cargo_args: &[String],
+) -> anyhow::Result<()> {
+    let profile = get_cargo_profile(command, cargo_args);
+    let manifest_path = ctx.workspace_root().join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+        .unwrap_or_default();
+    let setting = find_symbol_strip(
+        &profile,
+        &manifest,
+        &manifest_path.display().to_string(),
+        |key| std::env::var(key).ok(),
+    );
+    match setting {
+        Some(setting) => Err(anyhow!(
+            "Cargo profile `{}` strips symbols (`strip = {}` set in {}), but BOLT requires them. \
+Set `strip = \"debuginfo\"` or `strip = false` in `[profile.{}]`, or use a different profile with \
+`-- --profile <name>`.",
+            profile,
+            setting.value,
+            setting.source,
+            setting.profile
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Finds a `strip` setting that removes symbols from binaries built with `profile`, either in the
+/// environment (`CARGO_PROFILE_<NAME>_STRIP`) or in the `[profile]` tables of `manifest`.
+/// Profiles inherited with `inherits` are also checked.
+fn find_symbol_strip<F: Fn(&str) -> Option<String>>(
+    profile: &str,
+    manifest: &toml::Table,
+    manifest_path: &str,
+    env: F,
+) -> Option<StripSetting> {
+    let profiles = manifest
+        .get("profile")
+        .and_then(|profiles| profiles.as_table());
+
+    let mut current = profile.to_string();
+    // Guard against inheritance cycles
+    for _ in 0..8 {
+        let key = format!(
+            "CARGO_PROFILE_{}_STRIP",
+            current.to_uppercase().replace('-', "_")
+        );
+        if let Some(value) = env(&key) {
+            return strips_symbols(&value).then(|| StripSetting {
+                profile: current.clone(),
+                value: format!("\"{value}\""),
+                source: format!("the `{key}` environment variable"),
+            });
+        }
+
+        let table = profiles
+            .and_then(|profiles| profiles.get(&current))
+            .and_then(|table| table.as_table());
+        if let Some(value) = table.and_then(|table| table.get("strip")) {
+            let strips = match value {
+                toml::Value::Boolean(value) => *value,
+                toml::Value::String(value) => strips_symbols(value),
+                _ => false,
+            };
+            return strips.then(|| StripSetting {
+                profile: current.clone(),
+                value: value.to_string(),
+                source: manifest_path.to_string(),
+            });
+        }
+
+        let parent = table
+            .and_then(|table| table.get("inherits"))
+            .and_then(|inherits| inherits.as_str());
+        current = match (parent, current.as_str()) {
+            (Some(parent), _) => parent.to_string(),
+            // The built-in `bench` profile inherits from `release`
+            (None, "bench") => "release".to_string(),
+            (None, _) => return None,
+        };
+    }
+    None
+}
+
+fn strips_symbols(value: &str) -> bool {
+    matches!(value, "true" | "symbols")
+}
+
+/// Reads the symbol table and relocation information of an ELF binary.
+/// Returns `None` if the file is not an ELF binary.
+fn inspect_elf(path: &Path) -> anyhow::Result<Option<ElfInfo>> {
+    let data = std::fs::read(path)?;
+    let file = match object::File::parse(data.as_slice()) {
+        Ok(file) if file.format() == BinaryFormat::Elf => file,
+        _ => return Ok(None),
+    };
+    let has_relocations = file.sections().any(|section| {
+        section
+            .name()
+            .is_ok_and(|name| name.starts_with(".rela.text") || name.starts_with(".rel.text"))
+    });
+    let has_fini = file
+        .symbols()
+        .any(|symbol| symbol.is_definition() && symbol.name() == Ok("_fini"));
+    Ok(Some(ElfInfo {
+        has_symbols: file.symbol_table().is_some(),
+        has_relocations,
+        has_fini,
+    }))
+}
+
+/// Returns the GNU build ID of an ELF binary as a hex string, if it has one.
+pub(crate) fn read_build_id(path: &Path) -> anyhow::Result<Option<String>> {
+    let data = std::fs::read(path)?;
+    let build_id = match object::File::parse(data.as_slice()) {
+        Ok(file) => file.build_id().ok().flatten().map(|id| {
+            id.iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>()
+        }),
+        Err(_) => None,
+    };
+    Ok(build_id)
+}
+
+/// Returns the names of all symbols defined in the symbol table of `path`.
+pub(crate) fn read_symbol_names(path: &Path) -> anyhow::Result<HashSet<String>> {
+    let data = std::fs::read(path)?;
+    let names = match object::File::parse(data.as_slice()) {
+        Ok(file) => file
+            .symbols()
+            .filter_map(|symbol| symbol.name().ok().map(|name| name.to_string()))
+            .collect(),
+        Err(_) => HashSet::new(),
+    };
+    Ok(names)
+}
+
+/// Checks that `binary` can be processed by BOLT, i.e. that it was not stripped and that it
+/// contains relocations.
+pub(crate) fn check_bolt_binary(binary: &Path) -> anyhow::Result<()> {
+    let info = match inspect_elf(binary)? {
+        Some(info) => info,
+        None => return Ok(()),
+    };
+    if !info.has_symbols {
+        return Err(anyhow!(
+            "{} does not contain a symbol table, which is required by BOLT. Make sure that it is not \
+stripped, i.e. that `strip` is not set to `true` or `\"symbols\"` in the `[profile]` section of \
+Cargo.toml and that `-Clink-arg=-s` or `-Cstrip=symbols` is not passed in RUSTFLAGS.",
+            cli_format_path(binary.display())
+        ));
+    }
+    if !info.has_relocations {
+        return Err(anyhow!(
+            "{} does not contain relocations, which are required by BOLT. `cargo-pgo` links binaries \
+with `-Clink-args=-Wl,-q`; make sure that this flag is not overridden by RUSTFLAGS or by \
+`rustflags` in `.cargo/config.toml`, and that your linker supports `--emit-relocs`.",
+            cli_format_path(binary.display())
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the shared `library` can be instrumented by BOLT. Unlike an executable, an
+/// instrumented library has no exit hook in which the BOLT runtime could write the profile, so the
+/// runtime hooks the finalizer of the library (`DT_FINI`) instead, which is executed when the
+/// library is unloaded or when the process exits. Relocations of position-independent code are
+/// handled by BOLT in the same way as for executables (`-relocs`), so no other flags are needed.
+pub(crate) fn check_bolt_library_instrumentation(library: &Path) -> anyhow::Result<()> {
+    match inspect_elf(library)? {
+        Some(info) if !info.has_fini => Err(anyhow!(
+            "{} does not define the `_fini` finalizer, which is required by BOLT to write the \
+profiles of an instrumented shared library. Make sure that it is linked with the default C runtime \
+startup files (i.e. without `-nostartfiles`).",
+            cli_format_path(library.display())
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::binary::{StripSetting, find_symbol_strip, get_cargo_profile, inspect_elf};
+    use crate::build::CargoCommand;
+
+    fn find_strip(profile: &str, manifest: &str, env: &[(&str, &str)]) -> Option<StripSetting> {
+        let manifest: toml::Table = manifest.parse().unwrap();
+        find_symbol_strip(profile, &manifest, "Cargo.toml", |key| {
+            env.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn profile_from_args() {
+        assert_eq!(get_cargo_profile(CargoCommand::Build, &[]), "release");
+        assert_eq!(get_cargo_profile(CargoCommand::Bench, &[]), "bench");
+        assert_eq!(
+            get_cargo_profile(
+                CargoCommand::Build,
+                &["--profile".to_string(), "dist".to_string()]
+            ),
+            "dist"
+        );
+        assert_eq!(
+            get_cargo_profile(
+                CargoCommand::Build,
+                &[
+                    "--profile=dist".to_string(),
+                    "--".to_string(),
+                    "--profile=foo".to_string()
+                ]
+            ),
+            "dist"
+        );
+    }
+
+    #[test]
+    fn strip_not_set() {
+        assert_eq!(
+            find_strip("release", "[profile.release]\nlto = true", &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn strip_debuginfo() {
+        assert_eq!(
+            find_strip("release", "[profile.release]\nstrip = \"debuginfo\"", &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn strip_symbols() {
+        let setting = find_strip("release", "[profile.release]\nstrip = true", &[]).unwrap();
+        assert_eq!(setting.profile, "release");
+        assert_eq!(setting.value, "true");
+        assert_eq!(setting.source, "Cargo.toml");
+    }
+
+    #[test]
+    fn strip_inherited() {
+        let manifest = r#"
+[profile.release]
+strip = "symbols"
+
+[profile.dist]
+inherits = "release"
+lto = true
+"#;
+        let setting = find_strip("dist", manifest, &[]).unwrap();
+        assert_eq!(setting.profile, "release");
+        assert_eq!(setting.value, "\"symbols\"");
+    }
+
+    #[test]
+    fn strip_bench_inherits_release() {
+        assert!(find_strip("bench", "[profile.release]\nstrip = true", &[]).is_some());
+    }
+
+    #[test]
+    fn strip_env_overrides_manifest() {
+        assert!(
+            find_strip(
+                "release",
+                "[profile.release]\nstrip = true",
+                &[("CARGO_PROFILE_RELEASE_STRIP", "none")]
+            )
+            .is_none()
+        );
+        let setting = find_strip("release", "", &[("CARGO_PROFILE_RELEASE_STRIP", "symbols")]);
+        assert!(
+            setting
+                .unwrap()
+                .source
+                .contains("CARGO_PROFILE_RELEASE_STRIP")
+        );
+    }
+
+    #[test]
+    fn inspect_non_elf() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo");
+        std::fs::write(&path, "not a binary").unwrap();
+        assert_eq!(inspect_elf(&path).unwrap(), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn inspect_test_binary() {
+        let info = inspect_elf(&std::env::current_exe().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(info.has_symbols);
+        assert!(info.has_fini);
+    }
+}
@@ -1,10 +1,71 @@
-#[derive(clap::Parser, Debug)]
+use crate::bolt::preset::BoltPreset;
+use crate::config::PgoConfig;
+
+#[derive(clap::Parser, Debug, Clone)]
 pub struct BoltArgs {
     /// Flags that will be passed to the BOLT command.
     /// Using this flag will override BOLT flags normally used by `cargo-pgo`.
     /// If you want to disable all flags, pass an empty string (`--bolt-args ""`).
     #[clap(long, allow_hyphen_values(true))]
     pub(crate) bolt_args: Option<String>,
+
+    /// Set of flags that will be passed to BOLT when optimizing a binary. The flags are adjusted
+    /// to the version of the installed `llvm-bolt`.
+    #[clap(long, value_enum, conflicts_with = "bolt_args")]
+    pub(crate) bolt_preset: Option<BoltPreset>,
+
+    /// Flags that will be passed to the BOLT command in addition to the flags normally used by
+    /// `cargo-pgo` (or the flags passed with `--bolt-args`).
+    #[clap(long, allow_hyphen_values(true))]
+    pub(crate) bolt_extra_args: Option<String>,
+}
+
+impl BoltArgs {
+    /// Uses BOLT flags from the configuration if no flags were passed on the command line.
+    pub(crate) fn with_config_defaults(self, config: &PgoConfig) -> Self {
+        // A preset passed on the command line takes precedence over flags from the configuration
+        let bolt_args = match self.bolt_preset {
+            Some(_) => self.bolt_args,
+            None => self.bolt_args.or_else(|| config.bolt_args.clone()),
+        };
+        BoltArgs {
+            bolt_args,
+            bolt_preset: self.bolt_preset.or(config.bolt_preset),
+            bolt_extra_args: self
+                .bolt_extra_args
+                .or_else(|| config.bolt_extra_args.clone()),
+        }
+    }
+
+    /// Adds flags used for instrumenting a binary to `args`.
+    pub(crate) fn add_instrument_args(&self, args: &mut Vec<String>) -> anyhow::Result<()> {
+        match self.bolt_args {
+            Some(ref bolt_args) => add_bolt_args(args, bolt_args)?,
+            None => args.push("-update-debug-sections".to_string()),
+        }
+        self.add_extra_args(args)
+    }
+
+    /// Adds flags used for optimizing a binary with the given major version of `llvm-bolt`
+    /// to `args`.
+    pub(crate) fn add_optimize_args(
+        &self,
+        args: &mut Vec<String>,
+        bolt_version: Option<u32>,
+    ) -> anyhow::Result<()> {
+        match self.bolt_args {
+            Some(ref bolt_args) => add_bolt_args(args, bolt_args)?,
+            None => args.extend(self.bolt_preset.unwrap_or_default().flags(bolt_version)),
+        }
+        self.add_extra_args(args)
+    }
+
+    fn add_extra_args(&self, args: &mut Vec<String>) -> anyhow::Result<()> {
+        match self.bolt_extra_args {
+            Some(ref extra_args) => add_bolt_args(args, extra_args),
+            None => Ok(()),
+        }
+    }
 }
 
 pub fn add_bolt_args(args: &mut Vec<String>, bolt_args: &str) -> anyhow::Result<()> {
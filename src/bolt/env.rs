@@ -1,25 +1,66 @@
-use crate::resolve_binary;
+use crate::error::tool_not_found;
+use crate::pgo::env::parse_llvm_version;
+use crate::pgo::sample::env::find_perf;
+use crate::{resolve_binary, run_command};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub struct BoltEnv {
     pub bolt: PathBuf,
     pub merge_fdata: PathBuf,
+    /// Major LLVM version of `llvm-bolt`, if it could be determined.
+    pub version: Option<u32>,
 }
 
 pub(crate) fn find_llvm_bolt() -> anyhow::Result<PathBuf> {
-    resolve_binary(Path::new("llvm-bolt"))
-        .map_err(|error| anyhow::anyhow!("Cannot find llvm-bolt: {:?}", error))
+    resolve_binary(Path::new("llvm-bolt")).map_err(|error| tool_not_found("llvm-bolt", error))
 }
 
 pub(crate) fn find_merge_fdata() -> anyhow::Result<PathBuf> {
-    resolve_binary(Path::new("merge-fdata"))
-        .map_err(|error| anyhow::anyhow!("Cannot find merge-fdata: {:?}", error))
+    resolve_binary(Path::new("merge-fdata")).map_err(|error| tool_not_found("merge-fdata", error))
 }
 
 pub fn find_bolt_env() -> anyhow::Result<BoltEnv> {
     let bolt = find_llvm_bolt()?;
     let merge_fdata = find_merge_fdata()?;
+    let version = get_llvm_bolt_version(&bolt);
 
-    Ok(BoltEnv { bolt, merge_fdata })
+    Ok(BoltEnv {
+        bolt,
+        merge_fdata,
+        version,
+    })
+}
+
+/// Returns the major LLVM version of the given `llvm-bolt` binary.
+fn get_llvm_bolt_version(bolt: &Path) -> Option<u32> {
+    let version = run_command(bolt, &["--version"])
+        .and_then(|output| output.ok())
+        .ok()
+        .and_then(|output| parse_llvm_version(&output.stdout));
+    match version {
+        Some(version) => log::debug!("Detected llvm-bolt version {version}."),
+        None => log::warn!(
+            "Cannot determine the version of llvm-bolt, BOLT flags will not be adjusted to it."
+        ),
+    }
+    version
+}
+
+/// Binaries required for gathering BOLT profiles with `perf`.
+#[derive(Debug)]
+pub struct BoltRecordEnv {
+    pub perf: PathBuf,
+    pub perf2bolt: PathBuf,
+}
+
+pub(crate) fn find_perf2bolt() -> anyhow::Result<PathBuf> {
+    resolve_binary(Path::new("perf2bolt")).map_err(|error| tool_not_found("perf2bolt", error))
+}
+
+pub fn find_bolt_record_env() -> anyhow::Result<BoltRecordEnv> {
+    let perf = find_perf()?;
+    let perf2bolt = find_perf2bolt()?;
+
+    Ok(BoltRecordEnv { perf, perf2bolt })
 }
@@ -1,41 +1,67 @@
-use anyhow::anyhow;
 use std::path::{Path, PathBuf};
 
+use cargo_metadata::Message;
 use cargo_metadata::camino::Utf8PathBuf;
-use cargo_metadata::{Artifact, Message};
 use colored::Colorize;
 
-use crate::bolt::cli::{BoltArgs, add_bolt_args};
+use crate::bolt::binary::{
+    check_bolt_binary, check_bolt_library_instrumentation, check_cargo_profile,
+};
+use crate::bolt::cli::BoltArgs;
 use crate::bolt::env::{BoltEnv, find_bolt_env};
-use crate::bolt::{bolt_pgo_rustflags, get_binary_profile_dir};
+use crate::bolt::profile::store_build_id;
+use crate::bolt::replace::restore_original_binary;
+use crate::bolt::{
+    Harness, bolt_cargo_args, bolt_output_path, bolt_pgo_rustflags, get_binary_profile_dir,
+    get_bolt_binary, run_harnesses,
+};
 use crate::build::{
-    CargoCommand, cargo_command_with_rustflags, get_artifact_kind, handle_metadata_message,
+    BuiltArtifact, CargoCommand, cargo_command_with_rustflags, get_artifact_kind,
+    handle_metadata_message,
 };
 use crate::cli::cli_format_path;
+use crate::error::PgoError;
+use crate::output::{Event, Tool, emit};
+use crate::pgo::optimize::parse_profile_weight;
 use crate::utils::str::capitalize;
 use crate::workspace::CargoContext;
 use crate::{clear_directory, run_command};
 
 #[derive(clap::Parser, Debug)]
 pub struct BoltInstrumentArgs {
+    /// Cargo command that will be used to build the instrumented artifact(s). With `test` and
+    /// `bench`, all test or benchmark executables are instrumented and then executed.
+    #[clap(value_enum, default_value = "build")]
+    pub(crate) command: CargoCommand,
+
     /// Instrument a PGO-optimized binary. To use this, you must already have PGO profiles on disk.
     /// Later also pass the same flag to `cargo pgo bolt optimize`.
     #[clap(long)]
-    with_pgo: bool,
+    pub(crate) with_pgo: bool,
+
+    /// Weight of PGO profiles stored in a subdirectory of the PGO profile directory, in the form
+    /// `<subdirectory>=<weight>`. Use the same weights as for `cargo pgo optimize`.
+    #[clap(long = "profile-weight", value_parser = parse_profile_weight, requires = "with_pgo")]
+    pub(crate) profile_weights: Vec<(String, u32)>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long, requires = "with_pgo")]
+    pub(crate) allow_llvm_mismatch: bool,
 
     /// Do not remove profiles that were gathered during previous runs.
     #[clap(long, action)]
-    keep_profiles: bool,
+    pub(crate) keep_profiles: bool,
 
     /// Override the BOLT profile path.
     #[clap(long)]
-    profiles_dir: Option<PathBuf>,
+    pub(crate) profiles_dir: Option<PathBuf>,
 
     #[clap(flatten)]
-    bolt_args: BoltArgs,
+    pub(crate) bolt_args: BoltArgs,
     /// Additional arguments that will be passed to `cargo build`.
     #[arg(last(true))]
-    cargo_args: Vec<String>,
+    pub(crate) cargo_args: Vec<String>,
 }
 
 impl BoltInstrumentArgs {
@@ -48,8 +74,22 @@ impl BoltInstrumentArgs {
     }
 }
 
-pub fn bolt_instrument(ctx: CargoContext, args: BoltInstrumentArgs) -> anyhow::Result<()> {
+pub fn bolt_instrument(ctx: &CargoContext, args: BoltInstrumentArgs) -> anyhow::Result<()> {
+    instrument(ctx, args)?;
+    Ok(())
+}
+
+/// Performs a build, instruments the built executables and shared libraries with BOLT and returns
+/// the paths of the instrumented executables, together with the directories into which they store
+/// their profiles.
+pub(crate) fn instrument(
+    ctx: &CargoContext,
+    args: BoltInstrumentArgs,
+) -> anyhow::Result<Vec<(BuiltArtifact, PathBuf)>> {
+    let cargo_args = ctx.resolve_cargo_args(args.cargo_args);
+    let build_args = bolt_cargo_args(args.command, cargo_args.clone())?;
     let bolt_dir = ctx.get_bolt_directory()?;
+    check_cargo_profile(ctx, args.command, &cargo_args)?;
     let bolt_env = find_bolt_env()?;
 
     if !args.keep_profiles {
@@ -61,36 +101,83 @@ pub fn bolt_instrument(ctx: CargoContext, args: BoltInstrumentArgs) -> anyhow::R
         "BOLT profiles will be stored into {}.",
         cli_format_path(bolt_dir.display())
     );
-
-    let flags = bolt_pgo_rustflags(&ctx, args.with_pgo)?;
-    let mut cargo = cargo_command_with_rustflags(CargoCommand::Build, flags, args.cargo_args)?;
-
+    emit(Event::ProfileDir {
+        tool: Tool::Bolt,
+        path: &bolt_dir,
+    });
+
+    let flags = bolt_pgo_rustflags(
+        ctx,
+        args.with_pgo,
+        args.profile_weights,
+        args.allow_llvm_mismatch,
+    )?;
+    let bolt_args = args.bolt_args.with_config_defaults(ctx.config());
+    let mut cargo = cargo_command_with_rustflags(args.command, flags, build_args)?;
+
+    let mut artifacts = vec![];
+    let mut harnesses = vec![];
     for message in cargo.messages() {
         let message = message?;
         match message {
             Message::CompilerArtifact(artifact) => {
-                if let Some(ref executable) = artifact.executable {
+                if let Some(binary) = get_bolt_binary(args.command, &artifact) {
                     log::info!(
                         "{} {} built successfully. It will be now instrumented with BOLT.",
                         capitalize(get_artifact_kind(&artifact)).yellow(),
                         artifact.target.name.blue(),
                     );
-                    let instrumented_path = instrument_binary(
-                        &bolt_env,
-                        &args.bolt_args,
-                        executable,
-                        &bolt_dir,
-                        &artifact,
-                    )?;
-                    log::info!(
-                        "{} {} instrumented successfully. Now run {} on your workload.",
-                        capitalize(get_artifact_kind(&artifact)).yellow(),
-                        artifact.target.name.blue(),
-                        cli_format_path(instrumented_path.display())
-                    );
+                    emit(Event::ArtifactBuilt {
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: binary.as_std_path(),
+                    });
+                    let profile_dir = get_binary_profile_dir(&bolt_dir, &artifact);
+                    restore_original_binary(binary.as_std_path(), artifact.fresh)?;
+                    if artifact.executable.is_none() {
+                        check_bolt_library_instrumentation(binary.as_std_path())?;
+                    }
+                    let instrumented_path =
+                        instrument_binary(&bolt_env, &bolt_args, binary, &profile_dir)?;
+                    emit(Event::Instrumented {
+                        tool: Tool::Bolt,
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: &instrumented_path,
+                    });
+                    if artifact.executable.is_none() {
+                        log::info!(
+                            "{} {} instrumented successfully. Now load {} into your application \
+instead of the original library and run your workload.",
+                            capitalize(get_artifact_kind(&artifact)).yellow(),
+                            artifact.target.name.blue(),
+                            cli_format_path(instrumented_path.display())
+                        );
+                        continue;
+                    }
+                    if let CargoCommand::Build = args.command {
+                        log::info!(
+                            "{} {} instrumented successfully. Now run {} on your workload.",
+                            capitalize(get_artifact_kind(&artifact)).yellow(),
+                            artifact.target.name.blue(),
+                            cli_format_path(instrumented_path.display())
+                        );
+                    } else {
+                        harnesses.push(Harness::new(instrumented_path.clone(), &artifact));
+                    }
+                    artifacts.push((
+                        BuiltArtifact {
+                            name: artifact.target.name.clone(),
+                            path: instrumented_path,
+                        },
+                        profile_dir,
+                    ));
                 }
             }
             Message::BuildFinished(res) => {
+                emit(Event::BuildFinished {
+                    success: res.success,
+                });
                 if res.success {
                     log::info!(
                         "BOLT instrumentation build finished {}.",
@@ -106,30 +193,24 @@ pub fn bolt_instrument(ctx: CargoContext, args: BoltInstrumentArgs) -> anyhow::R
 
     cargo.check_status()?;
 
-    Ok(())
+    run_harnesses(args.command, &harnesses, &cargo_args)?;
+
+    Ok(artifacts)
 }
 
-/// Instruments a binary using BOLT.
+/// Instruments a binary using BOLT, so that it stores its profiles into `profile_dir`.
 /// If it succeeds, returns the path to the instrumented binary.
 fn instrument_binary(
     bolt_env: &BoltEnv,
     bolt_args: &BoltArgs,
     path: &Utf8PathBuf,
     profile_dir: &Path,
-    artifact: &Artifact,
 ) -> anyhow::Result<PathBuf> {
-    let basename = path
-        .as_path()
-        .file_stem()
-        .expect("Cannot extract executable basename");
+    check_bolt_binary(path.as_std_path())?;
+    let target_path = bolt_output_path(path, "bolt-instrumented");
 
-    let target_path = path
-        .parent()
-        .expect("Cannot get parent of compiled binary")
-        .join(format!("{basename}-bolt-instrumented"));
-
-    let profile_dir = get_binary_profile_dir(profile_dir, artifact);
-    std::fs::create_dir_all(&profile_dir)?;
+    std::fs::create_dir_all(profile_dir)?;
+    store_build_id(path.as_std_path(), profile_dir)?;
 
     let profile_path = profile_dir.join("profile");
 
@@ -146,14 +227,11 @@ fn instrument_binary(
         target_path.as_str().to_string(),
     ];
 
-    match bolt_args.bolt_args {
-        Some(ref bolt_args) => add_bolt_args(&mut args, bolt_args)?,
-        None => args.push("-update-debug-sections".to_string()),
-    }
+    bolt_args.add_instrument_args(&mut args)?;
 
     let output = run_command(&bolt_env.bolt, &args)?
         .ok()
-        .map_err(|error| anyhow!("Cannot instrument binary with BOLT: {}.", error))?;
+        .map_err(|error| PgoError::Bolt(format!("Cannot instrument binary with BOLT: {error}.")))?;
 
     log::debug!("BOLT instrumentation stdout\n{}\n\n", output.stdout);
     log::debug!("BOLT instrumentation stderr\n{}", output.stderr);
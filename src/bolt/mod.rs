@@ -1,12 +1,25 @@
-use crate::pgo::optimize::{get_pgo_env, prepare_pgo_optimization_flags};
+use crate::build::{CargoCommand, get_shared_library};
+use crate::output::child_stdout;
+use crate::pgo::optimize::{find_checked_pgo_env, prepare_pgo_optimization_flags};
 use crate::workspace::CargoContext;
+use anyhow::anyhow;
 use cargo_metadata::Artifact;
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use colored::Colorize;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
+pub(crate) mod binary;
 pub mod cli;
 pub(crate) mod env;
 pub mod instrument;
 pub mod optimize;
+pub mod preset;
+pub(crate) mod profile;
+pub mod record;
+pub(crate) mod replace;
+pub mod run;
+pub mod stats;
 
 pub fn llvm_bolt_install_hint() -> &'static str {
     "Build LLVM with BOLT and add its `bin` directory to PATH."
@@ -16,12 +29,20 @@ fn bolt_common_rustflags() -> Vec<String> {
     vec!["-Clink-args=-Wl,-q".to_string()]
 }
 
-fn bolt_pgo_rustflags(ctx: &CargoContext, with_pgo: bool) -> anyhow::Result<Vec<String>> {
+/// Returns RUSTFLAGS for a build processed by BOLT. With `with_pgo`, the PGO profiles are merged
+/// with the given `profile_weights`, so that the same profile as in the PGO-optimized build is used.
+fn bolt_pgo_rustflags(
+    ctx: &CargoContext,
+    with_pgo: bool,
+    profile_weights: Vec<(String, u32)>,
+    allow_llvm_mismatch: bool,
+) -> anyhow::Result<Vec<String>> {
     let flags = match with_pgo {
         true => {
-            let pgo_env = get_pgo_env()?;
+            let pgo_env = find_checked_pgo_env(allow_llvm_mismatch)?;
             let pgo_dir = ctx.get_pgo_directory()?;
-            let mut flags = prepare_pgo_optimization_flags(&pgo_env, &pgo_dir)?;
+            let weights = ctx.resolve_profile_weights(profile_weights);
+            let mut flags = prepare_pgo_optimization_flags(&pgo_env, &pgo_dir, &weights)?;
             flags.extend(bolt_common_rustflags());
             flags
         }
@@ -32,5 +53,172 @@ fn bolt_pgo_rustflags(ctx: &CargoContext, with_pgo: bool) -> anyhow::Result<Vec<
 
 fn get_binary_profile_dir(bolt_dir: &Path, artifact: &Artifact) -> PathBuf {
     let name = &artifact.target.name;
+    if artifact.executable.is_none() {
+        // Use the file name of the library (e.g. `libfoo`), so that its profiles do not clash with
+        // a binary with the same name
+        if let Some(stem) = get_shared_library(artifact).and_then(|library| library.file_stem()) {
+            return bolt_dir.join(stem);
+        }
+    }
+    if artifact.profile.test {
+        // Test harnesses of a library and of a binary with the same name would otherwise share
+        // the same directory.
+        let kind = artifact
+            .target
+            .kind
+            .first()
+            .map(|kind| kind.to_string())
+            .unwrap_or_default();
+        return bolt_dir.join(format!("{name}-{kind}"));
+    }
     bolt_dir.join(name)
 }
+
+/// Returns the binary of `artifact` that should be processed by BOLT, i.e. its executable or,
+/// when building with `cargo build`, the shared library that it produces.
+fn get_bolt_binary(command: CargoCommand, artifact: &Artifact) -> Option<&Utf8PathBuf> {
+    match (&artifact.executable, command) {
+        (Some(executable), _) => Some(executable),
+        (None, CargoCommand::Build) => get_shared_library(artifact),
+        (None, _) => None,
+    }
+}
+
+/// Returns the path of a binary created by BOLT next to `binary`, with the given `suffix` added to
+/// its name, e.g. `foo-bolt-optimized` or `libfoo-bolt-optimized.so`.
+pub(crate) fn bolt_output_path(binary: &Utf8Path, suffix: &str) -> Utf8PathBuf {
+    let basename = binary
+        .file_stem()
+        .expect("Cannot extract executable basename");
+    let file_name = match binary.extension() {
+        Some(extension) => format!("{basename}-{suffix}.{extension}"),
+        None => format!("{basename}-{suffix}"),
+    };
+    binary
+        .parent()
+        .expect("Cannot get parent of compiled binary")
+        .join(file_name)
+}
+
+/// Returns Cargo arguments that build the executables processed by BOLT. Test and benchmark
+/// executables are only built, because they have to be processed by BOLT before being executed.
+fn bolt_cargo_args(command: CargoCommand, cargo_args: Vec<String>) -> anyhow::Result<Vec<String>> {
+    match command {
+        CargoCommand::Build => Ok(cargo_args),
+        CargoCommand::Test | CargoCommand::Bench => {
+            let mut args = vec!["--no-run".to_string()];
+            args.extend(cargo_args);
+            Ok(args)
+        }
+        CargoCommand::Run => Err(anyhow!(
+            "`run` cannot be used with BOLT, use `cargo pgo bolt run` instead"
+        )),
+    }
+}
+
+/// A test or benchmark executable processed by BOLT, which should be executed in the same way
+/// as `cargo test` or `cargo bench` would do it.
+struct Harness {
+    path: PathBuf,
+    /// Directory of the package of the harness, used as its working directory.
+    package_dir: PathBuf,
+}
+
+impl Harness {
+    fn new(path: PathBuf, artifact: &Artifact) -> Self {
+        Harness {
+            path,
+            package_dir: artifact
+                .manifest_path
+                .parent()
+                .map(|dir| dir.to_path_buf().into_std_path_buf())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Executes test or benchmark `harnesses` with the arguments passed after `--` in `cargo_args`.
+fn run_harnesses(
+    command: CargoCommand,
+    harnesses: &[Harness],
+    cargo_args: &[String],
+) -> anyhow::Result<()> {
+    let mut args: Vec<String> = match command {
+        CargoCommand::Bench => vec!["--bench".to_string()],
+        _ => vec![],
+    };
+    args.extend(
+        cargo_args
+            .iter()
+            .skip_while(|arg| *arg != "--")
+            .skip(1)
+            .cloned(),
+    );
+
+    for harness in harnesses {
+        log::info!("Running {}.", harness.path.display().to_string().blue());
+        let status = Command::new(&harness.path)
+            .args(&args)
+            .current_dir(&harness.package_dir)
+            .stdout(child_stdout())
+            .status()
+            .map_err(|error| anyhow!("Cannot execute {}: {}", harness.path.display(), error))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "{} has {} ({})",
+                harness.path.display(),
+                "failed".red(),
+                status
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::{bolt_cargo_args, bolt_output_path};
+    use crate::build::CargoCommand;
+    use cargo_metadata::camino::Utf8Path;
+
+    #[test]
+    fn output_path_executable() {
+        assert_eq!(
+            bolt_output_path(Utf8Path::new("/target/release/foo"), "bolt-optimized"),
+            Utf8Path::new("/target/release/foo-bolt-optimized")
+        );
+    }
+
+    #[test]
+    fn output_path_library() {
+        assert_eq!(
+            bolt_output_path(
+                Utf8Path::new("/target/release/libfoo.so"),
+                "bolt-instrumented"
+            ),
+            Utf8Path::new("/target/release/libfoo-bolt-instrumented.so")
+        );
+    }
+
+    #[test]
+    fn cargo_args_build() {
+        let args = bolt_cargo_args(CargoCommand::Build, vec!["--bin".to_string()]).unwrap();
+        assert_eq!(args, vec!["--bin".to_string()]);
+    }
+
+    #[test]
+    fn cargo_args_test_no_run() {
+        let args = bolt_cargo_args(
+            CargoCommand::Test,
+            vec!["--".to_string(), "--nocapture".to_string()],
+        )
+        .unwrap();
+        assert_eq!(args[0], "--no-run");
+        assert_eq!(args[2], "--nocapture");
+    }
+
+    #[test]
+    fn cargo_args_run() {
+        assert!(bolt_cargo_args(CargoCommand::Run, vec![]).is_err());
+    }
+}
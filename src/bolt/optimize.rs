@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -7,32 +8,71 @@ use cargo_metadata::Message;
 use cargo_metadata::camino::Utf8PathBuf;
 use colored::Colorize;
 
-use crate::bolt::cli::{BoltArgs, add_bolt_args};
+use crate::bolt::binary::{check_bolt_binary, check_cargo_profile};
+use crate::bolt::cli::BoltArgs;
 use crate::bolt::env::{BoltEnv, find_bolt_env};
-use crate::bolt::{bolt_pgo_rustflags, get_binary_profile_dir};
+use crate::bolt::profile::{gather_bolt_profile_stats, print_bolt_profile_stats};
+use crate::bolt::replace::{replace_binary, restore_original_binary};
+use crate::bolt::stats::{BoltStats, parse_bolt_stats, print_bolt_stats, store_bolt_stats};
+use crate::bolt::{
+    Harness, bolt_cargo_args, bolt_output_path, bolt_pgo_rustflags, get_binary_profile_dir,
+    get_bolt_binary, run_harnesses,
+};
 use crate::build::{
     CargoCommand, cargo_command_with_rustflags, get_artifact_kind, handle_metadata_message,
 };
 use crate::cli::cli_format_path;
-use crate::run_command;
+use crate::error::PgoError;
+use crate::output::{Event, OutputFormat, Tool, emit, output_format};
+use crate::pgo::optimize::{emit_merged_profile, parse_profile_weight};
 use crate::utils::file::gather_files_with_extension;
 use crate::utils::str::capitalize;
 use crate::workspace::CargoContext;
+use crate::{ensure_directory, run_command};
+
+/// Name of the file with BOLT statistics of the optimized binaries, stored in the BOLT profile
+/// directory.
+const DYNO_STATS_FILE_NAME: &str = "dyno-stats.json";
 
 #[derive(clap::Parser, Debug)]
 pub struct BoltOptimizeArgs {
+    /// Cargo command that will be used to build the optimized artifact(s). With `test` and
+    /// `bench`, all test or benchmark executables are optimized.
+    #[clap(value_enum, default_value = "build")]
+    pub(crate) command: CargoCommand,
     /// Optimize a PGO-optimized binary. To use this, you must already have PGO profiles on disk.
     /// Use this flag only if you have also used it for `cargo pgo bolt build`.
     #[clap(long)]
-    with_pgo: bool,
+    pub(crate) with_pgo: bool,
+
+    /// Weight of PGO profiles stored in a subdirectory of the PGO profile directory, in the form
+    /// `<subdirectory>=<weight>`. Use the same weights as for `cargo pgo optimize`.
+    #[clap(long = "profile-weight", value_parser = parse_profile_weight, requires = "with_pgo")]
+    pub(crate) profile_weights: Vec<(String, u32)>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long, requires = "with_pgo")]
+    pub(crate) allow_llvm_mismatch: bool,
     #[clap(flatten)]
-    bolt_args: BoltArgs,
+    pub(crate) bolt_args: BoltArgs,
     /// Override the BOLT profile path.
     #[clap(long)]
-    profiles_dir: Option<PathBuf>,
+    pub(crate) profiles_dir: Option<PathBuf>,
+    /// Replace the original binaries with the optimized ones. A backup of each original binary
+    /// is kept next to it with the `.pre-bolt` suffix.
+    #[clap(long)]
+    pub(crate) replace: bool,
+    /// Copy the final optimized binaries (under their original names) into the given directory.
+    #[clap(long)]
+    pub(crate) output_dir: Option<PathBuf>,
+    /// Execute the optimized test or benchmark executables after they are optimized. Can only be
+    /// used with `test` and `bench`.
+    #[clap(long)]
+    pub(crate) run: bool,
     /// Additional arguments that will be passed to `cargo build`.
     #[arg(last(true))]
-    cargo_args: Vec<String>,
+    pub(crate) cargo_args: Vec<String>,
 }
 
 impl BoltOptimizeArgs {
@@ -45,50 +85,122 @@ impl BoltOptimizeArgs {
     }
 }
 
-pub fn bolt_optimize(ctx: CargoContext, args: BoltOptimizeArgs) -> anyhow::Result<()> {
+pub fn bolt_optimize(ctx: &CargoContext, args: BoltOptimizeArgs) -> anyhow::Result<()> {
+    if args.run && matches!(args.command, CargoCommand::Build) {
+        return Err(anyhow!(
+            "`--run` can only be used with the `test` and `bench` commands"
+        ));
+    }
+    let cargo_args = ctx.resolve_cargo_args(args.cargo_args);
+    let build_args = bolt_cargo_args(args.command, cargo_args.clone())?;
     let bolt_dir = ctx.get_bolt_directory()?;
+    check_cargo_profile(ctx, args.command, &cargo_args)?;
     let bolt_env = find_bolt_env()?;
 
-    let flags = bolt_pgo_rustflags(&ctx, args.with_pgo)?;
-    let mut cargo = cargo_command_with_rustflags(CargoCommand::Build, flags, args.cargo_args)?;
+    let flags = bolt_pgo_rustflags(
+        ctx,
+        args.with_pgo,
+        args.profile_weights,
+        args.allow_llvm_mismatch,
+    )?;
+    let bolt_args = args.bolt_args.with_config_defaults(ctx.config());
+    let mut cargo = cargo_command_with_rustflags(args.command, flags, build_args)?;
 
+    let mut harnesses = vec![];
+    let mut produced_files = vec![];
+    let mut stats = BTreeMap::new();
     for message in cargo.messages() {
         let message = message?;
         match message {
             Message::CompilerArtifact(artifact) => {
-                if let Some(ref executable) = artifact.executable {
+                if let Some(binary) = get_bolt_binary(args.command, &artifact) {
                     log::info!(
                         "{} {} built successfully. It will be now optimized with BOLT.",
                         capitalize(get_artifact_kind(&artifact)).yellow(),
                         artifact.target.name.blue()
                     );
+                    emit(Event::ArtifactBuilt {
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: binary.as_std_path(),
+                    });
+
+                    restore_original_binary(binary.as_std_path(), artifact.fresh)?;
 
                     let profile_dir = get_binary_profile_dir(&bolt_dir, &artifact);
-                    let optimized_path = match merge_profiles(&bolt_env, &profile_dir)? {
-                        Some(profile_file) => optimize_binary(
-                            &bolt_env,
-                            &args.bolt_args,
-                            executable,
-                            Some(&profile_file),
-                        )?,
-                        None => {
-                            log::warn!(
-                                "No profiles found for target {}. \
+                    let profile_stats = gather_bolt_profile_stats(&profile_dir)?;
+                    if profile_stats.file_count() > 0 {
+                        print_bolt_profile_stats(
+                            &artifact.target.name,
+                            binary.as_std_path(),
+                            &profile_stats,
+                            &profile_dir,
+                        )?;
+                    }
+                    let (optimized_path, binary_stats) =
+                        match merge_profiles(&bolt_env, &profile_dir)? {
+                            Some(profile_file) => {
+                                optimize_binary(&bolt_env, &bolt_args, binary, Some(&profile_file))?
+                            }
+                            None => {
+                                log::warn!(
+                                    "No profiles found for target {}. \
 The optimization will probably not be very effective.",
-                                artifact.target.name.blue()
-                            );
-                            optimize_binary(&bolt_env, &args.bolt_args, executable, None)?
+                                    artifact.target.name.blue()
+                                );
+                                optimize_binary(&bolt_env, &bolt_args, binary, None)?
+                            }
+                        };
+                    if !binary_stats.is_empty() {
+                        let name = profile_dir
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_else(|| artifact.target.name.clone());
+                        emit(Event::BoltStats {
+                            target: &name,
+                            stats: &binary_stats,
+                        });
+                        if output_format() == OutputFormat::Text {
+                            print_bolt_stats(&name, &binary_stats);
                         }
+                        stats.insert(name, binary_stats);
+                    }
+                    let final_path = if args.replace {
+                        let backup = replace_binary(binary.as_std_path(), &optimized_path)?;
+                        produced_files.push(backup);
+                        binary.clone().into_std_path_buf()
+                    } else {
+                        optimized_path
                     };
                     log::info!(
                         "{} {} successfully optimized with BOLT. You can find it at {}.",
                         capitalize(get_artifact_kind(&artifact)).yellow(),
                         artifact.target.name.blue(),
-                        cli_format_path(optimized_path.display())
+                        cli_format_path(final_path.display())
                     );
+                    emit(Event::Optimized {
+                        tool: Tool::Bolt,
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: &final_path,
+                    });
+                    produced_files.push(final_path.clone());
+                    if let Some(ref output_dir) = args.output_dir {
+                        produced_files.push(copy_to_output_dir(
+                            binary.as_std_path(),
+                            &final_path,
+                            output_dir,
+                        )?);
+                    }
+                    if args.run && artifact.executable.is_some() {
+                        harnesses.push(Harness::new(final_path, &artifact));
+                    }
                 }
             }
             Message::BuildFinished(res) => {
+                emit(Event::BuildFinished {
+                    success: res.success,
+                });
                 if res.success {
                     log::info!("BOLT optimized build finished {}.", "successfully".green());
                 } else {
@@ -101,16 +213,55 @@ The optimization will probably not be very effective.",
 
     cargo.check_status()?;
 
+    if !stats.is_empty() {
+        store_bolt_stats(&stats, &bolt_dir.join(DYNO_STATS_FILE_NAME))?;
+    }
+
+    if !produced_files.is_empty() {
+        log::info!("BOLT optimization has produced the following files:");
+        for path in &produced_files {
+            log::info!("- {}", cli_format_path(path.display()));
+        }
+    }
+
+    if args.run {
+        run_harnesses(args.command, &harnesses, &cargo_args)?;
+    }
+
     Ok(())
 }
 
-/// Optimizes `binary` with BOLT and returns a path to the optimized binary.
+/// Copies the `optimized` binary into `output_dir`, under the file name of the original `binary`.
+/// Returns the path to the copy.
+fn copy_to_output_dir(
+    binary: &Path,
+    optimized: &Path,
+    output_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    ensure_directory(output_dir)?;
+    let target = output_dir.join(
+        binary
+            .file_name()
+            .expect("Cannot get file name of compiled binary"),
+    );
+    std::fs::copy(optimized, &target).map_err(|error| {
+        anyhow!(
+            "Cannot copy optimized binary to {}: {}",
+            cli_format_path(target.display()),
+            error
+        )
+    })?;
+    Ok(target)
+}
+
+/// Optimizes `binary` with BOLT and returns a path to the optimized binary, along with the
+/// statistics printed by BOLT.
 fn optimize_binary(
     bolt_env: &BoltEnv,
     bolt_args: &BoltArgs,
     binary: &Utf8PathBuf,
     profile: Option<&Path>,
-) -> anyhow::Result<PathBuf> {
+) -> anyhow::Result<(PathBuf, BoltStats)> {
     match profile {
         Some(profile) => {
             log::debug!(
@@ -124,15 +275,8 @@ fn optimize_binary(
         }
     }
 
-    let basename = binary
-        .as_path()
-        .file_stem()
-        .expect("Cannot extract executable basename");
-
-    let target_path = binary
-        .parent()
-        .expect("Cannot get parent of compiled binary")
-        .join(format!("{basename}-bolt-optimized"));
+    check_bolt_binary(binary.as_std_path())?;
+    let target_path = bolt_output_path(binary, "bolt-optimized");
 
     let mut args = vec![binary.to_string()];
 
@@ -148,37 +292,19 @@ fn optimize_binary(
 
     args.extend(["-o".to_string(), target_path.to_string()]);
 
-    match bolt_args.bolt_args {
-        Some(ref bolt_args) => add_bolt_args(&mut args, bolt_args)?,
-        None => {
-            args.extend(
-                [
-                    "-reorder-blocks=ext-tsp",
-                    "-reorder-functions=hfsort",
-                    "-split-functions=2",
-                    "-split-all-cold",
-                    "-jump-tables=move",
-                    "-use-gnu-stack",
-                    "-split-eh",
-                    "-lite=1",
-                    "-icf=1",
-                    "-relocs",
-                    "-update-debug-sections",
-                    "-dyno-stats",
-                ]
-                .map(|s| s.to_string()),
-            );
-        }
-    }
+    bolt_args.add_optimize_args(&mut args, bolt_env.version)?;
 
     let output = run_command(&bolt_env.bolt, &args)?
         .ok()
-        .map_err(|error| anyhow!("Cannot optimize binary with BOLT: {}.", error))?;
+        .map_err(|error| PgoError::Bolt(format!("Cannot optimize binary with BOLT: {error}.")))?;
 
     log::debug!("BOLT optimization stdout\n{}\n\n", output.stdout);
     log::debug!("BOLT optimization stderr\n{}", output.stderr);
 
-    Ok(target_path.into_std_path_buf())
+    // Depending on the version, BOLT prints its statistics either to stdout or to stderr
+    let stats = parse_bolt_stats(&format!("{}\n{}", output.stdout, output.stderr));
+
+    Ok((target_path.into_std_path_buf(), stats))
 }
 
 /// Merges BOLT profiles from `profile_dir` and returns a path to the merged profile file,
@@ -206,11 +332,31 @@ fn merge_profiles(bolt_env: &BoltEnv, profile_dir: &Path) -> anyhow::Result<Opti
             "Merged BOLT profile(s) to {}.",
             cli_format_path(target_profile.display())
         );
+        emit_merged_profile(Tool::Bolt, &target_profile)?;
         Ok(Some(target_profile))
     } else {
-        Err(anyhow!(
+        Err(PgoError::ProfileMerge(format!(
             "Failed to merge BOLT profile(s): {}.",
             String::from_utf8_lossy(&output.stderr).red()
         ))
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::optimize::copy_to_output_dir;
+
+    #[test]
+    fn copy_with_original_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let optimized = dir.path().join("libfoo-bolt-optimized.so");
+        std::fs::write(&optimized, "optimized").unwrap();
+
+        let output_dir = dir.path().join("out");
+        let copy =
+            copy_to_output_dir(&dir.path().join("libfoo.so"), &optimized, &output_dir).unwrap();
+        assert_eq!(copy, output_dir.join("libfoo.so"));
+        assert_eq!(std::fs::read_to_string(copy).unwrap(), "optimized");
     }
 }
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+/// A named set of flags passed to `llvm-bolt` when optimizing a binary.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoltPreset {
+    /// Flags that work well for most binaries.
+    #[default]
+    Default,
+    /// Enables additional optimizations and optimizes all functions, not only the hot ones.
+    Aggressive,
+    /// Avoids optimizations that increase the size of the binary.
+    Size,
+    /// Only reorders functions and basic blocks, which is the least likely to cause problems.
+    Conservative,
+}
+
+/// Flags shared by all presets. `-dyno-stats` and `-print-cache-metrics` print the statistics that
+/// are shown after the optimization.
+const COMMON_FLAGS: &[&str] = &[
+    "-relocs",
+    "-update-debug-sections",
+    "-dyno-stats",
+    "-print-cache-metrics",
+];
+
+/// Major version of `llvm-bolt` whose flags are used if the version cannot be detected. These
+/// flags match the flags that were used before the presets were introduced.
+const FALLBACK_BOLT_VERSION: u32 = 14;
+
+impl BoltPreset {
+    /// Returns the flags of this preset, adjusted for the given major version of `llvm-bolt`.
+    /// If the version is unknown, the flags for [`FALLBACK_BOLT_VERSION`] are returned.
+    pub fn flags(&self, bolt_version: Option<u32>) -> Vec<String> {
+        let flags: &[&str] = match self {
+            BoltPreset::Default => &[
+                "-reorder-blocks=ext-tsp",
+                "-reorder-functions=hfsort",
+                "-split-functions",
+                "-split-all-cold",
+                "-jump-tables=move",
+                "-use-gnu-stack",
+                "-split-eh",
+                "-lite=1",
+                "-icf=1",
+            ],
+            BoltPreset::Aggressive => &[
+                "-reorder-blocks=ext-tsp",
+                "-reorder-functions=cdsort",
+                "-split-functions",
+                "-split-strategy=cdsplit",
+                "-split-all-cold",
+                "-jump-tables=move",
+                "-use-gnu-stack",
+                "-split-eh",
+                "-lite=0",
+                "-icf=1",
+                "-plt=all",
+                "-peepholes=all",
+                "-indirect-call-promotion=all",
+                "-inline-small-functions",
+            ],
+            BoltPreset::Size => &[
+                "-reorder-blocks=ext-tsp",
+                "-reorder-functions=hfsort",
+                "-jump-tables=move",
+                "-use-gnu-stack",
+                "-lite=1",
+                "-icf=1",
+                "-align-functions=1",
+            ],
+            BoltPreset::Conservative => &["-reorder-blocks=ext-tsp", "-reorder-functions=hfsort"],
+        };
+        flags
+            .iter()
+            .chain(COMMON_FLAGS)
+            .filter_map(|flag| adjust_flag(flag, bolt_version.unwrap_or(FALLBACK_BOLT_VERSION)))
+            .collect()
+    }
+}
+
+/// Adjusts a `flag` to the form supported by the given major version of `llvm-bolt`.
+/// Returns `None` if the flag is not supported by that version at all.
+fn adjust_flag(flag: &str, version: u32) -> Option<String> {
+    let adjusted = match flag {
+        // `-split-functions` used to take a numeric splitting strategy
+        "-split-functions" if version < 15 => "-split-functions=2",
+        // Cache-directed sort was added in LLVM 18, it replaces `hfsort+`
+        "-reorder-functions=cdsort" if version < 18 => "-reorder-functions=hfsort+",
+        // Cache-directed splitting was also added in LLVM 18
+        "-split-strategy=cdsplit" if version < 18 => return None,
+        flag => flag,
+    };
+    Some(adjusted.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::preset::BoltPreset;
+
+    #[test]
+    fn unknown_version() {
+        let flags = BoltPreset::Default.flags(None);
+        assert_eq!(flags, BoltPreset::Default.flags(Some(14)));
+        assert!(flags.contains(&"-split-functions=2".to_string()));
+        assert!(flags.contains(&"-dyno-stats".to_string()));
+        assert!(flags.contains(&"-print-cache-metrics".to_string()));
+    }
+
+    #[test]
+    fn old_version() {
+        let flags = BoltPreset::Aggressive.flags(Some(14));
+        assert!(flags.contains(&"-split-functions=2".to_string()));
+        assert!(flags.contains(&"-reorder-functions=hfsort+".to_string()));
+        assert!(!flags.contains(&"-split-strategy=cdsplit".to_string()));
+        assert!(flags.contains(&"-print-cache-metrics".to_string()));
+    }
+
+    #[test]
+    fn new_version() {
+        let flags = BoltPreset::Aggressive.flags(Some(18));
+        assert!(flags.contains(&"-split-functions".to_string()));
+        assert!(flags.contains(&"-reorder-functions=cdsort".to_string()));
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use colored::Colorize;
+use humansize::{BINARY, format_size};
+
+use crate::bolt::binary::{read_build_id, read_symbol_names};
+use crate::cli::cli_format_path;
+use crate::utils::file::gather_files_with_extension;
+use crate::utils::str::pluralize;
+
+/// Name of the file that stores the build ID of the binary from which BOLT profiles were gathered.
+const BUILD_ID_FILE_NAME: &str = "build-id";
+
+/// Statistics of the BOLT profiles (`.fdata` files) gathered for a single binary.
+#[derive(Debug, Default)]
+pub(crate) struct BoltProfileStats {
+    file_count: usize,
+    total_size: u64,
+    /// Names of the profiled functions.
+    functions: HashSet<String>,
+    /// Number of distinct branches (edges between two locations) in the profiles.
+    branches: usize,
+}
+
+impl BoltProfileStats {
+    pub(crate) fn file_count(&self) -> usize {
+        self.file_count
+    }
+}
+
+/// Gathers statistics of the BOLT profiles stored in `profile_dir`.
+pub(crate) fn gather_bolt_profile_stats(profile_dir: &Path) -> anyhow::Result<BoltProfileStats> {
+    let mut stats = BoltProfileStats::default();
+    let mut branches = HashSet::new();
+    for file in gather_files_with_extension(profile_dir, "fdata") {
+        stats.file_count += 1;
+        stats.total_size += std::fs::metadata(&file)?.len();
+        let content = std::fs::read_to_string(&file)?;
+        parse_fdata(&content, &mut stats.functions, &mut branches);
+    }
+    stats.branches = branches.len();
+    Ok(stats)
+}
+
+/// Parses the profiled functions and branches from the content of a `.fdata` file.
+///
+/// Branch records have the form `<is-sym> <from> <offset> <is-sym> <to> <offset> <mispreds>
+/// <count>`, records without branch information (`no_lbr` mode) only describe a single location.
+fn parse_fdata(content: &str, functions: &mut HashSet<String>, branches: &mut HashSet<String>) {
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let locations = match fields.len() {
+            8 => {
+                // Skip the counts, the same branch can be present in multiple profiles
+                branches.insert(fields[..6].join(" "));
+                &fields[..6]
+            }
+            4 => &fields[..3],
+            _ => continue,
+        };
+        for location in locations.chunks(3) {
+            // `1` marks a location that is relative to a symbol
+            if location[0] == "1" {
+                functions.insert(function_name(location[1]).to_string());
+            }
+        }
+    }
+}
+
+/// Removes the `/<n>` suffix that BOLT adds to names of local functions.
+fn function_name(name: &str) -> &str {
+    match name.rsplit_once('/') {
+        Some((name, suffix))
+            if !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            name
+        }
+        _ => name,
+    }
+}
+
+/// Prints statistics of the BOLT profiles of `binary` and warns if the profiles seem to have been
+/// gathered from a different build of the binary.
+pub(crate) fn print_bolt_profile_stats(
+    name: &str,
+    binary: &Path,
+    stats: &BoltProfileStats,
+    profile_dir: &Path,
+) -> anyhow::Result<()> {
+    log::info!(
+        "Found {} BOLT profile {} with total size {} for {} at {} \
+({} profiled {}, {} profiled {}).",
+        stats.file_count,
+        pluralize("file", stats.file_count),
+        format_size(stats.total_size, BINARY).yellow(),
+        name.blue(),
+        cli_format_path(profile_dir.display()),
+        stats.functions.len().to_string().yellow(),
+        pluralize("function", stats.functions.len()),
+        stats.branches.to_string().yellow(),
+        if stats.branches == 1 {
+            "branch"
+        } else {
+            "branches"
+        },
+    );
+
+    if let (Some(recorded_id), Some(current_id)) =
+        (read_recorded_build_id(profile_dir), read_build_id(binary)?)
+    {
+        if recorded_id != current_id {
+            log::warn!(
+                "BOLT profiles of {} were gathered from a different build of the binary \
+(build ID {} instead of {}). The optimization will probably not be very effective.",
+                name.blue(),
+                recorded_id,
+                current_id
+            );
+        }
+    } else if !stats.functions.is_empty() {
+        // Without build IDs, check whether the profiled functions exist in the binary
+        let symbols = read_symbol_names(binary)?;
+        let missing = stats
+            .functions
+            .iter()
+            .filter(|function| !symbols.contains(function.as_str()))
+            .count();
+        if missing * 2 > stats.functions.len() {
+            log::warn!(
+                "{} out of {} profiled functions were not found in {}. The profiles were probably \
+gathered from a different build of the binary.",
+                missing,
+                stats.functions.len(),
+                cli_format_path(binary.display())
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Stores the build ID of `binary` into `profile_dir`, so that it can be checked later that the
+/// profiles are used to optimize the same build of the binary.
+pub(crate) fn store_build_id(binary: &Path, profile_dir: &Path) -> anyhow::Result<()> {
+    if let Some(build_id) = read_build_id(binary)? {
+        std::fs::write(profile_dir.join(BUILD_ID_FILE_NAME), build_id)?;
+    }
+    Ok(())
+}
+
+/// Returns the build ID stored in `profile_dir` by [`store_build_id`], if there is one.
+fn read_recorded_build_id(profile_dir: &Path) -> Option<String> {
+    std::fs::read_to_string(profile_dir.join(BUILD_ID_FILE_NAME))
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+/// Checks that the BOLT-`optimized` binary was built from the binary whose profiles are currently
+/// stored in `profile_dir`. BOLT keeps the build ID of the original binary, so if the recorded
+/// build ID differs, the profiles were gathered again after the optimization and the optimized
+/// binary is outdated.
+pub(crate) fn check_bolt_optimized_binary(
+    optimized: &Path,
+    profile_dir: &Path,
+) -> anyhow::Result<()> {
+    if let (Some(recorded_id), Some(optimized_id)) = (
+        read_recorded_build_id(profile_dir),
+        read_build_id(optimized)?,
+    ) {
+        if recorded_id != optimized_id {
+            return Err(anyhow::anyhow!(
+                "BOLT-optimized binary {} is outdated, the BOLT profiles in {} were gathered from a \
+different build of the binary (build ID {} instead of {}). Build it again with `cargo pgo bolt optimize`.",
+                cli_format_path(optimized.display()),
+                cli_format_path(profile_dir.display()),
+                recorded_id,
+                optimized_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::bolt::binary::read_build_id;
+    use crate::bolt::profile::{
+        BUILD_ID_FILE_NAME, check_bolt_optimized_binary, function_name, parse_fdata,
+    };
+
+    #[test]
+    fn parse_branches() {
+        let content = "1 main 1f 1 foo/1 0 0 5
+1 main 1f 1 foo/1 0 0 5
+1 main 30 0 [unknown] 7f00 0 2
+";
+        let mut functions = HashSet::new();
+        let mut branches = HashSet::new();
+        parse_fdata(content, &mut functions, &mut branches);
+        assert_eq!(
+            functions,
+            HashSet::from(["main".to_string(), "foo".to_string()])
+        );
+        assert_eq!(branches.len(), 2);
+    }
+
+    #[test]
+    fn parse_no_lbr() {
+        let content = "no_lbr cycles:u
+1 main 1f 10
+1 bar 0 3
+";
+        let mut functions = HashSet::new();
+        let mut branches = HashSet::new();
+        parse_fdata(content, &mut functions, &mut branches);
+        assert_eq!(functions.len(), 2);
+        assert!(branches.is_empty());
+    }
+
+    #[test]
+    fn local_function_name() {
+        assert_eq!(function_name("foo/12"), "foo");
+        assert_eq!(function_name("foo"), "foo");
+        assert_eq!(function_name("a/b"), "a/b");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn optimized_binary_freshness() {
+        let binary = std::env::current_exe().unwrap();
+        let Some(build_id) = read_build_id(&binary).unwrap() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        // No profiles were gathered
+        assert!(check_bolt_optimized_binary(&binary, dir.path()).is_ok());
+
+        std::fs::write(dir.path().join(BUILD_ID_FILE_NAME), &build_id).unwrap();
+        assert!(check_bolt_optimized_binary(&binary, dir.path()).is_ok());
+
+        std::fs::write(dir.path().join(BUILD_ID_FILE_NAME), "deadbeef").unwrap();
+        let error = check_bolt_optimized_binary(&binary, dir.path()).unwrap_err();
+        assert!(error.to_string().contains("is outdated"));
+    }
+}
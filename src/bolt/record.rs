@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::anyhow;
+use cargo_metadata::Message;
+use colored::Colorize;
+
+use crate::bolt::env::{BoltRecordEnv, find_bolt_record_env};
+use crate::bolt::profile::store_build_id;
+use crate::bolt::replace::restore_original_binary;
+use crate::bolt::run::split_binary_args;
+use crate::bolt::{bolt_pgo_rustflags, get_binary_profile_dir};
+use crate::build::{
+    BuiltArtifact, CargoCommand, cargo_command_with_rustflags, get_artifact_kind,
+    handle_metadata_message,
+};
+use crate::clear_directory;
+use crate::cli::cli_format_path;
+use crate::pgo::optimize::parse_profile_weight;
+use crate::utils::str::capitalize;
+use crate::workload::Workload;
+use crate::workspace::CargoContext;
+
+#[derive(clap::Parser, Debug)]
+pub struct BoltRecordArgs {
+    /// Record a PGO-optimized binary. To use this, you must already have PGO profiles on disk.
+    /// Later also pass the same flag to `cargo pgo bolt optimize`.
+    #[clap(long)]
+    with_pgo: bool,
+
+    /// Weight of PGO profiles stored in a subdirectory of the PGO profile directory, in the form
+    /// `<subdirectory>=<weight>`. Use the same weights as for `cargo pgo optimize`.
+    #[clap(long = "profile-weight", value_parser = parse_profile_weight, requires = "with_pgo")]
+    profile_weights: Vec<(String, u32)>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long, requires = "with_pgo")]
+    allow_llvm_mismatch: bool,
+
+    /// Do not remove profiles that were gathered during previous runs.
+    #[clap(long, action)]
+    keep_profiles: bool,
+
+    /// Override the BOLT profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// Name of the binary target that should be built and recorded. It can be omitted if only a
+    /// single binary is built.
+    #[clap(long)]
+    bin: Option<String>,
+
+    /// Additional arguments that will be passed to `cargo build`. Arguments after a second `--`
+    /// form the command that will be recorded with `perf`. Use `{bin}` as a placeholder for the
+    /// path to the built binary. If no command is specified, the binary is executed without
+    /// arguments.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl BoltRecordArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+}
+
+/// Returns the Cargo arguments (or the configured default arguments) used for building the
+/// recorded or executed binary, with the binary selected by `bin`.
+pub(crate) fn build_args(
+    ctx: &CargoContext,
+    cargo_args: Vec<String>,
+    bin: Option<String>,
+) -> Vec<String> {
+    let mut args = ctx.resolve_cargo_args(cargo_args);
+    if let Some(bin) = bin {
+        args.extend(["--bin".to_string(), bin]);
+    }
+    args
+}
+
+/// Builds a binary without BOLT instrumentation, executes the given command under `perf record`
+/// and converts the recorded data into a BOLT profile that can be used by `cargo pgo bolt optimize`.
+pub fn bolt_record(ctx: &CargoContext, args: BoltRecordArgs) -> anyhow::Result<()> {
+    let bolt_dir = ctx.get_bolt_directory()?;
+    let record_env = find_bolt_record_env()?;
+
+    if !args.keep_profiles {
+        log::info!("BOLT profile directory will be cleared.");
+        clear_directory(&bolt_dir)?;
+    }
+
+    let (cargo_args, command) = split_binary_args(args.cargo_args);
+    let workload = record_workload(&command)?;
+    let flags = bolt_pgo_rustflags(
+        ctx,
+        args.with_pgo,
+        args.profile_weights,
+        args.allow_llvm_mismatch,
+    )?;
+    let mut cargo = cargo_command_with_rustflags(
+        CargoCommand::Build,
+        flags,
+        build_args(ctx, cargo_args, args.bin),
+    )?;
+
+    let mut artifacts = vec![];
+    for message in cargo.messages() {
+        let message = message?;
+        match message {
+            Message::CompilerArtifact(artifact) => {
+                if let Some(ref executable) = artifact.executable {
+                    restore_original_binary(executable.as_std_path(), artifact.fresh)?;
+                    log::info!(
+                        "{} {} built successfully.",
+                        capitalize(get_artifact_kind(&artifact)).yellow(),
+                        artifact.target.name.blue()
+                    );
+                    artifacts.push((
+                        BuiltArtifact {
+                            name: artifact.target.name.clone(),
+                            path: executable.clone().into_std_path_buf(),
+                        },
+                        get_binary_profile_dir(&bolt_dir, &artifact),
+                    ));
+                }
+            }
+            Message::BuildFinished(res) => {
+                if res.success {
+                    log::info!(
+                        "Build for BOLT recording finished {}.",
+                        "successfully".green()
+                    );
+                } else {
+                    log::error!("Build for BOLT recording has {}.", "failed".red());
+                }
+            }
+            _ => handle_metadata_message(message),
+        }
+    }
+
+    cargo.check_status()?;
+
+    let (artifact, profile_dir) = match artifacts.as_slice() {
+        [artifact] => artifact,
+        [] => return Err(anyhow!("No binary was built, there is nothing to record")),
+        _ => {
+            return Err(anyhow!(
+                "Multiple binaries were built ({}), use `--bin` to select one of them",
+                artifacts
+                    .iter()
+                    .map(|(artifact, _)| artifact.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    };
+    std::fs::create_dir_all(profile_dir)?;
+    store_build_id(&artifact.path, profile_dir)?;
+
+    let tempdir = tempfile::tempdir()?;
+    let perf_data = tempdir.path().join("perf.data");
+    let recorded = workload.to_command(std::slice::from_ref(artifact))?;
+    log::info!("Recording {} with perf.", workload.to_string().blue());
+    let status = perf_record_command(&record_env, &perf_data, &recorded)
+        .status()
+        .map_err(|error| anyhow!("Cannot execute perf: {}", error))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Recording of `{}` has {} ({})",
+            workload,
+            "failed".red(),
+            status
+        ));
+    }
+
+    let profile = profile_dir.join(format!("perf-{}.fdata", std::process::id()));
+    convert_perf_data(&record_env, &perf_data, &artifact.path, &profile)?;
+
+    log::info!(
+        "BOLT profile of {} was stored into {}. Now build the optimized binary with {}.",
+        artifact.name.blue(),
+        cli_format_path(profile.display()),
+        "cargo pgo bolt optimize".blue()
+    );
+
+    Ok(())
+}
+
+/// Creates a workload from the recorded `command`, which executes the built binary by default.
+fn record_workload(command: &[String]) -> anyhow::Result<Workload> {
+    let mut workload: Workload = "{bin}".parse()?;
+    if let Some((program, args)) = command.split_first() {
+        workload.program = program.clone();
+        workload.args = args.to_vec();
+    }
+    Ok(workload)
+}
+
+/// Creates a command that records `recorded` with `perf`, with branch sampling enabled, so that
+/// BOLT can reconstruct the control flow of the binary.
+fn perf_record_command(
+    record_env: &BoltRecordEnv,
+    perf_data: &Path,
+    recorded: &Command,
+) -> Command {
+    let mut command = Command::new(&record_env.perf);
+    command
+        .args(["record", "-e", "cycles:u", "-j", "any,u", "-o"])
+        .arg(perf_data)
+        .arg("--")
+        .arg(recorded.get_program())
+        .args(recorded.get_args());
+    if let Some(cwd) = recorded.get_current_dir() {
+        command.current_dir(cwd);
+    }
+    command
+}
+
+/// Converts `perf_data` recorded from `binary` into a BOLT profile stored at `profile`.
+fn convert_perf_data(
+    record_env: &BoltRecordEnv,
+    perf_data: &Path,
+    binary: &Path,
+    profile: &Path,
+) -> anyhow::Result<()> {
+    let output = Command::new(&record_env.perf2bolt)
+        .arg("-p")
+        .arg(perf_data)
+        .arg("-o")
+        .arg(profile)
+        .arg(binary)
+        .output()?;
+    log::debug!(
+        "perf2bolt stdout\n{}\n\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Cannot convert perf data to a BOLT profile: {}.",
+            String::from_utf8_lossy(&output.stderr).red()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::record::{build_args, record_workload};
+    use crate::config::PgoConfig;
+    use crate::workspace::CargoContext;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn build_args_with_bin() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = CargoContext::for_target_directory(
+            dir.path(),
+            PgoConfig {
+                cargo_args: Some(args(&["--features", "foo"])),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            build_args(&ctx, vec![], Some("bar".to_string())),
+            args(&["--features", "foo", "--bin", "bar"])
+        );
+        assert_eq!(
+            build_args(&ctx, args(&["--release"]), Some("bar".to_string())),
+            args(&["--release", "--bin", "bar"])
+        );
+        assert_eq!(build_args(&ctx, vec![], None), args(&["--features", "foo"]));
+    }
+
+    #[test]
+    fn record_binary_by_default() {
+        let workload = record_workload(&[]).unwrap();
+        assert_eq!(workload.program, "{bin}");
+        assert!(workload.args.is_empty());
+    }
+
+    #[test]
+    fn record_custom_command() {
+        let workload = record_workload(&[
+            "{bin}".to_string(),
+            "--input".to_string(),
+            "a b".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(workload.to_string(), "{bin} --input a b");
+    }
+}
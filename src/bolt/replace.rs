@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use crate::cli::cli_format_path;
+
+fn backup_path(binary: &Path) -> PathBuf {
+    let mut backup = binary.as_os_str().to_owned();
+    backup.push(".pre-bolt");
+    PathBuf::from(backup)
+}
+
+/// `cargo pgo bolt optimize --replace` overwrites the original `binary` with the optimized one.
+/// If `binary` was not rebuilt by Cargo since then (it is `fresh`), the original binary is
+/// restored from its backup, so that BOLT or `perf` do not process an already optimized binary.
+/// If it was rebuilt, the backup is outdated and it is removed.
+///
+/// This has to be called by every command that processes a built binary with BOLT.
+pub(crate) fn restore_original_binary(binary: &Path, fresh: bool) -> anyhow::Result<()> {
+    let backup = backup_path(binary);
+    if !backup.is_file() {
+        return Ok(());
+    }
+    if fresh {
+        log::debug!("Restoring {} from {}.", binary.display(), backup.display());
+        std::fs::copy(&backup, binary)?;
+    } else {
+        log::debug!("Removing outdated backup {}.", backup.display());
+        std::fs::remove_file(&backup)?;
+    }
+    Ok(())
+}
+
+/// Overwrites the original `binary` with a copy of the `optimized` binary, after making a backup
+/// of the original binary. The `optimized` binary is kept. Returns the path to the backup.
+pub(crate) fn replace_binary(binary: &Path, optimized: &Path) -> anyhow::Result<PathBuf> {
+    let backup = backup_path(binary);
+    std::fs::copy(binary, &backup).map_err(|error| {
+        anyhow!(
+            "Cannot create a backup of {}: {}",
+            cli_format_path(binary.display()),
+            error
+        )
+    })?;
+
+    let mut replacement = binary.as_os_str().to_owned();
+    replacement.push(".bolt-tmp");
+    let replacement = PathBuf::from(replacement);
+    // The copy is in the same directory, so the original binary is replaced atomically
+    std::fs::copy(optimized, &replacement)
+        .and_then(|_| std::fs::rename(&replacement, binary))
+        .map_err(|error| {
+            anyhow!(
+                "Cannot replace {} with the optimized binary: {}",
+                cli_format_path(binary.display()),
+                error
+            )
+        })?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::replace::{replace_binary, restore_original_binary};
+
+    #[test]
+    fn replace_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("foo");
+        let optimized = dir.path().join("foo-bolt-optimized");
+        std::fs::write(&binary, "original").unwrap();
+        std::fs::write(&optimized, "optimized").unwrap();
+
+        let backup = replace_binary(&binary, &optimized).unwrap();
+        assert_eq!(backup, dir.path().join("foo.pre-bolt"));
+        assert_eq!(std::fs::read_to_string(&binary).unwrap(), "optimized");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "original");
+        assert_eq!(std::fs::read_to_string(&optimized).unwrap(), "optimized");
+        assert!(!dir.path().join("foo.bolt-tmp").exists());
+
+        restore_original_binary(&binary, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&binary).unwrap(), "original");
+    }
+
+    /// `cargo pgo bolt build` after `cargo pgo bolt optimize --replace` must instrument the
+    /// original binary, not the optimized one.
+    #[test]
+    fn instrument_after_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("foo");
+        let optimized = dir.path().join("foo-bolt-optimized");
+        std::fs::write(&binary, "original").unwrap();
+        std::fs::write(&optimized, "optimized").unwrap();
+        let backup = replace_binary(&binary, &optimized).unwrap();
+
+        // Cargo has not rebuilt the binary
+        restore_original_binary(&binary, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&binary).unwrap(), "original");
+
+        // Cargo has rebuilt the binary, so the backup must not overwrite it later
+        std::fs::write(&binary, "rebuilt").unwrap();
+        restore_original_binary(&binary, false).unwrap();
+        assert_eq!(std::fs::read_to_string(&binary).unwrap(), "rebuilt");
+        assert!(!backup.exists());
+        restore_original_binary(&binary, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&binary).unwrap(), "rebuilt");
+    }
+}
@@ -0,0 +1,250 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::anyhow;
+use colored::Colorize;
+
+use crate::bolt::cli::BoltArgs;
+use crate::bolt::instrument::{BoltInstrumentArgs, instrument};
+use crate::bolt::record::build_args;
+use crate::build::{BuiltArtifact, CargoCommand};
+use crate::cli::cli_format_path;
+use crate::output::child_stdout;
+use crate::pgo::optimize::parse_profile_weight;
+use crate::utils::file::gather_files_with_extension;
+use crate::utils::str::pluralize;
+use crate::workspace::CargoContext;
+
+#[derive(clap::Parser, Debug)]
+pub struct BoltRunArgs {
+    /// Instrument a PGO-optimized binary. To use this, you must already have PGO profiles on disk.
+    /// Later also pass the same flag to `cargo pgo bolt optimize`.
+    #[clap(long)]
+    with_pgo: bool,
+
+    /// Weight of PGO profiles stored in a subdirectory of the PGO profile directory, in the form
+    /// `<subdirectory>=<weight>`. Use the same weights as for `cargo pgo optimize`.
+    #[clap(long = "profile-weight", value_parser = parse_profile_weight, requires = "with_pgo")]
+    profile_weights: Vec<(String, u32)>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long, requires = "with_pgo")]
+    allow_llvm_mismatch: bool,
+
+    /// Do not remove profiles that were gathered during previous runs.
+    #[clap(long, action)]
+    keep_profiles: bool,
+
+    /// Override the BOLT profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// Name of the binary target that should be built and executed. It can be omitted if only a
+    /// single binary is built.
+    #[clap(long)]
+    bin: Option<String>,
+
+    #[clap(flatten)]
+    bolt_args: BoltArgs,
+
+    /// Additional arguments that will be passed to `cargo build`. Arguments after a second `--`
+    /// will be passed to the executed binary, like with `cargo run`.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl BoltRunArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+}
+
+/// Builds a BOLT-instrumented binary and executes it with the passed arguments.
+pub fn bolt_run(ctx: &CargoContext, args: BoltRunArgs) -> anyhow::Result<()> {
+    let (cargo_args, binary_args) = split_binary_args(args.cargo_args);
+    let artifacts = instrument(
+        ctx,
+        BoltInstrumentArgs {
+            command: CargoCommand::Build,
+            with_pgo: args.with_pgo,
+            profile_weights: args.profile_weights,
+            allow_llvm_mismatch: args.allow_llvm_mismatch,
+            keep_profiles: args.keep_profiles,
+            profiles_dir: None,
+            bolt_args: args.bolt_args,
+            cargo_args: build_args(ctx, cargo_args, args.bin),
+        },
+    )?;
+
+    run_instrumented(&artifacts, &binary_args)?;
+    Ok(())
+}
+
+/// Executes the single instrumented binary from `artifacts` with `binary_args`. Returns the
+/// number of BOLT profiles that it has written into its profile directory.
+fn run_instrumented(
+    artifacts: &[(BuiltArtifact, PathBuf)],
+    binary_args: &[String],
+) -> anyhow::Result<usize> {
+    let (artifact, profile_dir) = match artifacts {
+        [artifact] => artifact,
+        [] => return Err(anyhow!("No binary was built, there is nothing to run")),
+        _ => {
+            return Err(anyhow!(
+                "Multiple binaries were built ({}), use `--bin` to select one of them",
+                artifacts
+                    .iter()
+                    .map(|(artifact, _)| artifact.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    };
+
+    let profile_count = gather_files_with_extension(profile_dir, "fdata").len();
+
+    log::info!("Running {}.", cli_format_path(artifact.path.display()));
+    let status = Command::new(&artifact.path)
+        .args(binary_args)
+        .stdin(Stdio::inherit())
+        .stdout(child_stdout())
+        .status()
+        .map_err(|error| anyhow!("Cannot execute {}: {}", artifact.path.display(), error))?;
+
+    let written = gather_files_with_extension(profile_dir, "fdata")
+        .len()
+        .saturating_sub(profile_count);
+    if written > 0 {
+        log::info!(
+            "{} {} has written {} BOLT {} to {}. Now build the optimized binary with {}.",
+            "Binary".yellow(),
+            artifact.name.blue(),
+            written,
+            pluralize("profile", written),
+            cli_format_path(profile_dir.display()),
+            "cargo pgo bolt optimize".blue()
+        );
+    } else {
+        log::warn!(
+            "No BOLT profiles were written to {}.",
+            cli_format_path(profile_dir.display())
+        );
+    }
+
+    if !status.success() {
+        return Err(anyhow!(
+            "{} has {} ({})",
+            artifact.name,
+            "failed".red(),
+            status
+        ));
+    }
+    Ok(written)
+}
+
+/// Splits arguments passed after `--` into Cargo arguments and arguments of the executed binary,
+/// which are separated by another `--`.
+pub(crate) fn split_binary_args(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut args = args.into_iter();
+    let cargo_args = args.by_ref().take_while(|arg| arg != "--").collect();
+    (cargo_args, args.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::bolt::run::{run_instrumented, split_binary_args};
+    use crate::build::BuiltArtifact;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn split_without_binary_args() {
+        let (cargo, binary) = split_binary_args(args(&["--bin", "foo"]));
+        assert_eq!(cargo, args(&["--bin", "foo"]));
+        assert!(binary.is_empty());
+    }
+
+    #[test]
+    fn split_with_binary_args() {
+        let (cargo, binary) = split_binary_args(args(&["--bin", "foo", "--", "-a", "--", "b"]));
+        assert_eq!(cargo, args(&["--bin", "foo"]));
+        assert_eq!(binary, args(&["-a", "--", "b"]));
+    }
+
+    /// Creates an artifact whose binary writes an empty file to the path in its first argument.
+    #[cfg(unix)]
+    fn touch_artifact(dir: &Path, name: &str) -> (BuiltArtifact, PathBuf) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\ntouch \"$1\"\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let profile_dir = dir.join("profiles").join(format!("lib{name}"));
+        std::fs::create_dir_all(&profile_dir).unwrap();
+        (
+            BuiltArtifact {
+                name: name.to_string(),
+                path,
+            },
+            profile_dir,
+        )
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_counts_profiles_in_profile_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifact = touch_artifact(dir.path(), "foo");
+        let profile_dir = artifact.1.clone();
+        std::fs::write(profile_dir.join("profile.1.fdata"), "").unwrap();
+
+        let profile = profile_dir.join("profile.2.fdata");
+        let (_, binary_args) =
+            split_binary_args(args(&["--bin", "foo", "--", profile.to_str().unwrap()]));
+        assert_eq!(run_instrumented(&[artifact], &binary_args).unwrap(), 1);
+        assert!(profile.is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_ignores_profiles_outside_profile_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifact = touch_artifact(dir.path(), "foo");
+        let profile = dir
+            .path()
+            .join("profiles")
+            .join("foo")
+            .join("profile.fdata");
+        std::fs::create_dir_all(profile.parent().unwrap()).unwrap();
+        assert_eq!(
+            run_instrumented(&[artifact], &args(&[profile.to_str().unwrap()])).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn run_requires_single_binary() {
+        let artifact = |name: &str| {
+            (
+                BuiltArtifact {
+                    name: name.to_string(),
+                    path: PathBuf::from(name),
+                },
+                PathBuf::from(name),
+            )
+        };
+        let error = run_instrumented(&[artifact("foo"), artifact("bar")], &[]).unwrap_err();
+        assert!(error.to_string().contains("foo, bar"));
+        assert!(error.to_string().contains("`--bin`"));
+        assert!(run_instrumented(&[], &[]).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use colored::Colorize;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::cli::cli_format_path;
+use crate::output::print_text;
+use crate::utils::str::capitalize;
+
+/// Dyno stats shown in the summary table. All parsed stats are stored in the JSON output.
+const SUMMARY_STATS: &[&str] = &[
+    "executed instructions",
+    "executed load instructions",
+    "executed store instructions",
+    "taken branches",
+    "total branches",
+    "all function calls",
+];
+
+/// A single dynamic statistic estimated by BOLT from the profile (`-dyno-stats`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DynoStat {
+    pub name: String,
+    /// Value before BOLT optimizations.
+    pub before: Option<u64>,
+    /// Value after BOLT optimizations.
+    pub after: Option<u64>,
+}
+
+impl DynoStat {
+    /// Relative change of the stat caused by BOLT, in percent.
+    fn change(&self) -> Option<f64> {
+        match (self.before, self.after) {
+            (Some(before), Some(after)) if before > 0 => {
+                Some((after as f64 - before as f64) / before as f64 * 100.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Statistics printed by `llvm-bolt` while optimizing a binary.
+#[derive(Debug, Default, Serialize)]
+pub struct BoltStats {
+    pub dyno_stats: Vec<DynoStat>,
+    /// Number of functions with a non-empty profile.
+    pub functions_with_profile: Option<u64>,
+    pub total_functions: Option<u64>,
+    /// Instruction cache metrics printed with `-print-cache-metrics`, in percent.
+    pub cache_metrics: BTreeMap<String, f64>,
+}
+
+impl BoltStats {
+    pub fn is_empty(&self) -> bool {
+        self.dyno_stats.is_empty()
+            && self.functions_with_profile.is_none()
+            && self.cache_metrics.is_empty()
+    }
+}
+
+#[derive(Copy, Clone)]
+enum DynoSection {
+    Before,
+    After,
+}
+
+/// Parses dyno stats, profile coverage and cache metrics from the output of `llvm-bolt`.
+pub fn parse_bolt_stats(output: &str) -> BoltStats {
+    static STAT_REGEX: OnceLock<Regex> = OnceLock::new();
+    static PROFILE_REGEX: OnceLock<Regex> = OnceLock::new();
+    static CACHE_REGEX: OnceLock<Regex> = OnceLock::new();
+
+    let stat_regex = STAT_REGEX
+        .get_or_init(|| Regex::new(r"^\s*(?P<value>\d+) : (?P<name>.*?)(?: \(.*\))?\s*$").unwrap());
+    let profile_regex = PROFILE_REGEX.get_or_init(|| {
+        Regex::new(r"(?P<profiled>\d+) out of (?P<total>\d+) functions in the binary .* have non-empty execution profile").unwrap()
+    });
+    let cache_regex = CACHE_REGEX.get_or_init(|| {
+        Regex::new(r"^\s*(?:Expected )?(?P<name>i-TLB cache hit ratio|Hot code takes)[: ]*(?P<value>[\d.]+)%")
+            .unwrap()
+    });
+
+    let mut stats = BoltStats::default();
+    let mut section = None;
+    for line in output.lines() {
+        if line.contains("dynostats") {
+            section = Some(if line.contains("after") {
+                DynoSection::After
+            } else {
+                DynoSection::Before
+            });
+            continue;
+        }
+        if let (Some(section), Some(captures)) = (section, stat_regex.captures(line)) {
+            let name = captures["name"].to_string();
+            let value = captures["value"].parse().ok();
+            let index = match stats.dyno_stats.iter().position(|stat| stat.name == name) {
+                Some(index) => index,
+                None => {
+                    stats.dyno_stats.push(DynoStat {
+                        name,
+                        before: None,
+                        after: None,
+                    });
+                    stats.dyno_stats.len() - 1
+                }
+            };
+            let stat = &mut stats.dyno_stats[index];
+            match section {
+                DynoSection::Before => stat.before = value,
+                DynoSection::After => stat.after = value,
+            }
+            continue;
+        }
+        if !line.trim().is_empty() {
+            section = None;
+        }
+
+        if let Some(captures) = profile_regex.captures(line) {
+            stats.functions_with_profile = captures["profiled"].parse().ok();
+            stats.total_functions = captures["total"].parse().ok();
+        } else if let Some(captures) = cache_regex.captures(line) {
+            let name = match &captures["name"] {
+                "Hot code takes" => "hot code share",
+                name => name,
+            };
+            if let Ok(value) = captures["value"].parse() {
+                stats.cache_metrics.insert(name.to_string(), value);
+            }
+        }
+    }
+    stats
+}
+
+/// Prints a summary of the most important statistics of an optimized binary.
+pub fn print_bolt_stats(name: &str, stats: &BoltStats) {
+    print_text!("{} {}", "BOLT statistics of".bold(), name.blue());
+
+    let summary: Vec<&DynoStat> = SUMMARY_STATS
+        .iter()
+        .filter_map(|name| stats.dyno_stats.iter().find(|stat| stat.name == *name))
+        .collect();
+    if !summary.is_empty() {
+        let name_width = summary
+            .iter()
+            .map(|stat| stat.name.len())
+            .max()
+            .unwrap_or(0);
+        let format_value = |value: Option<u64>| match value {
+            Some(value) => value.to_string(),
+            None => "-".to_string(),
+        };
+        print_text!(
+            "  {:name_width$}  {:>15}  {:>15}  {:>8}",
+            "Stat",
+            "Before",
+            "After",
+            "Change"
+        );
+        for stat in summary {
+            let change = match stat.change() {
+                Some(change) => format!("{change:+.1}%"),
+                None => "-".to_string(),
+            };
+            print_text!(
+                "  {:name_width$}  {:>15}  {:>15}  {:>8}",
+                stat.name,
+                format_value(stat.before),
+                format_value(stat.after),
+                change
+            );
+        }
+    }
+    if let (Some(profiled), Some(total)) = (stats.functions_with_profile, stats.total_functions) {
+        print_text!("  Functions with profile: {profiled} out of {total}");
+    }
+    for (name, value) in &stats.cache_metrics {
+        print_text!("  {}: {:.2}%", capitalize(name), value);
+    }
+}
+
+/// Stores the statistics of all optimized binaries into a JSON file.
+pub fn store_bolt_stats(stats: &BTreeMap<String, BoltStats>, path: &Path) -> anyhow::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(stats)?)?;
+    log::info!(
+        "BOLT statistics were stored into {}.",
+        cli_format_path(path.display())
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::stats::parse_bolt_stats;
+
+    const OUTPUT: &str =
+        "BOLT-INFO: 1 out of 128 functions in the binary (0.8%) have non-empty execution profile
+BOLT-INFO: program-wide dynostats before running passes:
+
+              1000 : executed forward branches
+               400 : taken forward branches
+             50000 : executed instructions
+               500 : taken branches
+
+BOLT-INFO: program-wide dynostats after all optimizations before SCTC and FOP:
+
+               900 : executed forward branches (-10.0%)
+               100 : taken forward branches (-75.0%)
+             49000 : executed instructions (-2.0%)
+               200 : taken branches (-60.0%)
+
+BOLT-INFO: cache metrics after emitting functions:
+  Hot code takes 1.50% of binary (1000 bytes out of 66666, 0.25 pages out of 16.28)
+  Expected i-TLB cache hit ratio: 99.90%
+";
+
+    #[test]
+    fn parse_dyno_stats() {
+        let stats = parse_bolt_stats(OUTPUT);
+        assert_eq!(stats.dyno_stats.len(), 4);
+        let taken = stats
+            .dyno_stats
+            .iter()
+            .find(|stat| stat.name == "taken branches")
+            .unwrap();
+        assert_eq!(taken.before, Some(500));
+        assert_eq!(taken.after, Some(200));
+        assert_eq!(taken.change(), Some(-60.0));
+    }
+
+    #[test]
+    fn parse_profile_and_cache() {
+        let stats = parse_bolt_stats(OUTPUT);
+        assert_eq!(stats.functions_with_profile, Some(1));
+        assert_eq!(stats.total_functions, Some(128));
+        assert_eq!(stats.cache_metrics["i-TLB cache hit ratio"], 99.9);
+        assert_eq!(stats.cache_metrics["hot code share"], 1.5);
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert!(parse_bolt_stats("BOLT-INFO: something").is_empty());
+    }
+}
@@ -1,4 +1,7 @@
+use crate::error::PgoError;
 use crate::get_default_target;
+use crate::output::{Event, OutputFormat, emit, output_format};
+use cargo_metadata::camino::Utf8PathBuf;
 use cargo_metadata::{Artifact, Message, MessageIter, TargetKind};
 use std::collections::HashMap;
 use std::fmt::Write as WriteFmt;
@@ -19,6 +22,14 @@ enum ReleaseMode {
     NoRelease,
 }
 
+/// An executable produced by a `cargo-pgo` command.
+#[derive(Debug, Clone)]
+pub struct BuiltArtifact {
+    /// Name of the Cargo target that has produced the executable.
+    pub name: String,
+    pub path: PathBuf,
+}
+
 pub struct RunningCargo {
     child: Child,
     message_iter: MessageIter<BufReader<ChildStdout>>,
@@ -51,10 +62,10 @@ impl RunningCargo {
     pub fn check_status(mut self) -> anyhow::Result<()> {
         let status = self.child.wait()?;
         if !status.success() {
-            return Err(anyhow::anyhow!(
-                "Cargo finished with an error ({})",
-                status.code().unwrap_or(-1),
-            ));
+            return Err(PgoError::Cargo {
+                exit_code: status.code(),
+            }
+            .into());
         }
         Ok(())
     }
@@ -264,6 +275,13 @@ fn get_key_value<Iter: Iterator<Item = String>>(
 }
 
 pub fn handle_metadata_message(message: Message) {
+    if output_format() == OutputFormat::Json {
+        match &message {
+            Message::TextLine(line) => emit(Event::CargoText { line }),
+            _ => emit(Event::CargoMessage { message: &message }),
+        }
+        return;
+    }
     let stdout = std::io::stdout();
     let mut stdout = stdout.lock();
     write_metadata_message(&mut stdout, message);
@@ -291,6 +309,22 @@ fn write_metadata_message<W: Write>(mut stream: W, message: Message) {
     }
 }
 
+/// Returns the path of the shared library (`cdylib` or `dylib`) produced by the artifact, if any.
+pub fn get_shared_library(artifact: &Artifact) -> Option<&Utf8PathBuf> {
+    let is_shared = artifact
+        .target
+        .kind
+        .iter()
+        .any(|kind| matches!(kind, TargetKind::CDyLib | TargetKind::DyLib));
+    if !is_shared {
+        return None;
+    }
+    artifact
+        .filenames
+        .iter()
+        .find(|path| matches!(path.extension(), Some("so" | "dylib" | "dll")))
+}
+
 /// Returns a user-friendly name of an artifact kind.
 pub fn get_artifact_kind(artifact: &Artifact) -> &str {
     for kind in &artifact.target.kind {
@@ -304,6 +338,9 @@ pub fn get_artifact_kind(artifact: &Artifact) -> &str {
             TargetKind::Example => {
                 return "example";
             }
+            TargetKind::CDyLib | TargetKind::DyLib => {
+                return "library";
+            }
             _ => {}
         }
     }
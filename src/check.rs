@@ -1,19 +1,29 @@
-use crate::bolt::env::{find_llvm_bolt, find_merge_fdata};
+use crate::bolt::env::{find_llvm_bolt, find_merge_fdata, find_perf2bolt};
 use crate::bolt::llvm_bolt_install_hint;
 use crate::cli::cli_format_path;
-use crate::pgo::env::find_pgo_env;
+use crate::config::{CONFIG_FILE_NAME, PgoConfig};
+use crate::output::print_text;
+use crate::pgo::env::{check_llvm_version, find_pgo_env, rustc_llvm_version};
 use crate::pgo::llvm_profdata_install_hint;
+use crate::pgo::sample::env::{find_perf, find_sample_converter};
+use crate::pgo::sample::{llvm_profgen_install_hint, perf_install_hint};
+use crate::workspace::CargoContext;
 use anyhow::anyhow;
 use colored::Colorize;
 use rustc_version;
 use std::path::PathBuf;
 
-/// Check that binaries required for performing PGO and BOLT can be found.
-pub fn environment_info() -> anyhow::Result<()> {
+/// Check that binaries required for performing PGO, sampling-based PGO and BOLT can be found and print the
+/// effective `cargo-pgo` configuration.
+pub fn environment_info(ctx: &CargoContext) -> anyhow::Result<()> {
     let mut success = true;
     success &= check_rustc_version();
     success &= check_pgo_env();
     success &= check_bolt_env();
+    check_perf();
+    check_sample_env(ctx);
+
+    print_config(ctx.config())?;
 
     if success {
         Ok(())
@@ -26,14 +36,14 @@ fn check_rustc_version() -> bool {
     match rustc_version::version() {
         Ok(version) => {
             if version >= semver::Version::new(1, 39, 0) {
-                println!(
+                print_text!(
                     "{}: {} is recent enough",
                     "[rustc version]".bold().green(),
                     version.to_string().blue(),
                 );
                 true
             } else {
-                println!(
+                print_text!(
                     "{}: {} is too old",
                     "[rustc version]".bold().red(),
                     version.to_string().blue(),
@@ -42,7 +52,7 @@ fn check_rustc_version() -> bool {
             }
         }
         Err(error) => {
-            println!(
+            print_text!(
                 "{}: cannot determine version ({})",
                 "[rustc version]".bold().red(),
                 error
@@ -55,7 +65,7 @@ fn check_rustc_version() -> bool {
 fn check_binary_available(name: &str, resolved: anyhow::Result<PathBuf>, hint: &str) -> bool {
     match resolved {
         Ok(path) => {
-            println!(
+            print_text!(
                 "{}: found at {}",
                 format!("[{name}]").bold().green(),
                 cli_format_path(path.display())
@@ -63,7 +73,7 @@ fn check_binary_available(name: &str, resolved: anyhow::Result<PathBuf>, hint: &
             true
         }
         Err(_) => {
-            println!(
+            print_text!(
                 "{}: could not be found ({})",
                 format!("[{name}]").bold().red(),
                 hint
@@ -73,12 +83,69 @@ fn check_binary_available(name: &str, resolved: anyhow::Result<PathBuf>, hint: &
     }
 }
 
+/// Checks a binary that is only needed by some commands. A missing binary is only reported as a
+/// warning, so that it does not fail the check for users who do not use these commands.
+fn check_optional_binary_available(
+    name: &str,
+    resolved: anyhow::Result<PathBuf>,
+    hint: &str,
+    needed_for: &str,
+) {
+    match resolved {
+        Ok(path) => {
+            print_text!(
+                "{}: found at {}",
+                format!("[{name}]").bold().green(),
+                cli_format_path(path.display())
+            );
+        }
+        Err(_) => {
+            print_text!(
+                "{}: could not be found, it is only needed for {} ({})",
+                format!("[{name}]").bold().yellow(),
+                needed_for,
+                hint
+            );
+        }
+    }
+}
+
 fn check_pgo_env() -> bool {
-    check_binary_available(
+    let pgo_env = find_pgo_env();
+    let version_check = pgo_env
+        .as_ref()
+        .ok()
+        .map(|env| check_llvm_version(env, rustc_llvm_version()));
+    let found = check_binary_available(
         "llvm-profdata",
-        find_pgo_env().map(|env| env.llvm_profdata),
+        pgo_env.map(|env| env.llvm_profdata),
         &llvm_profdata_install_hint(),
-    )
+    );
+    match version_check {
+        Some(Err(message)) => {
+            print_text!("{}: {}", "[llvm-profdata]".bold().red(), message);
+            false
+        }
+        _ => found,
+    }
+}
+
+fn check_perf() {
+    check_optional_binary_available(
+        "perf",
+        find_perf(),
+        perf_install_hint(),
+        "sampling-based PGO and `cargo pgo bolt record`",
+    );
+}
+
+fn check_sample_env(ctx: &CargoContext) {
+    check_optional_binary_available(
+        "llvm-profgen",
+        find_sample_converter(ctx.config().sample_converter.as_deref()),
+        llvm_profgen_install_hint(),
+        "sampling-based PGO",
+    );
 }
 
 fn check_bolt_env() -> bool {
@@ -86,5 +153,25 @@ fn check_bolt_env() -> bool {
 
     let llvm_bolt = check_binary_available("llvm-bolt", find_llvm_bolt(), hint);
     let merge_fdata = check_binary_available("merge-fdata", find_merge_fdata(), hint);
+    check_optional_binary_available(
+        "perf2bolt",
+        find_perf2bolt(),
+        hint,
+        "`cargo pgo bolt record`",
+    );
     llvm_bolt && merge_fdata
 }
+
+fn print_config(config: &PgoConfig) -> anyhow::Result<()> {
+    if config.is_empty() {
+        print_text!(
+            "{}: not found (you can create `{}` or use `[package.metadata.pgo]` in `Cargo.toml`)",
+            "[configuration]".bold(),
+            CONFIG_FILE_NAME
+        );
+    } else {
+        print_text!("{}:", "[configuration]".bold());
+        print_text!("{}", toml::to_string(config)?.trim_end());
+    }
+    Ok(())
+}
@@ -1,11 +1,16 @@
+use std::io::ErrorKind;
+
 use crate::workspace::CargoContext;
 
 pub fn clean_artifacts(ctx: CargoContext) -> anyhow::Result<()> {
-    let pgo_dir = ctx.get_pgo_directory()?;
-    let res = std::fs::remove_dir_all(pgo_dir);
-
-    let bolt_dir = ctx.get_bolt_directory()?;
-    std::fs::remove_dir_all(bolt_dir).and(res)?;
+    let mut res = Ok(());
+    for directory in ctx.artifact_directories()? {
+        match std::fs::remove_dir_all(directory) {
+            Err(error) if error.kind() != ErrorKind::NotFound => res = res.and(Err(error)),
+            _ => {}
+        }
+    }
+    res?;
 
     Ok(())
 }
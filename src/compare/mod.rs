@@ -0,0 +1,612 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Instant;
+
+use anyhow::anyhow;
+use cargo_metadata::Message;
+use colored::Colorize;
+use humansize::{BINARY, format_size};
+use serde::Serialize;
+
+use crate::bolt::bolt_output_path;
+use crate::bolt::profile::check_bolt_optimized_binary;
+use crate::build::{
+    BuiltArtifact, CargoCommand, cargo_command_with_rustflags, handle_metadata_message,
+};
+use crate::clear_directory;
+use crate::cli::cli_format_path;
+use crate::compare::stats::{SIGNIFICANCE_LEVEL, mean, median, stddev, welch_t_test};
+use crate::output::print_text;
+use crate::pgo::optimize::{find_checked_pgo_env, prepare_pgo_optimization_flags};
+use crate::pipeline::resolve_workloads;
+use crate::workload::Workload;
+use crate::workspace::CargoContext;
+
+pub mod stats;
+
+/// Name of the file with comparison results, stored in the comparison directory.
+const RESULTS_FILE_NAME: &str = "results.json";
+
+#[derive(clap::Parser, Debug)]
+pub struct CompareArgs {
+    /// Command that will be executed to benchmark the binaries. Can be passed multiple times.
+    /// Use `{bin}` (or `{bin:<name>}` if multiple binaries are built) as a placeholder for the
+    /// path to the benchmarked binary. If no workload is passed, workloads from the `cargo-pgo`
+    /// configuration will be used.
+    #[clap(long = "workload")]
+    workloads: Vec<Workload>,
+
+    /// How many times should each workload be executed with each binary.
+    #[clap(long, default_value_t = 10)]
+    runs: usize,
+
+    /// How many times should each workload be executed with each binary before the measurement.
+    #[clap(long, default_value_t = 1)]
+    warmup: usize,
+
+    /// Also benchmark the `-bolt-optimized` binaries produced by `cargo pgo bolt optimize`.
+    #[clap(long)]
+    bolt: bool,
+
+    /// Reuse the binaries built by a previous `cargo pgo compare` run instead of building them
+    /// again.
+    #[clap(long)]
+    reuse: bool,
+
+    /// Path of the JSON file where the results will be stored. Defaults to
+    /// `<target-dir>/pgo-compare/results.json`.
+    #[clap(long)]
+    json: Option<PathBuf>,
+
+    /// Override the PGO profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// Override the BOLT profile path. It is used to check that the `-bolt-optimized` binaries
+    /// were built from the currently gathered BOLT profiles.
+    #[clap(long)]
+    bolt_profiles_dir: Option<PathBuf>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+
+    /// Additional arguments that will be passed to `cargo build`.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl CompareArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+
+    pub fn bolt_profiles_dir(&self) -> &Option<PathBuf> {
+        &self.bolt_profiles_dir
+    }
+}
+
+/// A variant of the binaries that is benchmarked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variant {
+    /// Plain release build.
+    Baseline,
+    Pgo,
+    Bolt,
+}
+
+impl Variant {
+    fn name(&self) -> &'static str {
+        match self {
+            Variant::Baseline => "baseline",
+            Variant::Pgo => "pgo",
+            Variant::Bolt => "bolt",
+        }
+    }
+}
+
+/// Resource usage of a single execution of a workload.
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    /// Wall time in seconds.
+    wall_time: f64,
+    /// User CPU time in seconds.
+    user_time: Option<f64>,
+    /// System CPU time in seconds.
+    sys_time: Option<f64>,
+    /// Maximum resident set size in bytes.
+    max_rss: Option<u64>,
+}
+
+/// Summary of all measurements of a single variant for a single workload.
+#[derive(Debug, Serialize)]
+pub struct VariantResult {
+    pub variant: String,
+    /// Mean wall time in seconds.
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    /// Mean user CPU time in seconds.
+    pub user_time: Option<f64>,
+    /// Mean system CPU time in seconds.
+    pub sys_time: Option<f64>,
+    /// Largest maximum resident set size of all runs, in bytes.
+    pub max_rss: Option<u64>,
+    /// Relative change of the mean wall time against the baseline, in percent.
+    pub change: Option<f64>,
+    /// p-value of Welch's t-test of the wall times against the baseline.
+    pub p_value: Option<f64>,
+    pub significant: Option<bool>,
+    /// Wall times of all runs, in seconds.
+    pub wall_times: Vec<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkloadResult {
+    pub workload: String,
+    pub runs: usize,
+    pub variants: Vec<VariantResult>,
+}
+
+/// Builds the baseline, PGO-optimized and optionally BOLT-optimized binaries, benchmarks them
+/// with the workloads and reports the differences between them.
+pub fn pgo_compare(ctx: &CargoContext, args: CompareArgs) -> anyhow::Result<()> {
+    if args.runs == 0 {
+        return Err(anyhow!("`--runs` has to be at least 1"));
+    }
+    let workloads = resolve_workloads(ctx, args.workloads)?;
+    let compare_dir = ctx.get_compare_directory()?;
+
+    let mut variants = vec![Variant::Baseline, Variant::Pgo];
+    if args.bolt {
+        variants.push(Variant::Bolt);
+    }
+
+    let binaries = if args.reuse {
+        variants
+            .iter()
+            .map(|variant| Ok((*variant, reuse_variant(&compare_dir, *variant)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else {
+        build_variants(
+            ctx,
+            &variants,
+            &compare_dir,
+            args.allow_llvm_mismatch,
+            args.cargo_args,
+        )?
+    };
+
+    let mut results = vec![];
+    for workload in &workloads {
+        log::info!(
+            "Benchmarking workload {} ({} {}, {} warmup).",
+            workload.to_string().blue(),
+            args.runs,
+            if args.runs == 1 { "run" } else { "runs" },
+            args.warmup
+        );
+        let mut measurements = vec![vec![]; binaries.len()];
+        for _ in 0..args.warmup {
+            for (_, artifacts) in &binaries {
+                measure_workload(workload, artifacts)?;
+            }
+        }
+        // The variants are interleaved, so that a change in the state of the system affects all
+        // of them in the same way
+        for _ in 0..args.runs {
+            for (index, (_, artifacts)) in binaries.iter().enumerate() {
+                measurements[index].push(measure_workload(workload, artifacts)?);
+            }
+        }
+
+        let result = summarize_workload(
+            workload,
+            args.runs,
+            binaries
+                .iter()
+                .map(|(variant, _)| *variant)
+                .zip(measurements),
+        );
+        print_workload_result(&result);
+        results.push(result);
+    }
+
+    let json_path = args
+        .json
+        .unwrap_or_else(|| compare_dir.join(RESULTS_FILE_NAME));
+    std::fs::write(&json_path, serde_json::to_string_pretty(&results)?)?;
+    log::info!(
+        "Comparison results were stored into {}.",
+        cli_format_path(json_path.display())
+    );
+    Ok(())
+}
+
+/// Builds the binaries of all `variants` and copies them into `<compare_dir>/<variant>`.
+fn build_variants(
+    ctx: &CargoContext,
+    variants: &[Variant],
+    compare_dir: &Path,
+    allow_llvm_mismatch: bool,
+    cargo_args: Vec<String>,
+) -> anyhow::Result<Vec<(Variant, Vec<BuiltArtifact>)>> {
+    let cargo_args = ctx.resolve_cargo_args(cargo_args);
+
+    let mut binaries = vec![];
+    let mut original_paths = vec![];
+    for variant in variants {
+        let variant_dir = compare_dir.join(variant.name());
+        clear_directory(&variant_dir)?;
+
+        let artifacts = match variant {
+            Variant::Baseline | Variant::Pgo => {
+                let flags = match variant {
+                    Variant::Pgo => {
+                        let pgo_env = find_checked_pgo_env(allow_llvm_mismatch)?;
+                        prepare_pgo_optimization_flags(
+                            &pgo_env,
+                            &ctx.get_pgo_directory()?,
+                            &ctx.resolve_profile_weights(vec![]),
+                        )?
+                    }
+                    _ => vec![],
+                };
+                log::info!("Building {} binaries.", variant.name().yellow());
+                original_paths = build_binaries(flags, cargo_args.clone())?;
+                original_paths.clone()
+            }
+            // The BOLT-optimized binaries are stored next to the original binaries
+            Variant::Bolt => {
+                let bolt_dir = ctx.get_bolt_directory()?;
+                original_paths
+                .iter()
+                .map(|artifact| {
+                    let path = bolt_output_path(
+                        artifact
+                            .path
+                            .as_path()
+                            .try_into()
+                            .map_err(|_| anyhow!("Binary path is not valid UTF-8"))?,
+                        "bolt-optimized",
+                    )
+                    .into_std_path_buf();
+                    if !path.is_file() {
+                        return Err(anyhow!(
+                            "BOLT-optimized binary {} does not exist. Build it with `cargo pgo bolt optimize` first.",
+                            cli_format_path(path.display())
+                        ));
+                    }
+                    check_bolt_optimized_binary(&path, &bolt_dir.join(&artifact.name))?;
+                    Ok(BuiltArtifact {
+                        name: artifact.name.clone(),
+                        path,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+            }
+        };
+
+        let mut copied = vec![];
+        for artifact in artifacts {
+            let target = variant_dir.join(
+                artifact
+                    .path
+                    .file_name()
+                    .expect("Cannot get file name of compiled binary"),
+            );
+            std::fs::copy(&artifact.path, &target)?;
+            copied.push(BuiltArtifact {
+                name: artifact.name,
+                path: target,
+            });
+        }
+        binaries.push((*variant, copied));
+    }
+    Ok(binaries)
+}
+
+/// Runs `cargo build` with the given RUSTFLAGS and returns the built executables.
+fn build_binaries(
+    flags: Vec<String>,
+    cargo_args: Vec<String>,
+) -> anyhow::Result<Vec<BuiltArtifact>> {
+    let mut cargo = cargo_command_with_rustflags(CargoCommand::Build, flags, cargo_args)?;
+    let mut artifacts = vec![];
+    for message in cargo.messages() {
+        let message = message?;
+        match message {
+            Message::CompilerArtifact(artifact) => {
+                if let Some(executable) = artifact.executable {
+                    artifacts.push(BuiltArtifact {
+                        name: artifact.target.name.clone(),
+                        path: executable.into_std_path_buf(),
+                    });
+                }
+            }
+            Message::BuildFinished(res) => {
+                if !res.success {
+                    log::error!("Build has {}.", "failed".red());
+                }
+            }
+            _ => handle_metadata_message(message),
+        }
+    }
+    cargo.check_status()?;
+    if artifacts.is_empty() {
+        return Err(anyhow!("No binary was built, there is nothing to compare"));
+    }
+    Ok(artifacts)
+}
+
+/// Finds binaries of `variant` stored by a previous run in the comparison directory.
+fn reuse_variant(compare_dir: &Path, variant: Variant) -> anyhow::Result<Vec<BuiltArtifact>> {
+    let variant_dir = compare_dir.join(variant.name());
+    let artifacts: Vec<BuiltArtifact> = std::fs::read_dir(&variant_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| BuiltArtifact {
+            name: entry
+                .path()
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: entry.path(),
+        })
+        .collect();
+    if artifacts.is_empty() {
+        return Err(anyhow!(
+            "No {} binaries were found at {}. Run `cargo pgo compare` without `--reuse` first.",
+            variant.name(),
+            cli_format_path(variant_dir.display())
+        ));
+    }
+    Ok(artifacts)
+}
+
+/// Executes `workload` once with the given binaries and measures its resource usage.
+fn measure_workload(
+    workload: &Workload,
+    artifacts: &[BuiltArtifact],
+) -> anyhow::Result<Measurement> {
+    let mut command = workload.to_command(artifacts)?;
+    command.stdout(Stdio::null());
+    command.stderr(Stdio::null());
+    match &workload.stdin {
+        Some(stdin) => {
+            let file = File::open(stdin).map_err(|error| {
+                anyhow!(
+                    "Cannot open stdin file {} of workload `{}`: {}",
+                    stdin.display(),
+                    workload,
+                    error
+                )
+            })?;
+            command.stdin(Stdio::from(file));
+        }
+        None => {
+            command.stdin(Stdio::null());
+        }
+    }
+    log::debug!("Executing workload command: {:?}", command);
+
+    let (status, measurement) = run_measured(&mut command)
+        .map_err(|error| anyhow!("Cannot execute workload `{}`: {}", workload, error))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Workload `{}` has {} ({})",
+            workload,
+            "failed".red(),
+            status
+        ));
+    }
+    Ok(measurement)
+}
+
+#[cfg(unix)]
+fn run_measured(command: &mut Command) -> std::io::Result<(ExitStatus, Measurement)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let start = Instant::now();
+    let child = command.spawn()?;
+    let mut status = 0;
+    // SAFETY: `rusage` is a plain C struct, for which zeroed memory is a valid value.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        // SAFETY: the child was spawned by us and it was not waited for yet, the pointers are
+        // valid for the duration of the call.
+        let result = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) };
+        if result >= 0 {
+            break;
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    let wall_time = start.elapsed().as_secs_f64();
+
+    let to_seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0;
+    // `ru_maxrss` is reported in bytes on macOS and in kilobytes elsewhere
+    let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    Ok((
+        ExitStatus::from_raw(status),
+        Measurement {
+            wall_time,
+            user_time: Some(to_seconds(usage.ru_utime)),
+            sys_time: Some(to_seconds(usage.ru_stime)),
+            max_rss: Some(usage.ru_maxrss as u64 * rss_unit),
+        },
+    ))
+}
+
+#[cfg(not(unix))]
+fn run_measured(command: &mut Command) -> std::io::Result<(ExitStatus, Measurement)> {
+    let start = Instant::now();
+    let status = command.status()?;
+    Ok((
+        status,
+        Measurement {
+            wall_time: start.elapsed().as_secs_f64(),
+            user_time: None,
+            sys_time: None,
+            max_rss: None,
+        },
+    ))
+}
+
+/// Computes statistics of the measurements of all variants. The first variant is used as the
+/// baseline to which the other variants are compared.
+fn summarize_workload<I: IntoIterator<Item = (Variant, Vec<Measurement>)>>(
+    workload: &Workload,
+    runs: usize,
+    measurements: I,
+) -> WorkloadResult {
+    let mut variants: Vec<VariantResult> = vec![];
+    for (variant, measurements) in measurements {
+        let wall_times: Vec<f64> = measurements.iter().map(|m| m.wall_time).collect();
+        let mean_of = |values: Option<Vec<f64>>| values.map(|values| mean(&values));
+
+        let (change, p_value) = match variants.first() {
+            Some(baseline) => (
+                Some((mean(&wall_times) - baseline.mean) / baseline.mean * 100.0),
+                welch_t_test(&baseline.wall_times, &wall_times),
+            ),
+            None => (None, None),
+        };
+        variants.push(VariantResult {
+            variant: variant.name().to_string(),
+            mean: mean(&wall_times),
+            median: median(&wall_times),
+            stddev: stddev(&wall_times),
+            user_time: mean_of(measurements.iter().map(|m| m.user_time).collect()),
+            sys_time: mean_of(measurements.iter().map(|m| m.sys_time).collect()),
+            max_rss: measurements.iter().map(|m| m.max_rss).max().flatten(),
+            change,
+            p_value,
+            significant: p_value.map(|p_value| p_value < SIGNIFICANCE_LEVEL),
+            wall_times,
+        });
+    }
+    WorkloadResult {
+        workload: workload.to_string(),
+        runs,
+        variants,
+    }
+}
+
+fn format_seconds(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value < 1.0 => format!("{:.1} ms", value * 1000.0),
+        Some(value) => format!("{value:.3} s"),
+        None => "-".to_string(),
+    }
+}
+
+fn print_workload_result(result: &WorkloadResult) {
+    print_text!(
+        "{} {}",
+        "Results of workload".bold(),
+        result.workload.as_str().blue()
+    );
+    print_text!(
+        "  {:<10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>11}  Change",
+        "Variant",
+        "Mean",
+        "Median",
+        "Stddev",
+        "User",
+        "Sys",
+        "Max RSS"
+    );
+    for variant in &result.variants {
+        let change = match (variant.change, variant.p_value, variant.significant) {
+            (Some(change), Some(p_value), Some(significant)) => {
+                let text = format!("{change:+.2}% (p = {p_value:.3})");
+                match (significant, change < 0.0) {
+                    (true, true) => text.green().to_string(),
+                    (true, false) => text.red().to_string(),
+                    (false, _) => format!("{text}, not significant"),
+                }
+            }
+            (Some(change), _, _) => format!("{change:+.2}%"),
+            _ => "".to_string(),
+        };
+        let line = format!(
+            "  {:<10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>11}  {}",
+            variant.variant,
+            format_seconds(Some(variant.mean)),
+            format_seconds(Some(variant.median)),
+            format_seconds(Some(variant.stddev)),
+            format_seconds(variant.user_time),
+            format_seconds(variant.sys_time),
+            variant
+                .max_rss
+                .map(|rss| format_size(rss, BINARY))
+                .unwrap_or_else(|| "-".to_string()),
+            change
+        );
+        print_text!("{}", line.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{Measurement, Variant, summarize_workload};
+
+    fn measurements(wall_times: &[f64]) -> Vec<Measurement> {
+        wall_times
+            .iter()
+            .map(|wall_time| Measurement {
+                wall_time: *wall_time,
+                user_time: Some(wall_time / 2.0),
+                sys_time: None,
+                max_rss: Some(1024),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn summarize_against_baseline() {
+        let result = summarize_workload(
+            &"{bin}".parse().unwrap(),
+            3,
+            [
+                (Variant::Baseline, measurements(&[2.0, 2.1, 1.9])),
+                (Variant::Pgo, measurements(&[1.0, 1.1, 0.9])),
+            ],
+        );
+        let baseline = &result.variants[0];
+        assert_eq!(baseline.change, None);
+        assert_eq!(baseline.user_time, Some(1.0));
+        assert_eq!(baseline.sys_time, None);
+        assert_eq!(baseline.max_rss, Some(1024));
+
+        let pgo = &result.variants[1];
+        assert_eq!(pgo.variant, "pgo");
+        assert_eq!(pgo.change, Some(-50.0));
+        assert_eq!(pgo.significant, Some(true));
+    }
+
+    #[test]
+    fn summarize_single_run() {
+        let result = summarize_workload(
+            &"{bin}".parse().unwrap(),
+            1,
+            [
+                (Variant::Baseline, measurements(&[2.0])),
+                (Variant::Pgo, measurements(&[2.0])),
+            ],
+        );
+        assert_eq!(result.variants[1].change, Some(0.0));
+        assert_eq!(result.variants[1].p_value, None);
+    }
+}
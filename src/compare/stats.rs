@@ -0,0 +1,169 @@
+/// Significance level used to decide whether a difference between two variants is significant.
+pub const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// Sample standard deviation.
+pub fn stddev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / (values.len() - 1) as f64;
+    variance.sqrt()
+}
+
+/// Performs Welch's t-test of the means of `a` and `b` and returns the two-sided p-value.
+/// Returns `None` if there are not enough samples.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let var_a = stddev(a).powi(2) / a.len() as f64;
+    let var_b = stddev(b).powi(2) / b.len() as f64;
+    let error = var_a + var_b;
+    if error == 0.0 {
+        // Both samples are constant
+        return Some(if mean(a) == mean(b) { 1.0 } else { 0.0 });
+    }
+    let t = (mean(a) - mean(b)) / error.sqrt();
+    let df = error.powi(2)
+        / (var_a.powi(2) / (a.len() - 1) as f64 + var_b.powi(2) / (b.len() - 1) as f64);
+    Some(regularized_incomplete_beta(
+        df / (df + t * t),
+        df / 2.0,
+        0.5,
+    ))
+}
+
+/// Natural logarithm of the gamma function (Lanczos approximation).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000000000190015;
+    for (index, coefficient) in COEFFICIENTS.iter().enumerate() {
+        series += coefficient / (x + 1.0 + index as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`.
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only for `x < (a + 1) / (a + b + 2)`
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Evaluates the continued fraction of the incomplete beta function (modified Lentz's method).
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const EPSILON: f64 = 1e-12;
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut result = d;
+    for m in 1..200 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let numerator = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1.0 + numerator / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        result *= d * c;
+
+        let numerator = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + numerator * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1.0 + numerator / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1.0 / d;
+        let delta = d * c;
+        result *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::stats::{mean, median, stddev, welch_t_test};
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-3,
+            "{value} is not close to {expected}"
+        );
+    }
+
+    #[test]
+    fn basic_stats() {
+        let values = [4.0, 1.0, 3.0, 2.0];
+        assert_close(mean(&values), 2.5);
+        assert_close(median(&values), 2.5);
+        assert_close(median(&values[..3]), 3.0);
+        assert_close(stddev(&values), 1.291);
+    }
+
+    #[test]
+    fn t_test_different_means() {
+        let a = [10.0, 10.2, 9.9, 10.1, 10.0];
+        let b = [8.0, 8.1, 7.9, 8.2, 8.0];
+        assert!(welch_t_test(&a, &b).unwrap() < 0.001);
+    }
+
+    #[test]
+    fn t_test_same_distribution() {
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let b = [1.5, 2.5, 2.0, 4.5, 4.0];
+        assert_close(welch_t_test(&a, &b).unwrap(), 0.9157);
+    }
+
+    #[test]
+    fn t_test_not_enough_samples() {
+        assert!(welch_t_test(&[1.0], &[2.0, 3.0]).is_none());
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use cargo_metadata::Metadata;
+use serde::{Deserialize, Serialize};
+
+use crate::bolt::preset::BoltPreset;
+use crate::workload::Workload;
+
+/// Name of a standalone configuration file that can be placed in the workspace root.
+pub const CONFIG_FILE_NAME: &str = "pgo.toml";
+
+/// Configuration of `cargo-pgo`, which can be stored in `pgo.toml` or in the
+/// `[workspace.metadata.pgo]` or `[package.metadata.pgo]` tables of `Cargo.toml`.
+///
+/// Values passed on the command line override the values from the configuration.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PgoConfig {
+    /// PGO profile directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profiles_dir: Option<PathBuf>,
+    /// BOLT profile directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bolt_profiles_dir: Option<PathBuf>,
+    /// Arguments passed to `cargo` when no arguments are passed on the command line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cargo_args: Option<Vec<String>>,
+    /// Flags passed to BOLT when `--bolt-args` is not passed on the command line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bolt_args: Option<String>,
+    /// BOLT flag preset used when neither `--bolt-args` nor `--bolt-preset` is passed on the
+    /// command line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bolt_preset: Option<BoltPreset>,
+    /// Flags passed to BOLT in addition to the other BOLT flags.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bolt_extra_args: Option<String>,
+    /// Weights of PGO profiles stored in subdirectories of the PGO profile directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_weights: Option<BTreeMap<String, u32>>,
+    /// Converter of `perf` data to LLVM sample profiles, used instead of `llvm-profgen`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_converter: Option<PathBuf>,
+    /// Workloads used by `cargo pgo pipeline` when no `--workload` is passed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workloads: Option<Vec<WorkloadConfig>>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WorkloadConfig {
+    /// Name of the workload. Profiles of the workload are stored in a subdirectory with this name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Weight of the profiles of this workload when they are merged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    /// Name of the binary target that should be executed.
+    /// It can be omitted if only a single binary is built.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// File that will be passed to the standard input of the binary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<PathBuf>,
+    /// Working directory of the binary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// How many times should the binary be executed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repetitions: Option<u32>,
+}
+
+impl WorkloadConfig {
+    /// Checks that the workload name can be used as the name of its profile subdirectory and that
+    /// the workload is executed at least once.
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(name) = &self.name {
+            let mut components = Path::new(name).components();
+            if !matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            ) {
+                return Err(anyhow!(
+                    "Invalid workload name `{name}`, it has to be a valid directory name"
+                ));
+            }
+        }
+        if self.repetitions == Some(0) {
+            return Err(anyhow!(
+                "Invalid number of repetitions of workload `{}`, it has to be at least 1",
+                self.to_workload()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_workload(&self) -> Workload {
+        let program = match &self.binary {
+            Some(binary) => format!("{{bin:{binary}}}"),
+            None => "{bin}".to_string(),
+        };
+        Workload {
+            name: self.name.clone(),
+            weight: self.weight,
+            program,
+            args: self.args.clone(),
+            env: self.env.clone(),
+            stdin: self.stdin.clone(),
+            cwd: self.cwd.clone(),
+            repetitions: self.repetitions.unwrap_or(1),
+        }
+    }
+}
+
+impl PgoConfig {
+    pub fn is_empty(&self) -> bool {
+        let PgoConfig {
+            profiles_dir,
+            bolt_profiles_dir,
+            cargo_args,
+            bolt_args,
+            bolt_preset,
+            bolt_extra_args,
+            profile_weights,
+            sample_converter,
+            workloads,
+        } = self;
+        profiles_dir.is_none()
+            && bolt_profiles_dir.is_none()
+            && cargo_args.is_none()
+            && bolt_args.is_none()
+            && bolt_preset.is_none()
+            && bolt_extra_args.is_none()
+            && profile_weights.is_none()
+            && sample_converter.is_none()
+            && workloads.is_none()
+    }
+
+    /// Overrides values of this configuration with values that are set in `other`.
+    fn merge(self, other: PgoConfig) -> PgoConfig {
+        PgoConfig {
+            profiles_dir: other.profiles_dir.or(self.profiles_dir),
+            bolt_profiles_dir: other.bolt_profiles_dir.or(self.bolt_profiles_dir),
+            cargo_args: other.cargo_args.or(self.cargo_args),
+            bolt_args: other.bolt_args.or(self.bolt_args),
+            bolt_preset: other.bolt_preset.or(self.bolt_preset),
+            bolt_extra_args: other.bolt_extra_args.or(self.bolt_extra_args),
+            profile_weights: other.profile_weights.or(self.profile_weights),
+            sample_converter: other.sample_converter.or(self.sample_converter),
+            workloads: other.workloads.or(self.workloads),
+        }
+    }
+
+    /// Makes relative paths in the configuration relative to `base`.
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut Option<PathBuf>| {
+            if let Some(path) = path {
+                *path = base.join(&*path);
+            }
+        };
+        resolve(&mut self.profiles_dir);
+        resolve(&mut self.bolt_profiles_dir);
+        // A bare binary name is looked up in PATH
+        if let Some(converter) = &mut self.sample_converter {
+            if converter.components().count() > 1 {
+                *converter = base.join(&*converter);
+            }
+        }
+        for workload in self.workloads.iter_mut().flatten() {
+            resolve(&mut workload.stdin);
+            resolve(&mut workload.cwd);
+        }
+    }
+}
+
+/// Loads `cargo-pgo` configuration from `pgo.toml` in the workspace root,
+/// `[workspace.metadata.pgo]` and `[package.metadata.pgo]`, in this order of precedence
+/// (from lowest to highest).
+pub(crate) fn load_config(metadata: &Metadata) -> anyhow::Result<PgoConfig> {
+    let workspace_root = metadata.workspace_root.as_std_path();
+    let mut config = PgoConfig::default();
+
+    let config_path = workspace_root.join(CONFIG_FILE_NAME);
+    if config_path.is_file() {
+        let content = std::fs::read_to_string(&config_path)?;
+        let file_config: PgoConfig = toml::from_str(&content)
+            .map_err(|error| anyhow!("Cannot parse {}: {}", config_path.display(), error))?;
+        config = config.merge(with_resolved_paths(file_config, workspace_root));
+    }
+
+    if let Some(value) = metadata.workspace_metadata.get("pgo") {
+        let workspace_config = parse_metadata_config(value, "workspace.metadata.pgo")?;
+        config = config.merge(with_resolved_paths(workspace_config, workspace_root));
+    }
+
+    if let Some(package) = metadata.root_package() {
+        if let Some(value) = package.metadata.get("pgo") {
+            let package_config = parse_metadata_config(value, "package.metadata.pgo")?;
+            let package_root = package
+                .manifest_path
+                .parent()
+                .map(|path| path.as_std_path())
+                .unwrap_or(workspace_root);
+            config = config.merge(with_resolved_paths(package_config, package_root));
+        }
+    }
+
+    for workload in config.workloads.iter().flatten() {
+        workload.validate()?;
+    }
+
+    log::debug!("Loaded configuration: {:?}", config);
+    Ok(config)
+}
+
+fn parse_metadata_config(value: &serde_json::Value, table: &str) -> anyhow::Result<PgoConfig> {
+    serde_json::from_value(value.clone())
+        .map_err(|error| anyhow!("Cannot parse `[{table}]` in Cargo.toml: {error}"))
+}
+
+fn with_resolved_paths(mut config: PgoConfig, base: &Path) -> PgoConfig {
+    config.resolve_paths(base);
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bolt::preset::BoltPreset;
+    use crate::config::PgoConfig;
+    use std::path::{Path, PathBuf};
+
+    fn parse(config: &str) -> PgoConfig {
+        toml::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn parse_full_config() {
+        let config = parse(
+            r#"
+profiles-dir = "profiles"
+cargo-args = ["--bin", "foo"]
+bolt-args = "-update-debug-sections"
+
+[[workloads]]
+binary = "foo"
+args = ["--input", "data.txt"]
+env = { FOO = "bar" }
+stdin = "input.txt"
+cwd = "work"
+repetitions = 3
+"#,
+        );
+        assert_eq!(config.profiles_dir, Some(PathBuf::from("profiles")));
+        assert_eq!(
+            config.cargo_args,
+            Some(vec!["--bin".to_string(), "foo".to_string()])
+        );
+        let workload = config.workloads.unwrap()[0].to_workload();
+        assert_eq!(workload.program, "{bin:foo}");
+        assert_eq!(workload.env["FOO"], "bar");
+        assert_eq!(workload.repetitions, 3);
+    }
+
+    #[test]
+    fn parse_weights() {
+        let config = parse(
+            r#"
+profile-weights = { api = 4, batch = 1 }
+
+[[workloads]]
+name = "api"
+weight = 4
+"#,
+        );
+        assert_eq!(config.profile_weights.unwrap()["api"], 4);
+        let workload = config.workloads.unwrap()[0].to_workload();
+        assert_eq!(workload.name, Some("api".to_string()));
+        assert_eq!(workload.weight, Some(4));
+    }
+
+    #[test]
+    fn validate_workloads() {
+        let validate = |config: &str| parse(config).workloads.unwrap()[0].validate();
+
+        assert!(validate("[[workloads]]\nname = \"api\"\nrepetitions = 2").is_ok());
+        assert!(validate("[[workloads]]").is_ok());
+        for name in ["..", ".", "", "/tmp", "a/b", "../a"] {
+            assert!(
+                validate(&format!("[[workloads]]\nname = \"{name}\"")).is_err(),
+                "{name}"
+            );
+        }
+        assert!(validate("[[workloads]]\nrepetitions = 0").is_err());
+    }
+
+    #[test]
+    fn parse_bolt_preset() {
+        let config = parse(
+            r#"
+bolt-preset = "aggressive"
+bolt-extra-args = "-hugify"
+"#,
+        );
+        assert_eq!(config.bolt_preset, Some(BoltPreset::Aggressive));
+        assert_eq!(config.bolt_extra_args, Some("-hugify".to_string()));
+    }
+
+    #[test]
+    fn parse_unknown_key() {
+        assert!(toml::from_str::<PgoConfig>("foo = 1").is_err());
+    }
+
+    #[test]
+    fn merge_override() {
+        let base = parse(
+            r#"
+profiles-dir = "a"
+bolt-args = "-foo"
+"#,
+        );
+        let config = base.merge(parse(r#"profiles-dir = "b""#));
+        assert_eq!(config.profiles_dir, Some(PathBuf::from("b")));
+        assert_eq!(config.bolt_args, Some("-foo".to_string()));
+    }
+
+    #[test]
+    fn resolve_relative_paths() {
+        let mut config = parse(
+            r#"
+profiles-dir = "profiles"
+
+[[workloads]]
+stdin = "input.txt"
+"#,
+        );
+        config.resolve_paths(Path::new("/project"));
+        assert_eq!(
+            config.profiles_dir,
+            Some(PathBuf::from("/project/profiles"))
+        );
+        assert_eq!(
+            config.workloads.unwrap()[0].stdin,
+            Some(PathBuf::from("/project/input.txt"))
+        );
+    }
+
+    #[test]
+    fn resolve_sample_converter() {
+        let mut config = parse(r#"sample-converter = "llvm-profgen-18""#);
+        config.resolve_paths(Path::new("/project"));
+        assert_eq!(
+            config.sample_converter,
+            Some(PathBuf::from("llvm-profgen-18"))
+        );
+
+        let mut config = parse(r#"sample-converter = "tools/profgen""#);
+        config.resolve_paths(Path::new("/project"));
+        assert_eq!(
+            config.sample_converter,
+            Some(PathBuf::from("/project/tools/profgen"))
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::{Display, Formatter};
+
+/// A failure of a `cargo-pgo` operation that callers might want to react to.
+///
+/// Functions of this crate return [`anyhow::Error`]. If the failure belongs to one of the kinds
+/// below, it can be recovered with `error.downcast_ref::<PgoError>()`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PgoError {
+    /// A required external tool (e.g. `llvm-profdata` or `llvm-bolt`) could not be found.
+    ToolNotFound { tool: String, message: String },
+    /// No profiles were found, or a workload did not produce any profiles.
+    NoProfiles(String),
+    /// Gathered profiles could not be merged.
+    ProfileMerge(String),
+    /// A Cargo command has failed.
+    Cargo {
+        /// Exit code of Cargo, if it has exited normally.
+        exit_code: Option<i32>,
+    },
+    /// BOLT has failed to instrument or optimize a binary.
+    Bolt(String),
+    /// `llvm-profdata` uses a different major LLVM version than `rustc`.
+    LlvmVersionMismatch(String),
+}
+
+impl PgoError {
+    /// Exit code of `cargo-pgo` when it fails with this error. The exit codes are also documented
+    /// in `README.md`, which has to contain the same table.
+    ///
+    /// | Exit code | Failure                                                              |
+    /// |-----------|----------------------------------------------------------------------|
+    /// | 1         | Other error                                                          |
+    /// | 2         | Invalid command line arguments                                       |
+    /// | 3         | A required tool (e.g. `llvm-profdata` or `llvm-bolt`) was not found  |
+    /// | 4         | No profiles were found, or a workload did not produce any profiles   |
+    /// | 5         | Profiles could not be merged                                         |
+    /// | 6         | A Cargo command has failed                                           |
+    /// | 7         | BOLT has failed to instrument or optimize a binary                   |
+    /// | 8         | `llvm-profdata` uses a different major LLVM version than `rustc`     |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PgoError::ToolNotFound { .. } => 3,
+            PgoError::NoProfiles(_) => 4,
+            PgoError::ProfileMerge(_) => 5,
+            PgoError::Cargo { .. } => 6,
+            PgoError::Bolt(_) => 7,
+            PgoError::LlvmVersionMismatch(_) => 8,
+        }
+    }
+}
+
+impl Display for PgoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgoError::ToolNotFound { message, .. }
+            | PgoError::NoProfiles(message)
+            | PgoError::ProfileMerge(message)
+            | PgoError::Bolt(message)
+            | PgoError::LlvmVersionMismatch(message) => f.write_str(message),
+            PgoError::Cargo { exit_code } => write!(
+                f,
+                "Cargo finished with an error ({})",
+                exit_code.unwrap_or(-1)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PgoError {}
+
+/// Creates an error describing that `tool` could not be found.
+pub(crate) fn tool_not_found(tool: &str, error: anyhow::Error) -> anyhow::Error {
+    PgoError::ToolNotFound {
+        tool: tool.to_string(),
+        message: format!("Cannot find {tool}: {error:?}"),
+    }
+    .into()
+}
+
+/// Returns the exit code of `cargo-pgo` for the given `error`.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<PgoError>()
+        .map(|error| error.exit_code())
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use crate::error::{PgoError, exit_code};
+
+    #[test]
+    fn exit_code_of_error() {
+        let error = anyhow::Error::from(PgoError::Cargo {
+            exit_code: Some(101),
+        });
+        assert_eq!(error.to_string(), "Cargo finished with an error (101)");
+        assert_eq!(exit_code(&error), 6);
+        assert_eq!(exit_code(&anyhow::anyhow!("foo")), 1);
+    }
+
+    #[test]
+    fn exit_code_with_context() {
+        let error: anyhow::Result<()> = Err(PgoError::NoProfiles("No profiles".to_string()).into());
+        let error = error.context("Cannot optimize").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PgoError>(),
+            Some(PgoError::NoProfiles(_))
+        ));
+        assert_eq!(exit_code(&error), 4);
+    }
+
+    /// The exit code table in the documentation of [`PgoError::exit_code`] has to match the one
+    /// in `README.md` and it has to contain all exit codes.
+    #[test]
+    fn exit_codes_documented() {
+        let rows: Vec<&str> = include_str!("error.rs")
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("/// | "))
+            .filter(|row| row.starts_with(|c: char| c.is_ascii_digit()))
+            .collect();
+        let readme_rows: Vec<&str> = include_str!("../README.md")
+            .lines()
+            .filter_map(|line| line.strip_prefix("| "))
+            .filter(|row| row.starts_with(|c: char| c.is_ascii_digit()))
+            .collect();
+        assert_eq!(rows, readme_rows);
+        let codes: Vec<i32> = rows
+            .iter()
+            .map(|row| row.split_whitespace().next().unwrap().parse().unwrap())
+            .collect();
+
+        let errors = [
+            PgoError::ToolNotFound {
+                tool: String::new(),
+                message: String::new(),
+            },
+            PgoError::NoProfiles(String::new()),
+            PgoError::ProfileMerge(String::new()),
+            PgoError::Cargo { exit_code: None },
+            PgoError::Bolt(String::new()),
+            PgoError::LlvmVersionMismatch(String::new()),
+        ];
+        for error in errors {
+            assert!(
+                codes.contains(&error.exit_code()),
+                "Exit code of {error:?} is not documented"
+            );
+        }
+    }
+}
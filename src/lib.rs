@@ -8,8 +8,15 @@ pub mod build;
 pub mod check;
 pub mod clean;
 pub(crate) mod cli;
+pub mod compare;
+pub mod config;
+pub mod error;
+pub mod output;
 pub mod pgo;
+pub mod pipeline;
+pub mod session;
 pub(crate) mod utils;
+pub mod workload;
 pub(crate) mod workspace;
 
 use std::ffi::OsStr;
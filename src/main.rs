@@ -2,12 +2,26 @@ use std::path::PathBuf;
 
 use cargo_pgo::bolt::instrument::{BoltInstrumentArgs, bolt_instrument};
 use cargo_pgo::bolt::optimize::{BoltOptimizeArgs, bolt_optimize};
+use cargo_pgo::bolt::record::{BoltRecordArgs, bolt_record};
+use cargo_pgo::bolt::run::{BoltRunArgs, bolt_run};
 use cargo_pgo::build::CargoCommand;
 use cargo_pgo::check::environment_info;
 use cargo_pgo::clean::clean_artifacts;
+use cargo_pgo::compare::{CompareArgs, pgo_compare};
+use cargo_pgo::error::exit_code;
 use cargo_pgo::get_cargo_ctx;
+use cargo_pgo::output::{OutputFormat, set_output_format};
+use cargo_pgo::pgo::cs::{PgoCsBuildArgs, pgo_cs_build};
 use cargo_pgo::pgo::instrument::{PgoInstrumentArgs, PgoInstrumentShortcutArgs, pgo_instrument};
 use cargo_pgo::pgo::optimize::{PgoOptimizeArgs, pgo_optimize};
+use cargo_pgo::pgo::profile::bundle::{
+    ProfileExportArgs, ProfileImportArgs, pgo_profile_export, pgo_profile_import,
+};
+use cargo_pgo::pgo::profile::diff::{ProfileDiffArgs, pgo_profile_diff};
+use cargo_pgo::pgo::profile::show::{ProfileShowArgs, pgo_profile_show};
+use cargo_pgo::pgo::sample::build::{SampleBuildArgs, sample_build};
+use cargo_pgo::pgo::sample::optimize::{SampleOptimizeArgs, sample_optimize};
+use cargo_pgo::pipeline::{PipelineArgs, pgo_pipeline};
 use clap::Parser;
 use env_logger::Env;
 
@@ -16,9 +30,19 @@ use env_logger::Env;
 #[clap(bin_name("cargo"))]
 #[clap(disable_help_subcommand(true))]
 enum Args {
-    #[clap(subcommand)]
     #[clap(author, version, about)]
-    Pgo(Subcommand),
+    Pgo(PgoArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct PgoArgs {
+    /// Format of the output printed to stdout. With `json`, the commands print structured events
+    /// (built artifacts, profile paths, etc.) as JSON lines and forward Cargo messages as JSON.
+    #[clap(long = "output", value_enum, global = true, default_value = "text")]
+    output_format: OutputFormat,
+
+    #[clap(subcommand)]
+    command: Subcommand,
 }
 
 #[derive(clap::Subcommand, Debug)]
@@ -44,6 +68,21 @@ enum Subcommand {
     Bench(PgoInstrumentShortcutArgs),
     /// Build an optimized version of a binary using generated PGO profiles.
     Optimize(PgoOptimizeArgs),
+    /// Build a PGO-optimized binary with additional context-sensitive instrumentation. When
+    /// executed, the binary will produce profiles that can be used by `optimize --cs`.
+    CsBuild(PgoCsBuildArgs),
+    /// Build a PGO-instrumented binary, run the given workloads on it and then build a
+    /// PGO-optimized binary, all in one step. Optionally also optimize the binary with BOLT.
+    Pipeline(PipelineArgs),
+    /// Build a plain release binary and a PGO-optimized binary (and optionally use the
+    /// BOLT-optimized binary), benchmark them with the given workloads and compare the results.
+    Compare(CompareArgs),
+    /// Inspect gathered PGO profiles.
+    #[clap(subcommand)]
+    Profile(ProfileArgs),
+    /// Sampling-based PGO using profiles recorded with `perf`.
+    #[clap(subcommand)]
+    Sample(SampleArgs),
     /// Optimization using BOLT.
     #[clap(subcommand)]
     Bolt(BoltArgs),
@@ -51,12 +90,61 @@ enum Subcommand {
     Clean(CleanArgs),
 }
 
+#[derive(clap::Subcommand, Debug)]
+enum ProfileArgs {
+    /// Show the hottest functions of a PGO profile, with demangled names.
+    Show(ProfileShowArgs),
+    /// Compare two PGO profiles (or directories with profiles) and show functions whose hotness
+    /// has changed.
+    Diff(ProfileDiffArgs),
+    /// Package a merged PGO profile together with a manifest describing the environment in which
+    /// it was created into a single archive.
+    Export(ProfileExportArgs),
+    /// Verify a profile bundle created by `cargo pgo profile export` and extract its profile into
+    /// the PGO profile directory.
+    Import(ProfileImportArgs),
+}
+
+impl ProfileArgs {
+    pub fn profiles_dir(&self) -> Option<PathBuf> {
+        match self {
+            ProfileArgs::Show(args) => args.profiles_dir().to_owned(),
+            ProfileArgs::Export(args) => args.profiles_dir().to_owned(),
+            ProfileArgs::Import(args) => args.profiles_dir().to_owned(),
+            ProfileArgs::Diff(..) => None,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum SampleArgs {
+    /// Run `cargo build` with debug info to prepare a binary for profiling with `perf`.
+    Build(SampleBuildArgs),
+    /// Convert recorded perf data to a sample profile and use it to build an optimized binary.
+    Optimize(SampleOptimizeArgs),
+}
+
+impl SampleArgs {
+    fn cargo_args(&self) -> &[String] {
+        match self {
+            SampleArgs::Build(args) => args.cargo_args(),
+            SampleArgs::Optimize(args) => args.cargo_args(),
+        }
+    }
+}
+
 #[derive(clap::Subcommand, Debug)]
 enum BoltArgs {
     /// Run `cargo build` with instrumentation to prepare for BOLT optimization.
     Build(BoltInstrumentArgs),
     /// Built an optimized version of a binary using generated BOLT profiles.
     Optimize(BoltOptimizeArgs),
+    /// Run `cargo build` and gather BOLT profiles by executing the binary under `perf record`,
+    /// without instrumenting it.
+    Record(BoltRecordArgs),
+    /// Run `cargo build` with BOLT instrumentation and then execute the instrumented binary to
+    /// gather BOLT profiles.
+    Run(BoltRunArgs),
 }
 
 impl BoltArgs {
@@ -64,6 +152,8 @@ impl BoltArgs {
         match self {
             BoltArgs::Build(args) => args.cargo_args(),
             BoltArgs::Optimize(args) => args.cargo_args(),
+            BoltArgs::Run(args) => args.cargo_args(),
+            BoltArgs::Record(args) => args.cargo_args(),
         }
     }
 
@@ -71,6 +161,8 @@ impl BoltArgs {
         match self {
             BoltArgs::Build(args) => args.profiles_dir(),
             BoltArgs::Optimize(args) => args.profiles_dir(),
+            BoltArgs::Record(args) => args.profiles_dir(),
+            BoltArgs::Run(args) => args.profiles_dir(),
         }
     }
 }
@@ -88,7 +180,7 @@ struct CleanArgs {
 impl Args {
     fn cargo_args(&self) -> &[String] {
         match self {
-            Args::Pgo(args) => match args {
+            Args::Pgo(args) => match &args.command {
                 Subcommand::Info => &[],
                 Subcommand::Instrument(args) => args.cargo_args(),
                 Subcommand::Build(args)
@@ -96,15 +188,19 @@ impl Args {
                 | Subcommand::Test(args)
                 | Subcommand::Bench(args) => args.cargo_args(),
                 Subcommand::Optimize(args) => args.cargo_args(),
+                Subcommand::CsBuild(args) => args.cargo_args(),
+                Subcommand::Pipeline(args) => args.cargo_args(),
+                Subcommand::Compare(args) => args.cargo_args(),
+                Subcommand::Sample(args) => args.cargo_args(),
                 Subcommand::Bolt(args) => args.cargo_args(),
-                Subcommand::Clean(..) => &[],
+                Subcommand::Profile(..) | Subcommand::Clean(..) => &[],
             },
         }
     }
 
     fn profiles_dir(&self) -> Option<PathBuf> {
         match self {
-            Args::Pgo(args) => match args {
+            Args::Pgo(args) => match &args.command {
                 Subcommand::Info => None,
                 Subcommand::Instrument(args) => args.profiles_dir().to_owned(),
                 Subcommand::Build(args)
@@ -112,16 +208,22 @@ impl Args {
                 | Subcommand::Test(args)
                 | Subcommand::Bench(args) => args.profiles_dir().to_owned(),
                 Subcommand::Optimize(args) => args.profiles_dir().to_owned(),
+                Subcommand::CsBuild(args) => args.profiles_dir().to_owned(),
+                Subcommand::Pipeline(args) => args.profiles_dir().to_owned(),
+                Subcommand::Compare(args) => args.profiles_dir().to_owned(),
+                Subcommand::Profile(args) => args.profiles_dir(),
                 Subcommand::Clean(CleanArgs { profiles_dir, .. }) => profiles_dir.to_owned(),
-                Subcommand::Bolt(..) => None,
+                Subcommand::Sample(..) | Subcommand::Bolt(..) => None,
             },
         }
     }
 
     fn bolt_profiles_dir(&self) -> Option<PathBuf> {
         match self {
-            Args::Pgo(args) => match args {
+            Args::Pgo(args) => match &args.command {
                 Subcommand::Bolt(args) => args.profiles_dir().to_owned(),
+                Subcommand::Pipeline(args) => args.bolt_profiles_dir().to_owned(),
+                Subcommand::Compare(args) => args.bolt_profiles_dir().to_owned(),
                 Subcommand::Clean(CleanArgs {
                     bolt_profiles_dir, ..
                 }) => bolt_profiles_dir.to_owned(),
@@ -131,7 +233,10 @@ impl Args {
                 | Subcommand::Run(..)
                 | Subcommand::Test(..)
                 | Subcommand::Bench(..)
-                | Subcommand::Optimize(..) => None,
+                | Subcommand::Optimize(..)
+                | Subcommand::CsBuild(..)
+                | Subcommand::Profile(..)
+                | Subcommand::Sample(..) => None,
             },
         }
     }
@@ -146,16 +251,28 @@ fn run() -> anyhow::Result<()> {
     let ctx = get_cargo_ctx(cargo_args, profiles_dir, bolt_profiles_dir)?;
 
     let Args::Pgo(args) = args;
-    match args {
-        Subcommand::Info => environment_info(),
-        Subcommand::Instrument(args) => pgo_instrument(ctx, args),
-        Subcommand::Build(args) => pgo_instrument(ctx, args.into_full_args(CargoCommand::Build)),
-        Subcommand::Test(args) => pgo_instrument(ctx, args.into_full_args(CargoCommand::Test)),
-        Subcommand::Run(args) => pgo_instrument(ctx, args.into_full_args(CargoCommand::Run)),
-        Subcommand::Bench(args) => pgo_instrument(ctx, args.into_full_args(CargoCommand::Bench)),
-        Subcommand::Optimize(args) => pgo_optimize(ctx, args),
-        Subcommand::Bolt(BoltArgs::Build(args)) => bolt_instrument(ctx, args),
-        Subcommand::Bolt(BoltArgs::Optimize(args)) => bolt_optimize(ctx, args),
+    set_output_format(args.output_format);
+    match args.command {
+        Subcommand::Info => environment_info(&ctx),
+        Subcommand::Instrument(args) => pgo_instrument(&ctx, args),
+        Subcommand::Build(args) => pgo_instrument(&ctx, args.into_full_args(CargoCommand::Build)),
+        Subcommand::Test(args) => pgo_instrument(&ctx, args.into_full_args(CargoCommand::Test)),
+        Subcommand::Run(args) => pgo_instrument(&ctx, args.into_full_args(CargoCommand::Run)),
+        Subcommand::Bench(args) => pgo_instrument(&ctx, args.into_full_args(CargoCommand::Bench)),
+        Subcommand::Optimize(args) => pgo_optimize(&ctx, args),
+        Subcommand::CsBuild(args) => pgo_cs_build(&ctx, args),
+        Subcommand::Pipeline(args) => pgo_pipeline(&ctx, args),
+        Subcommand::Compare(args) => pgo_compare(&ctx, args),
+        Subcommand::Profile(ProfileArgs::Show(args)) => pgo_profile_show(&ctx, args),
+        Subcommand::Profile(ProfileArgs::Diff(args)) => pgo_profile_diff(args),
+        Subcommand::Profile(ProfileArgs::Export(args)) => pgo_profile_export(&ctx, args),
+        Subcommand::Profile(ProfileArgs::Import(args)) => pgo_profile_import(&ctx, args),
+        Subcommand::Sample(SampleArgs::Build(args)) => sample_build(&ctx, args),
+        Subcommand::Sample(SampleArgs::Optimize(args)) => sample_optimize(&ctx, args),
+        Subcommand::Bolt(BoltArgs::Build(args)) => bolt_instrument(&ctx, args),
+        Subcommand::Bolt(BoltArgs::Optimize(args)) => bolt_optimize(&ctx, args),
+        Subcommand::Bolt(BoltArgs::Record(args)) => bolt_record(&ctx, args),
+        Subcommand::Bolt(BoltArgs::Run(args)) => bolt_run(&ctx, args),
         Subcommand::Clean(..) => clean_artifacts(ctx),
     }
 }
@@ -165,6 +282,6 @@ fn main() {
 
     if let Err(error) = run() {
         eprintln!("{}", format!("{error:?}").trim_end_matches('\n'));
-        std::process::exit(1);
+        std::process::exit(exit_code(&error));
     }
 }
@@ -0,0 +1,196 @@
+use std::fmt::Arguments;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::OnceLock;
+
+use cargo_metadata::Message;
+use serde::Serialize;
+
+use crate::bolt::stats::BoltStats;
+
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Format of the output printed to stdout.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable output.
+    #[default]
+    Text,
+    /// Structured events printed as JSON lines.
+    Json,
+}
+
+/// Sets the output format for the rest of the execution. Can only be set once.
+pub fn set_output_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+pub(crate) fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Tool that has produced an artifact or a profile.
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Tool {
+    Pgo,
+    Bolt,
+}
+
+/// An event printed to stdout as a JSON line when the JSON output format is used.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum Event<'a> {
+    /// Directory where profiles gathered from instrumented artifacts will be stored.
+    ProfileDir {
+        tool: Tool,
+        path: &'a Path,
+    },
+    /// An artifact was built by Cargo, before it was processed by BOLT.
+    ArtifactBuilt {
+        target: &'a str,
+        kind: &'a str,
+        path: &'a Path,
+    },
+    /// An instrumented artifact was created.
+    Instrumented {
+        tool: Tool,
+        target: &'a str,
+        kind: &'a str,
+        path: &'a Path,
+    },
+    /// Profiles were merged into a single profile used for optimization.
+    MergedProfile {
+        tool: Tool,
+        path: &'a Path,
+        hash: &'a str,
+    },
+    /// Functions without PGO profile data were found during an optimized build.
+    MissingProfiles {
+        count: usize,
+        total_functions: Option<usize>,
+    },
+    /// An optimized artifact was created.
+    Optimized {
+        tool: Tool,
+        target: &'a str,
+        kind: &'a str,
+        path: &'a Path,
+    },
+    /// Statistics printed by BOLT while optimizing an artifact.
+    BoltStats {
+        target: &'a str,
+        stats: &'a BoltStats,
+    },
+    BuildFinished {
+        success: bool,
+    },
+    /// A message produced by Cargo, forwarded without changes.
+    CargoMessage {
+        message: &'a Message,
+    },
+    /// A line printed by Cargo that is not a JSON message.
+    CargoText {
+        line: &'a str,
+    },
+}
+
+/// Prints `event` to stdout if the JSON output format is used. Does nothing otherwise.
+pub(crate) fn emit(event: Event) {
+    if output_format() != OutputFormat::Json {
+        return;
+    }
+    let line = serde_json::to_string(&event).expect("Cannot serialize output event");
+    if let Err(error) = write_line(&mut std::io::stdout().lock(), &line) {
+        log::debug!("Cannot write output event: {error}");
+    }
+}
+
+/// Writes a line of JSON output to `writer`. A closed reader of the output (e.g. when the output is
+/// piped to `head`) is not considered to be an error.
+fn write_line<W: Write>(writer: &mut W, line: &str) -> std::io::Result<()> {
+    match writeln!(writer, "{line}").and_then(|_| writer.flush()) {
+        Err(error) if error.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Prints `value` as JSON to stdout. It is printed on a single line with the JSON output format,
+/// so that it can be parsed in the same way as the JSON events.
+pub(crate) fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    let line = match output_format() {
+        OutputFormat::Text => serde_json::to_string_pretty(value)?,
+        OutputFormat::Json => serde_json::to_string(value)?,
+    };
+    write_line(&mut std::io::stdout().lock(), &line)?;
+    Ok(())
+}
+
+/// Prints a line of human-readable output, like `println!`. With the JSON output format, the line
+/// is printed to stderr instead, so that stdout only contains JSON events.
+macro_rules! print_text {
+    ($($arg:tt)*) => {
+        $crate::output::write_text(format_args!($($arg)*))
+    };
+}
+pub(crate) use print_text;
+
+pub(crate) fn write_text(args: Arguments) {
+    match output_format() {
+        OutputFormat::Text => println!("{args}"),
+        OutputFormat::Json => eprintln!("{args}"),
+    }
+}
+
+/// Returns the stdout of an executed child process (e.g. a workload). With the JSON output format,
+/// the output of the child is redirected to stderr, so that stdout only contains JSON events.
+pub(crate) fn child_stdout() -> Stdio {
+    match output_format() {
+        OutputFormat::Text => Stdio::inherit(),
+        OutputFormat::Json => std::io::stderr().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Write};
+    use std::path::Path;
+
+    use crate::output::{Event, Tool, write_line};
+
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn write_line_to_closed_pipe() {
+        assert!(write_line(&mut ClosedPipe, "{}").is_ok());
+
+        let mut output = vec![];
+        write_line(&mut output, "{}").unwrap();
+        assert_eq!(output, b"{}\n");
+    }
+
+    #[test]
+    fn serialize_event() {
+        let event = Event::Instrumented {
+            tool: Tool::Bolt,
+            target: "foo",
+            kind: "binary",
+            path: Path::new("/target/foo-bolt-instrumented"),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"instrumented","tool":"bolt","target":"foo","kind":"binary","path":"/target/foo-bolt-instrumented"}"#
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use cargo_metadata::Message;
+use colored::Colorize;
+
+use crate::build::{
+    CargoCommand, cargo_command_with_rustflags, get_artifact_kind, handle_metadata_message,
+};
+use crate::clear_directory;
+use crate::cli::cli_format_path;
+use crate::pgo::env::PgoEnv;
+use crate::pgo::optimize::{
+    ProfileWeights, find_checked_pgo_env, gather_pgo_profile_stats, merge_profiles,
+    prepare_pgo_profile, print_pgo_profile_stats,
+};
+use crate::workspace::CargoContext;
+
+#[derive(clap::Parser, Debug)]
+pub struct PgoCsBuildArgs {
+    /// Do not remove context-sensitive profiles that were gathered during previous runs.
+    #[clap(long, action)]
+    keep_profiles: bool,
+
+    /// Override the PGO profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// Linker used to link the binary with LTO. It has to be a `clang` binary that uses the same
+    /// LLVM version as `rustc`.
+    #[clap(long, default_value = "clang")]
+    cs_linker: String,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+
+    /// Additional arguments that will be passed to `cargo build`.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl PgoCsBuildArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+}
+
+/// Builds a PGO-optimized binary that is additionally instrumented with context-sensitive
+/// instrumentation. When executed, it produces context-sensitive profiles, which can be merged
+/// with the original PGO profiles by `cargo pgo optimize --cs`.
+pub fn pgo_cs_build(ctx: &CargoContext, args: PgoCsBuildArgs) -> anyhow::Result<()> {
+    let pgo_dir = ctx.get_pgo_directory()?;
+    let cs_dir = ctx.get_cs_pgo_directory()?;
+    let pgo_env = find_checked_pgo_env(args.allow_llvm_mismatch)?;
+
+    let profile = prepare_pgo_profile(&pgo_env, &pgo_dir, &ctx.resolve_profile_weights(vec![]))?;
+
+    if !args.keep_profiles {
+        log::info!("Context-sensitive PGO profile directory will be cleared.");
+        clear_directory(&cs_dir)?;
+    }
+    log::info!(
+        "Context-sensitive PGO profiles will be stored into {}.",
+        cli_format_path(cs_dir.display())
+    );
+
+    let flags = cs_instrument_flags(&profile, &cs_dir, &args.cs_linker);
+    let mut cargo = cargo_command_with_rustflags(
+        CargoCommand::Build,
+        flags,
+        ctx.resolve_cargo_args(args.cargo_args),
+    )?;
+
+    for message in cargo.messages() {
+        let message = message?;
+        match message {
+            Message::CompilerArtifact(artifact) => {
+                if let Some(ref executable) = artifact.executable {
+                    log::info!(
+                        "CS-PGO-instrumented {} {} built successfully.",
+                        get_artifact_kind(&artifact).yellow(),
+                        artifact.target.name.blue()
+                    );
+                    log::info!(
+                        "Now run {} on your workload and then build the final binary with {}.",
+                        cli_format_path(executable),
+                        "cargo pgo optimize --cs".blue()
+                    );
+                }
+            }
+            Message::BuildFinished(res) => {
+                if res.success {
+                    log::info!(
+                        "CS-PGO instrumentation build finished {}.",
+                        "successfully".green()
+                    );
+                } else {
+                    log::error!("CS-PGO instrumentation build has {}.", "failed".red());
+                }
+            }
+            _ => handle_metadata_message(message),
+        }
+    }
+
+    cargo.check_status()?;
+
+    Ok(())
+}
+
+/// Merges the merged PGO `profile` with the context-sensitive profiles from `cs_dir` and returns
+/// the path to the combined profile.
+pub(crate) fn merge_cs_profiles(
+    pgo_env: &PgoEnv,
+    profile: &Path,
+    cs_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let mut stats = gather_pgo_profile_stats(cs_dir, &ProfileWeights::default())?;
+    print_pgo_profile_stats(&stats, cs_dir)?;
+    stats.add_merged_profile(profile)?;
+    merge_profiles(pgo_env, &stats, cs_dir)
+}
+
+/// Linker flags that enable LTO with `lld`, so that the LTO backend can perform the
+/// context-sensitive instrumentation or optimization. `rustc` itself cannot do that.
+fn lto_link_flags(linker: &str) -> Vec<String> {
+    vec![
+        "-Clinker-plugin-lto".to_string(),
+        format!("-Clinker={linker}"),
+        "-Clink-arg=-fuse-ld=lld".to_string(),
+        "-Clink-arg=-flto".to_string(),
+    ]
+}
+
+/// Creates RUSTFLAGS that use the PGO `profile` and add context-sensitive instrumentation,
+/// which will store profiles into `cs_dir`.
+fn cs_instrument_flags(profile: &Path, cs_dir: &Path, linker: &str) -> Vec<String> {
+    let mut flags = vec![format!("-Cprofile-use={}", profile.display())];
+    flags.extend(lto_link_flags(linker));
+    // `clang` also links the profiler runtime when this flag is used
+    flags.push(format!(
+        "-Clink-arg=-fcs-profile-generate={}",
+        cs_dir.display()
+    ));
+    flags
+}
+
+/// Creates RUSTFLAGS that use the combined PGO and context-sensitive `profile`.
+pub(crate) fn cs_use_flags(profile: &Path, linker: &str) -> Vec<String> {
+    let mut flags = vec![
+        format!("-Cprofile-use={}", profile.display()),
+        "-Cllvm-args=-pgo-warn-missing-function".to_string(),
+    ];
+    flags.extend(lto_link_flags(linker));
+    flags.push(format!(
+        "-Clink-arg=-Wl,--lto-cs-profile-file={}",
+        profile.display()
+    ));
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgo::cs::{cs_instrument_flags, cs_use_flags};
+    use std::path::Path;
+
+    #[test]
+    fn instrument_flags() {
+        let flags = cs_instrument_flags(
+            Path::new("/pgo/merged.profdata"),
+            Path::new("/cs-pgo"),
+            "clang",
+        );
+        assert_eq!(
+            flags,
+            vec![
+                "-Cprofile-use=/pgo/merged.profdata",
+                "-Clinker-plugin-lto",
+                "-Clinker=clang",
+                "-Clink-arg=-fuse-ld=lld",
+                "-Clink-arg=-flto",
+                "-Clink-arg=-fcs-profile-generate=/cs-pgo",
+            ]
+        );
+    }
+
+    #[test]
+    fn use_flags() {
+        let flags = cs_use_flags(Path::new("/cs-pgo/merged.profdata"), "clang-18");
+        assert_eq!(
+            flags,
+            vec![
+                "-Cprofile-use=/cs-pgo/merged.profdata",
+                "-Cllvm-args=-pgo-warn-missing-function",
+                "-Clinker-plugin-lto",
+                "-Clinker=clang-18",
+                "-Clink-arg=-fuse-ld=lld",
+                "-Clink-arg=-flto",
+                "-Clink-arg=-Wl,--lto-cs-profile-file=/cs-pgo/merged.profdata",
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::PgoError;
 use crate::{resolve_binary, run_command};
 use colored::Colorize;
 use std::path::{Path, PathBuf};
@@ -5,44 +6,159 @@ use std::path::{Path, PathBuf};
 #[derive(Debug)]
 pub struct PgoEnv {
     pub llvm_profdata: PathBuf,
+    /// Major LLVM version of `llvm-profdata`, if it could be determined.
+    pub version: Option<u32>,
 }
 
 pub fn find_pgo_env() -> anyhow::Result<PgoEnv> {
     // Try to resolve `llvm-profdata` from `llvm-tools-preview`
+    if let Some(llvm_profdata) = find_llvm_tool("llvm-profdata")? {
+        let version = get_llvm_profdata_version(&llvm_profdata);
+        return Ok(PgoEnv {
+            llvm_profdata,
+            version,
+        });
+    }
+
+    // Try to find `llvm-profdata` directly in PATH
+    if let Ok(llvm_profdata) = resolve_binary(Path::new("llvm-profdata"))
+        .or_else(|_| resolve_binary(Path::new("llvm-profdata.exe")))
+    {
+        let version = get_llvm_profdata_version(&llvm_profdata);
+        if version.is_none() {
+            log::warn!(
+                "llvm-profdata was resolved from PATH and its LLVM version cannot be determined. \
+Make sure that its version is compatible with rustc! If not, run `{}`.",
+                "rustup component add llvm-tools-preview".blue()
+            );
+        }
+
+        Ok(PgoEnv {
+            llvm_profdata,
+            version,
+        })
+    } else {
+        Err(PgoError::ToolNotFound {
+            tool: "llvm-profdata".to_string(),
+            message: "Could not find `llvm-profdata`".to_string(),
+        }
+        .into())
+    }
+}
+
+/// Tries to find a binary with the given `name` in the `llvm-tools-preview` component of the
+/// current toolchain.
+pub(crate) fn find_llvm_tool(name: &str) -> anyhow::Result<Option<PathBuf>> {
     let path = run_command("rustc", &["--print", "target-libdir"])?
         .ok()?
         .stdout;
 
     let mut libpath = PathBuf::from(path);
     libpath.pop();
-    libpath.push("bin/llvm-profdata");
+    libpath.push("bin");
+    libpath.push(name);
 
     if libpath.exists() {
-        return Ok(PgoEnv {
-            llvm_profdata: libpath,
-        });
+        return Ok(Some(libpath));
     }
 
-    libpath.set_file_name("llvm-profdata.exe");
+    libpath.set_file_name(format!("{name}.exe"));
 
     if libpath.exists() {
-        return Ok(PgoEnv {
-            llvm_profdata: libpath,
-        });
+        return Ok(Some(libpath));
     }
+    Ok(None)
+}
 
-    // Try to find `llvm-profdata` directly in PATH
-    if let Ok(llvm_profdata) = resolve_binary(Path::new("llvm-profdata"))
-        .or_else(|_| resolve_binary(Path::new("llvm-profdata.exe")))
-    {
-        log::warn!(
-            "llvm-profdata was resolved from PATH. \
-Make sure that its version is compatible with rustc! If not, run `{}`.",
+/// Returns the major LLVM version of the given `llvm-profdata` binary.
+fn get_llvm_profdata_version(llvm_profdata: &Path) -> Option<u32> {
+    // Older versions of `llvm-profdata` only support `--version` after a subcommand
+    run_command(llvm_profdata, &["merge", "--version"])
+        .and_then(|output| output.ok())
+        .ok()
+        .and_then(|output| parse_llvm_version(&output.stdout))
+}
+
+/// Returns the major LLVM version used by `rustc`.
+pub(crate) fn rustc_llvm_version() -> Option<u32> {
+    let version = rustc_version::version_meta().ok()?.llvm_version?;
+    u32::try_from(version.major).ok()
+}
+
+/// Checks that `llvm-profdata` uses the same major LLVM version as `rustc`. Otherwise, it will
+/// most likely not be able to read the raw profiles produced by instrumented binaries.
+/// Returns a description of the mismatch if the versions differ.
+pub(crate) fn check_llvm_version(pgo_env: &PgoEnv, rustc_llvm: Option<u32>) -> Result<(), String> {
+    match (pgo_env.version, rustc_llvm) {
+        (Some(profdata_llvm), Some(rustc_llvm)) if profdata_llvm != rustc_llvm => Err(format!(
+            "`llvm-profdata` at {} uses LLVM {}, but `rustc` uses LLVM {}, so the profile formats \
+will most likely not match. Use `llvm-profdata` from LLVM {}, e.g. by running `{}`.",
+            pgo_env.llvm_profdata.display(),
+            profdata_llvm,
+            rustc_llvm,
+            rustc_llvm,
             "rustup component add llvm-tools-preview".blue()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Parses the major version from the `--version` output of an LLVM tool, which contains a line
+/// like `LLVM version 17.0.6`.
+pub(crate) fn parse_llvm_version(output: &str) -> Option<u32> {
+    output.lines().find_map(|line| {
+        let (_, version) = line.split_once("LLVM version ")?;
+        version.split('.').next()?.trim().parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::pgo::env::{PgoEnv, check_llvm_version, parse_llvm_version};
+
+    #[test]
+    fn parse_version() {
+        let output = "LLVM (http://llvm.org/):
+  LLVM version 17.0.6
+  Optimized build.
+BOLT revision 6009708b4367171ccdbf4b5905cb6a803753fe18
+
+  Registered Targets:
+    x86-64     - 64-bit X86: EM64T and AMD64
+";
+        assert_eq!(parse_llvm_version(output), Some(17));
+    }
+
+    #[test]
+    fn parse_version_with_suffix() {
+        assert_eq!(
+            parse_llvm_version(
+                "LLVM (http://llvm.org/):\n  LLVM version 22.1.2-rust-1.95.0-stable"
+            ),
+            Some(22)
         );
+        assert_eq!(parse_llvm_version("Debian LLVM version 14.0.6"), Some(14));
+    }
 
-        Ok(PgoEnv { llvm_profdata })
-    } else {
-        Err(anyhow::anyhow!("Could not find `llvm-profdata`"))
+    #[test]
+    fn parse_version_missing() {
+        assert_eq!(parse_llvm_version("BOLT revision abc"), None);
+    }
+
+    #[test]
+    fn llvm_version_mismatch() {
+        let env = PgoEnv {
+            llvm_profdata: PathBuf::from("llvm-profdata"),
+            version: Some(14),
+        };
+        assert!(check_llvm_version(&env, Some(14)).is_ok());
+        assert!(check_llvm_version(&env, None).is_ok());
+        assert!(
+            check_llvm_version(&env, Some(22))
+                .unwrap_err()
+                .contains("uses LLVM 14, but `rustc` uses LLVM 22")
+        );
     }
 }
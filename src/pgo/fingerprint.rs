@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::build::BuiltArtifact;
+use crate::cli::cli_format_path;
+use crate::utils::file::hash_file;
+use crate::workspace::CargoContext;
+
+/// Name of the file with the fingerprint, stored in the PGO profile directory.
+const FINGERPRINT_FILE_NAME: &str = "fingerprint.json";
+
+/// Describes the state of the workspace and of the toolchain at the time when PGO-instrumented
+/// binaries were built, so that it can be checked whether the gathered profiles are still fresh.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileFingerprint {
+    pub rustc_version: String,
+    pub git_commit: Option<String>,
+    /// Versions of the workspace packages.
+    pub crates: BTreeMap<String, String>,
+    /// Hash of the Rust sources and manifests of the workspace packages.
+    #[serde(default)]
+    pub sources: Option<String>,
+    /// Hashes of the instrumented binaries.
+    pub binaries: BTreeMap<String, String>,
+}
+
+impl ProfileFingerprint {
+    /// Creates a fingerprint of the current workspace and toolchain, along with hashes of the
+    /// given `artifacts`.
+    pub(crate) fn current(ctx: &CargoContext, artifacts: &[BuiltArtifact]) -> anyhow::Result<Self> {
+        let binaries = artifacts
+            .iter()
+            .map(|artifact| Ok((artifact.name.clone(), hash_file(&artifact.path)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(ProfileFingerprint {
+            rustc_version: rustc_version::version_meta()?.short_version_string,
+            git_commit: ctx.git_commit(),
+            crates: ctx
+                .packages()
+                .iter()
+                .map(|package| (package.name.clone(), package.version.clone()))
+                .collect(),
+            sources: Some(hash_workspace_sources(ctx)?),
+            binaries,
+        })
+    }
+
+    /// Loads a fingerprint stored in the PGO profile directory, if there is any.
+    pub(crate) fn load(pgo_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = fingerprint_path(pgo_dir);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        let fingerprint = serde_json::from_str(&content).map_err(|error| {
+            anyhow!(
+                "Cannot parse profile fingerprint {}: {}",
+                cli_format_path(path.display()),
+                error
+            )
+        })?;
+        Ok(Some(fingerprint))
+    }
+
+    pub(crate) fn store(&self, pgo_dir: &Path) -> anyhow::Result<()> {
+        std::fs::write(
+            fingerprint_path(pgo_dir),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Returns a description of differences between the workspace and toolchain recorded in this
+    /// fingerprint and in the `current` fingerprint. Binaries are not compared, because they are
+    /// not available before the optimized build; changed sources are detected by their hash.
+    pub(crate) fn drift(&self, current: &ProfileFingerprint) -> Vec<String> {
+        let mut changes = vec![];
+        if self.rustc_version != current.rustc_version {
+            changes.push(format!(
+                "compiler changed from `{}` to `{}`",
+                self.rustc_version, current.rustc_version
+            ));
+        }
+        if let (Some(recorded), Some(current)) = (&self.git_commit, &current.git_commit) {
+            if recorded != current {
+                changes.push(format!("git commit changed from {recorded} to {current}"));
+            }
+        }
+        if let (Some(recorded), Some(current)) = (&self.sources, &current.sources) {
+            if recorded != current {
+                changes.push("sources of the workspace have changed".to_string());
+            }
+        }
+        for (name, version) in &self.crates {
+            match current.crates.get(name) {
+                Some(current_version) if current_version != version => changes.push(format!(
+                    "crate `{name}` changed from version {version} to {current_version}"
+                )),
+                Some(_) => {}
+                None => changes.push(format!("crate `{name}` was removed from the workspace")),
+            }
+        }
+        for name in current.crates.keys() {
+            if !self.crates.contains_key(name) {
+                changes.push(format!("crate `{name}` was added to the workspace"));
+            }
+        }
+        changes
+    }
+
+    /// Returns names of binaries whose hash differs between the two fingerprints.
+    pub(crate) fn changed_binaries(&self, other: &ProfileFingerprint) -> Vec<String> {
+        self.binaries
+            .iter()
+            .filter(|(name, hash)| {
+                other
+                    .binaries
+                    .get(*name)
+                    .is_some_and(|other| other != *hash)
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// Hashes the Rust source files and manifests of all workspace packages, so that uncommitted
+/// changes of the sources are also detected. The target directory and hidden directories
+/// are skipped.
+fn hash_workspace_sources(ctx: &CargoContext) -> anyhow::Result<String> {
+    let mut roots: Vec<&Path> = ctx
+        .packages()
+        .iter()
+        .map(|package| package.root.as_path())
+        .collect();
+    roots.push(ctx.workspace_root());
+    roots.sort();
+    roots.dedup();
+    // Packages nested in other packages (or in the workspace root) are hashed with their parent
+    let roots: Vec<&Path> = roots
+        .iter()
+        .filter(|root| {
+            !roots
+                .iter()
+                .any(|other| other != *root && root.starts_with(other))
+        })
+        .copied()
+        .collect();
+
+    let mut hasher = blake3::Hasher::new();
+    for root in roots {
+        let walker = WalkDir::new(root)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || !(entry.file_type().is_dir()
+                        && (entry.path() == ctx.target_directory()
+                            || entry.file_name().to_string_lossy().starts_with('.')))
+            });
+        for entry in walker {
+            let entry = entry?;
+            if !entry.file_type().is_file() || !is_source_file(entry.path()) {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update(hash_file(entry.path())?.as_bytes());
+        }
+    }
+    Ok(hasher.finalize().to_string())
+}
+
+fn is_source_file(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some("Cargo.toml" | "Cargo.lock") => true,
+        _ => path.extension().is_some_and(|extension| extension == "rs"),
+    }
+}
+
+fn fingerprint_path(pgo_dir: &Path) -> PathBuf {
+    pgo_dir.join(FINGERPRINT_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::pgo::fingerprint::{ProfileFingerprint, is_source_file};
+
+    fn fingerprint(rustc: &str, commit: &str, crates: &[(&str, &str)]) -> ProfileFingerprint {
+        ProfileFingerprint {
+            rustc_version: rustc.to_string(),
+            git_commit: Some(commit.to_string()),
+            crates: crates
+                .iter()
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+            sources: Some("sources".to_string()),
+            binaries: Default::default(),
+        }
+    }
+
+    #[test]
+    fn no_drift() {
+        let recorded = fingerprint("rustc 1.80.0", "abc", &[("foo", "0.1.0")]);
+        assert!(recorded.drift(&recorded).is_empty());
+    }
+
+    #[test]
+    fn drift_toolchain_and_commit() {
+        let recorded = fingerprint("rustc 1.80.0", "abc", &[("foo", "0.1.0")]);
+        let current = fingerprint("rustc 1.81.0", "def", &[("foo", "0.1.0")]);
+        let drift = recorded.drift(&current);
+        assert_eq!(drift.len(), 2);
+        assert!(drift[0].contains("rustc 1.81.0"));
+        assert!(drift[1].contains("def"));
+    }
+
+    #[test]
+    fn drift_sources() {
+        let recorded = fingerprint("rustc", "abc", &[("foo", "0.1.0")]);
+        let mut current = recorded.clone();
+        current.sources = Some("changed".to_string());
+        assert_eq!(
+            recorded.drift(&current),
+            vec!["sources of the workspace have changed"]
+        );
+
+        // Fingerprints created by older versions do not contain the hash
+        current.sources = None;
+        assert!(recorded.drift(&current).is_empty());
+    }
+
+    #[test]
+    fn source_files() {
+        assert!(is_source_file(Path::new("src/main.rs")));
+        assert!(is_source_file(Path::new("Cargo.toml")));
+        assert!(!is_source_file(Path::new("README.md")));
+    }
+
+    #[test]
+    fn drift_crates() {
+        let recorded = fingerprint("rustc", "abc", &[("foo", "0.1.0"), ("bar", "1.0.0")]);
+        let current = fingerprint("rustc", "abc", &[("foo", "0.2.0"), ("baz", "1.0.0")]);
+        assert_eq!(
+            recorded.drift(&current),
+            vec![
+                "crate `bar` was removed from the workspace",
+                "crate `foo` changed from version 0.1.0 to 0.2.0",
+                "crate `baz` was added to the workspace"
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_binaries() {
+        let mut recorded = ProfileFingerprint::default();
+        recorded.binaries.insert("foo".to_string(), "a".to_string());
+        recorded.binaries.insert("bar".to_string(), "b".to_string());
+        let mut current = ProfileFingerprint::default();
+        current.binaries.insert("foo".to_string(), "c".to_string());
+        current.binaries.insert("bar".to_string(), "b".to_string());
+        assert_eq!(recorded.changed_binaries(&current), vec!["foo"]);
+    }
+}
@@ -1,11 +1,14 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::build::{
-    CargoCommand, cargo_command_with_rustflags, get_artifact_kind, handle_metadata_message,
+    BuiltArtifact, CargoCommand, cargo_command_with_rustflags, get_artifact_kind,
+    get_shared_library, handle_metadata_message,
 };
-use crate::clear_directory;
 use crate::cli::cli_format_path;
+use crate::output::{Event, Tool, emit};
+use crate::pgo::fingerprint::ProfileFingerprint;
 use crate::workspace::CargoContext;
+use crate::{clear_directory, ensure_directory};
 use cargo_metadata::Message;
 use colored::Colorize;
 
@@ -13,19 +16,23 @@ use colored::Colorize;
 pub struct PgoInstrumentArgs {
     /// Cargo command that will be used for PGO-instrumented compilation.
     #[clap(value_enum, default_value = "build")]
-    command: CargoCommand,
+    pub(crate) command: CargoCommand,
 
     /// Do not remove profiles that were gathered during previous runs.
     #[clap(long, action)]
-    keep_profiles: bool,
+    pub(crate) keep_profiles: bool,
 
     /// Override the PGO profile path.
     #[clap(long)]
-    profiles_dir: Option<PathBuf>,
+    pub(crate) profiles_dir: Option<PathBuf>,
+
+    /// Do not print the output of Cargo to stdout. Only used by the library API.
+    #[clap(skip)]
+    pub(crate) quiet: bool,
 
     /// Additional arguments that will be passed to the executed `cargo` command.
     #[arg(last(true))]
-    cargo_args: Vec<String>,
+    pub(crate) cargo_args: Vec<String>,
 }
 
 impl PgoInstrumentArgs {
@@ -75,12 +82,22 @@ impl PgoInstrumentShortcutArgs {
             command,
             keep_profiles,
             profiles_dir,
+            quiet: false,
             cargo_args,
         }
     }
 }
 
-pub fn pgo_instrument(ctx: CargoContext, args: PgoInstrumentArgs) -> anyhow::Result<()> {
+pub fn pgo_instrument(ctx: &CargoContext, args: PgoInstrumentArgs) -> anyhow::Result<()> {
+    instrument(ctx, args)?;
+    Ok(())
+}
+
+/// Performs a PGO-instrumented build and returns the executables that were built by it.
+pub(crate) fn instrument(
+    ctx: &CargoContext,
+    args: PgoInstrumentArgs,
+) -> anyhow::Result<Vec<BuiltArtifact>> {
     let pgo_dir = ctx.get_pgo_directory()?;
 
     if !args.keep_profiles {
@@ -92,15 +109,27 @@ pub fn pgo_instrument(ctx: CargoContext, args: PgoInstrumentArgs) -> anyhow::Res
         "PGO profiles will be stored into {}.",
         cli_format_path(pgo_dir.display())
     );
+    emit(Event::ProfileDir {
+        tool: Tool::Pgo,
+        path: &pgo_dir,
+    });
 
     let flags = vec![format!("-Cprofile-generate={}", pgo_dir.display())];
-    let mut cargo = cargo_command_with_rustflags(args.command, flags, args.cargo_args)?;
+    let mut cargo =
+        cargo_command_with_rustflags(args.command, flags, ctx.resolve_cargo_args(args.cargo_args))?;
 
+    let mut artifacts = vec![];
     for message in cargo.messages() {
         let message = message?;
         match message {
             Message::CompilerArtifact(artifact) => {
                 if let Some(ref executable) = artifact.executable {
+                    emit(Event::Instrumented {
+                        tool: Tool::Pgo,
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: executable.as_std_path(),
+                    });
                     if let CargoCommand::Build = args.command {
                         log::info!(
                             "PGO-instrumented {} {} built successfully.",
@@ -120,9 +149,34 @@ with the following environment variable to have more precise profiles:\n{}",
                             .blue()
                         );
                     }
+                    artifacts.push(BuiltArtifact {
+                        name: artifact.target.name.clone(),
+                        path: executable.clone().into_std_path_buf(),
+                    });
+                } else if let Some(library) = get_shared_library(&artifact) {
+                    emit(Event::Instrumented {
+                        tool: Tool::Pgo,
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: library.as_std_path(),
+                    });
+                    log::info!(
+                        "PGO-instrumented {} {} built successfully at {}.",
+                        get_artifact_kind(&artifact).yellow(),
+                        artifact.target.name.blue(),
+                        cli_format_path(library)
+                    );
+                    log::info!(
+                        "Now load it into your application and run your workload. The profiles \
+will be stored into {} when the application exits.",
+                        cli_format_path(pgo_dir.display())
+                    );
                 }
             }
             Message::BuildFinished(res) => {
+                emit(Event::BuildFinished {
+                    success: res.success,
+                });
                 if res.success {
                     log::info!(
                         "PGO instrumentation build finished {}.",
@@ -132,11 +186,39 @@ with the following environment variable to have more precise profiles:\n{}",
                     log::error!("PGO instrumentation build has {}.", "failed".red());
                 }
             }
+            _ if args.quiet => {}
             _ => handle_metadata_message(message),
         }
     }
 
     cargo.check_status()?;
 
-    Ok(())
+    store_fingerprint(ctx, &pgo_dir, &artifacts, args.keep_profiles)?;
+
+    Ok(artifacts)
+}
+
+/// Records the state of the workspace and toolchain next to the profiles, so that
+/// `cargo pgo optimize` can detect stale profiles.
+fn store_fingerprint(
+    ctx: &CargoContext,
+    pgo_dir: &Path,
+    artifacts: &[BuiltArtifact],
+    keep_profiles: bool,
+) -> anyhow::Result<()> {
+    let fingerprint = ProfileFingerprint::current(ctx, artifacts)?;
+    if keep_profiles {
+        if let Some(previous) = ProfileFingerprint::load(pgo_dir)? {
+            let changed = previous.changed_binaries(&fingerprint);
+            if !changed.is_empty() {
+                log::warn!(
+                    "Binaries {} have changed since the kept PGO profiles were gathered. \
+The profiles from the previous binaries might be stale.",
+                    changed.join(", ")
+                );
+            }
+        }
+    }
+    ensure_directory(pgo_dir)?;
+    fingerprint.store(pgo_dir)
 }
@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::cli::cli_format_path;
+use crate::utils::str::pluralize;
+
+/// Format of the missing profile report.
+#[derive(Debug, Copy, Clone, clap::ValueEnum)]
+pub enum MissingProfileReportFormat {
+    Text,
+    Json,
+}
+
+/// A function for which no PGO profile data was found during the optimized build.
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingFunction {
+    /// Demangled name of the function.
+    pub function: String,
+    /// LLVM module (codegen unit) in which the function was compiled.
+    pub llvm_module: String,
+}
+
+/// Collects functions without PGO profile data reported by `rustc`.
+#[derive(Debug, Default)]
+pub struct MissingProfiles {
+    functions: Vec<MissingFunction>,
+}
+
+impl MissingProfiles {
+    pub fn add(&mut self, function: MissingFunction) {
+        self.functions.push(function);
+    }
+
+    /// Groups the missing functions by crate and module.
+    pub fn report(&self) -> MissingProfileReport {
+        let mut crates: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+        for function in &self.functions {
+            let location = FunctionLocation::from_function(&function.function)
+                .unwrap_or_else(|| FunctionLocation::from_llvm_module(&function.llvm_module));
+            crates
+                .entry(location.krate)
+                .or_default()
+                .entry(location.module)
+                .or_default()
+                .push(function.function.clone());
+        }
+
+        let mut crates: Vec<CrateReport> = crates
+            .into_iter()
+            .map(|(name, modules)| {
+                let mut modules: Vec<ModuleReport> = modules
+                    .into_iter()
+                    .map(|(name, mut functions)| {
+                        functions.sort();
+                        functions.dedup();
+                        ModuleReport {
+                            name,
+                            count: functions.len(),
+                            functions,
+                        }
+                    })
+                    .collect();
+                modules.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+                CrateReport {
+                    name,
+                    count: modules.iter().map(|module| module.count).sum(),
+                    modules,
+                }
+            })
+            .collect();
+        crates.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+
+        MissingProfileReport {
+            total: crates.iter().map(|krate| krate.count).sum(),
+            crates,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingProfileReport {
+    /// Total number of (distinct) functions without profile data.
+    pub total: usize,
+    pub crates: Vec<CrateReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrateReport {
+    pub name: String,
+    pub count: usize,
+    pub modules: Vec<ModuleReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModuleReport {
+    pub name: String,
+    pub count: usize,
+    pub functions: Vec<String>,
+}
+
+impl MissingProfileReport {
+    /// Returns up to `count` modules with the most functions without profile data.
+    pub fn top_modules(&self, count: usize) -> Vec<&ModuleReport> {
+        let mut modules: Vec<&ModuleReport> = self
+            .crates
+            .iter()
+            .flat_map(|krate| krate.modules.iter())
+            .collect();
+        modules.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        modules.truncate(count);
+        modules
+    }
+
+    pub fn write(&self, path: &Path, format: MissingProfileReportFormat) -> anyhow::Result<()> {
+        let content = match format {
+            MissingProfileReportFormat::Text => self.to_text(),
+            MissingProfileReportFormat::Json => serde_json::to_string_pretty(self)?,
+        };
+        std::fs::write(path, content).map_err(|error| {
+            anyhow::anyhow!(
+                "Cannot write missing profile report to {}: {}",
+                path.display(),
+                error
+            )
+        })?;
+        log::info!(
+            "Missing profile report was written to {}.",
+            cli_format_path(path.display())
+        );
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        let mut output = String::new();
+        writeln!(
+            output,
+            "PGO profile data was not found for {} {}.",
+            self.total,
+            pluralize("function", self.total)
+        )
+        .unwrap();
+        for krate in &self.crates {
+            writeln!(
+                output,
+                "\n{} ({} {})",
+                krate.name,
+                krate.count,
+                pluralize("function", krate.count)
+            )
+            .unwrap();
+            for module in &krate.modules {
+                writeln!(
+                    output,
+                    "  {} ({} {})",
+                    module.name,
+                    module.count,
+                    pluralize("function", module.count)
+                )
+                .unwrap();
+                for function in &module.functions {
+                    writeln!(output, "    {function}").unwrap();
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Prints a short summary of modules with the most functions without profile data.
+/// If the total number of functions is known, the ratio of functions without profile data is
+/// also printed.
+pub fn print_missing_profile_summary(
+    report: &MissingProfileReport,
+    count: usize,
+    total_functions: Option<usize>,
+) {
+    let mut summary = String::new();
+    for module in report.top_modules(count) {
+        write!(
+            summary,
+            "\n  {}: {} {}",
+            module.name.blue(),
+            module.count,
+            pluralize("function", module.count)
+        )
+        .unwrap();
+    }
+    let ratio = match total_functions {
+        Some(total) if total > 0 => format!(
+            " ({:.1}% of all functions)",
+            report.total as f64 / total as f64 * 100.0
+        ),
+        _ => String::new(),
+    };
+    log::warn!(
+        "PGO profile data was not found for {} {}{}. Modules with the most affected functions:{}",
+        report.total,
+        pluralize("function", report.total),
+        ratio,
+        summary
+    );
+}
+
+/// Returns the name of the crate in which a function with the given demangled name is defined,
+/// if it can be determined.
+pub(crate) fn function_crate(function: &str) -> Option<String> {
+    FunctionLocation::from_function(function).map(|location| location.krate)
+}
+
+struct FunctionLocation {
+    krate: String,
+    module: String,
+}
+
+impl FunctionLocation {
+    /// Finds the crate and module of a demangled function path, e.g. `foo::bar::baz` or
+    /// `<foo::bar::Baz as core::fmt::Debug>::fmt`.
+    fn from_function(function: &str) -> Option<Self> {
+        let segments = split_path(function);
+        let (_, parents) = segments.split_last()?;
+        match parents {
+            [] => None,
+            // Trait method `<Type as Trait>::method`, find the location of the type instead
+            [first] if first.starts_with('<') && first.ends_with('>') => {
+                let inner = &first[1..first.len() - 1];
+                let (self_type, trait_path) = match inner.split_once(" as ") {
+                    Some((self_type, trait_path)) => (self_type, Some(trait_path)),
+                    None => (inner, None),
+                };
+                let self_type = self_type
+                    .trim_start_matches(['&', '*'])
+                    .trim_start_matches("mut ")
+                    .trim_start_matches("const ")
+                    .trim_start_matches("dyn ");
+                Self::from_type(self_type).or_else(|| trait_path.and_then(Self::from_type))
+            }
+            _ => Some(FunctionLocation {
+                krate: parents[0].to_string(),
+                module: parents.join("::"),
+            }),
+        }
+    }
+
+    /// Finds the crate and module of a type path, e.g. `foo::bar::Baz<T>`.
+    fn from_type(path: &str) -> Option<Self> {
+        let segments = split_path(path);
+        let (_, parents) = segments.split_last()?;
+        if parents.is_empty() || parents[0].starts_with(['<', '[', '(']) {
+            return None;
+        }
+        Some(FunctionLocation {
+            krate: parents[0].to_string(),
+            module: parents.join("::"),
+        })
+    }
+
+    /// Uses the crate name from a LLVM module name, e.g. `foo.a1b2c3-cgu.0`.
+    fn from_llvm_module(module: &str) -> Self {
+        let krate = module.split('.').next().unwrap_or(module).to_string();
+        FunctionLocation {
+            module: krate.clone(),
+            krate,
+        }
+    }
+}
+
+/// Splits a path by `::`, ignoring separators nested in generic arguments.
+fn split_path(path: &str) -> Vec<&str> {
+    let mut segments = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let bytes = path.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'<' | b'[' | b'(' => depth += 1,
+            b'>' | b']' | b')' => depth -= 1,
+            b':' if depth == 0 && bytes.get(index + 1) == Some(&b':') => {
+                segments.push(&path[start..index]);
+                index += 2;
+                start = index;
+                continue;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgo::missing::{FunctionLocation, MissingFunction, MissingProfiles, split_path};
+
+    fn location(function: &str) -> Option<(String, String)> {
+        FunctionLocation::from_function(function).map(|location| (location.krate, location.module))
+    }
+
+    fn location_ref(krate: &str, module: &str) -> Option<(String, String)> {
+        Some((krate.to_string(), module.to_string()))
+    }
+
+    #[test]
+    fn split_path_generics() {
+        assert_eq!(
+            split_path("foo::bar<a::B>::baz"),
+            vec!["foo", "bar<a::B>", "baz"]
+        );
+    }
+
+    #[test]
+    fn location_plain_function() {
+        assert_eq!(location("foo::bar::baz"), location_ref("foo", "foo::bar"));
+    }
+
+    #[test]
+    fn location_crate_function() {
+        assert_eq!(location("foo::main"), location_ref("foo", "foo"));
+    }
+
+    #[test]
+    fn location_without_path() {
+        assert_eq!(location("main"), None);
+    }
+
+    #[test]
+    fn location_trait_impl() {
+        assert_eq!(
+            location("<foo::bar::Baz as core::fmt::Debug>::fmt"),
+            location_ref("foo", "foo::bar")
+        );
+    }
+
+    #[test]
+    fn location_trait_impl_primitive() {
+        assert_eq!(
+            location("<u32 as foo::Trait>::method"),
+            location_ref("foo", "foo")
+        );
+    }
+
+    #[test]
+    fn location_inherent_method() {
+        assert_eq!(
+            location("foo::bar::Baz<T>::method"),
+            location_ref("foo", "foo::bar::Baz<T>")
+        );
+    }
+
+    #[test]
+    fn report_groups_functions() {
+        let mut profiles = MissingProfiles::default();
+        for function in [
+            "foo::a::x",
+            "foo::a::y",
+            "foo::b::z",
+            "bar::c::w",
+            "foo::a::x",
+        ] {
+            profiles.add(MissingFunction {
+                function: function.to_string(),
+                llvm_module: "foo.1234-cgu.0".to_string(),
+            });
+        }
+        let report = profiles.report();
+        assert_eq!(report.total, 4);
+        assert_eq!(report.crates[0].name, "foo");
+        assert_eq!(report.crates[0].count, 3);
+        assert_eq!(report.crates[0].modules[0].name, "foo::a");
+        assert_eq!(report.crates[1].name, "bar");
+        assert_eq!(report.top_modules(1)[0].name, "foo::a");
+    }
+
+    #[test]
+    fn report_llvm_module_fallback() {
+        let mut profiles = MissingProfiles::default();
+        profiles.add(MissingFunction {
+            function: "main".to_string(),
+            llvm_module: "foo.1234-cgu.0".to_string(),
+        });
+        assert_eq!(profiles.report().crates[0].name, "foo");
+    }
+}
@@ -1,8 +1,13 @@
 use colored::Colorize;
 
+pub mod cs;
 pub(crate) mod env;
+pub mod fingerprint;
 pub mod instrument;
+pub mod missing;
 pub mod optimize;
+pub mod profile;
+pub mod sample;
 
 pub fn llvm_profdata_install_hint() -> String {
     format!(
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::OnceLock;
@@ -11,28 +12,95 @@ use regex::Regex;
 use rustc_demangle::{Demangle, demangle};
 
 use crate::build::{
-    CargoCommand, cargo_command_with_rustflags, get_artifact_kind, handle_metadata_message,
+    BuiltArtifact, CargoCommand, cargo_command_with_rustflags, get_artifact_kind,
+    get_shared_library, handle_metadata_message,
 };
 use crate::cli::cli_format_path;
-use crate::pgo::env::{PgoEnv, find_pgo_env};
+use crate::ensure_directory;
+use crate::error::PgoError;
+use crate::output::{Event, OutputFormat, Tool, emit, output_format};
+use crate::pgo::cs::{cs_use_flags, merge_cs_profiles};
+use crate::pgo::env::{PgoEnv, check_llvm_version, find_pgo_env, rustc_llvm_version};
+use crate::pgo::fingerprint::ProfileFingerprint;
 use crate::pgo::llvm_profdata_install_hint;
+use crate::pgo::missing::{
+    MissingFunction, MissingProfileReport, MissingProfileReportFormat, MissingProfiles,
+    print_missing_profile_summary,
+};
+use crate::pgo::profile::{count_profile_functions, newest_file};
 use crate::utils::file::{gather_files_with_extension, hash_file, move_file};
 use crate::utils::str::pluralize;
 use crate::workspace::CargoContext;
 
+/// Number of modules shown in the summary of functions without profile data.
+const MISSING_PROFILE_SUMMARY_MODULES: usize = 5;
+
+/// Prefix of the names of profiles merged by `cargo-pgo`.
+const MERGED_PROFILE_PREFIX: &str = "merged-";
+
+/// Weights of PGO profiles, keyed by the name of a subdirectory of the PGO profile directory.
+/// All profiles stored (recursively) in the subdirectory are merged with the given weight.
+/// Profiles without a weight are merged with weight `1`.
+pub type ProfileWeights = BTreeMap<String, u32>;
+
 #[derive(clap::Parser, Debug)]
 pub struct PgoOptimizeArgs {
     /// Cargo command that will be used for PGO-optimized compilation.
     #[clap(value_enum, default_value = "build")]
-    command: CargoCommand,
+    pub(crate) command: CargoCommand,
 
     /// Override the PGO profile path.
     #[clap(long)]
-    profiles_dir: Option<PathBuf>,
+    pub(crate) profiles_dir: Option<PathBuf>,
+
+    /// Write a report of functions without PGO profile data, grouped by crate and module,
+    /// into the given file.
+    #[clap(long)]
+    pub(crate) missing_profile_report: Option<PathBuf>,
+
+    /// Format of the missing profile report.
+    #[clap(long, value_enum, default_value = "text")]
+    pub(crate) missing_profile_report_format: MissingProfileReportFormat,
+
+    /// Weight of profiles stored in a subdirectory of the PGO profile directory, in the form
+    /// `<subdirectory>=<weight>`, e.g. `--profile-weight api=4`. Can be passed multiple times.
+    /// Profiles without a weight have the weight `1`.
+    #[clap(long = "profile-weight", value_parser = parse_profile_weight)]
+    pub(crate) profile_weights: Vec<(String, u32)>,
+
+    /// Use the given merged `.profdata` file instead of the profiles from the PGO profile
+    /// directory.
+    #[clap(long, conflicts_with = "profile_weights")]
+    pub(crate) profile_file: Option<PathBuf>,
+
+    /// Fail if the workspace crates, the git commit or the compiler have changed since the PGO
+    /// profiles were gathered, instead of only printing a warning.
+    #[clap(long, conflicts_with = "profile_file")]
+    pub(crate) require_fresh_profiles: bool,
+
+    /// Merge the PGO profiles with context-sensitive profiles gathered from a binary built by
+    /// `cargo pgo cs-build` and use them for a build with LTO. If `--profile-file` is used, it has
+    /// to contain both PGO and context-sensitive profiles.
+    #[clap(long)]
+    pub(crate) cs: bool,
+
+    /// Linker used to link the binary with LTO when `--cs` is used. It has to be a `clang` binary
+    /// that uses the same LLVM version as `rustc`.
+    #[clap(long, default_value = "clang", requires = "cs")]
+    pub(crate) cs_linker: String,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    pub(crate) allow_llvm_mismatch: bool,
+
+    /// Do not print the output of Cargo to stdout. Only used by the library API.
+    #[clap(skip)]
+    pub(crate) quiet: bool,
 
     /// Additional arguments that will be passed to the executed `cargo` command.
     #[arg(last(true))]
-    cargo_args: Vec<String>,
+    pub(crate) cargo_args: Vec<String>,
 }
 
 impl PgoOptimizeArgs {
@@ -45,36 +113,248 @@ impl PgoOptimizeArgs {
     }
 }
 
+pub(crate) fn parse_profile_weight(value: &str) -> Result<(String, u32), String> {
+    let (directory, weight) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<subdirectory>=<weight>`, got `{value}`"))?;
+    let weight = weight
+        .parse::<u32>()
+        .map_err(|error| format!("invalid weight `{weight}`: {error}"))?;
+    if directory.is_empty() {
+        return Err("subdirectory cannot be empty".to_string());
+    }
+    Ok((directory.to_string(), weight))
+}
+
 /// Merges PGO profiles and creates RUSTFLAGS that use them.
 pub fn prepare_pgo_optimization_flags(
     pgo_env: &PgoEnv,
     pgo_dir: &Path,
+    weights: &ProfileWeights,
 ) -> anyhow::Result<Vec<String>> {
-    let stats = gather_pgo_profile_stats(pgo_dir)?;
+    let profile = prepare_pgo_profile(pgo_env, pgo_dir, weights)?;
+    Ok(pgo_use_flags(&profile))
+}
+
+/// Merges PGO profiles from `pgo_dir` and returns the path to the merged profile.
+pub(crate) fn prepare_pgo_profile(
+    pgo_env: &PgoEnv,
+    pgo_dir: &Path,
+    weights: &ProfileWeights,
+) -> anyhow::Result<PathBuf> {
+    Ok(prepare_pgo_profile_with_stats(pgo_env, pgo_dir, weights)?.0)
+}
+
+/// Merges PGO profiles from `pgo_dir` and returns the path to the merged profile, along with
+/// statistics of the merged profile files.
+fn prepare_pgo_profile_with_stats(
+    pgo_env: &PgoEnv,
+    pgo_dir: &Path,
+    weights: &ProfileWeights,
+) -> anyhow::Result<(PathBuf, ProfileStats)> {
+    let stats = gather_pgo_profile_stats(pgo_dir, weights)?;
+
+    // If there are no profiles to merge, but the directory contains a profile merged before,
+    // use it directly.
+    if stats.file_count() == 0 {
+        let merged = gather_files_with_extension(pgo_dir, "profdata")
+            .into_iter()
+            .filter(|file| is_merged_profile(file))
+            .collect();
+        if let Some((profile, _)) = newest_file(merged)? {
+            log::info!(
+                "No unmerged PGO profiles were found, using already merged profile {}.",
+                cli_format_path(profile.display())
+            );
+            return Ok((profile, stats));
+        }
+    }
 
     print_pgo_profile_stats(&stats, pgo_dir)?;
 
-    let target_file = merge_profiles(pgo_env, &stats, pgo_dir)?;
+    let profile = merge_profiles(pgo_env, &stats, pgo_dir)?;
+    Ok((profile, stats))
+}
+
+/// Copies the given merged `profile` into `pgo_dir` under a name containing its hash and returns
+/// the path to the copy.
+fn prepare_profile_file(profile: &Path, pgo_dir: &Path) -> anyhow::Result<PathBuf> {
+    if !profile.is_file() {
+        return Err(anyhow!(
+            "Profile file {} does not exist",
+            cli_format_path(profile.display())
+        ));
+    }
+    ensure_directory(pgo_dir)?;
 
-    Ok(vec![
-        format!("-Cprofile-use={}", target_file.display()),
+    let target_profile = hashed_profile_path(profile, pgo_dir)?;
+    if !target_profile.exists() {
+        std::fs::copy(profile, &target_profile)?;
+    }
+
+    log::info!(
+        "Using PGO profile {} with size {} (copied to {}).",
+        cli_format_path(profile.display()),
+        format_size(std::fs::metadata(profile)?.len(), BINARY).yellow(),
+        cli_format_path(target_profile.display())
+    );
+    Ok(target_profile)
+}
+
+fn pgo_use_flags(profile: &Path) -> Vec<String> {
+    vec![
+        format!("-Cprofile-use={}", profile.display()),
         "-Cllvm-args=-pgo-warn-missing-function".to_string(),
-    ])
+    ]
 }
 
-pub fn pgo_optimize(ctx: CargoContext, args: PgoOptimizeArgs) -> anyhow::Result<()> {
+/// Compares the fingerprint recorded during the instrumented build with the current state of
+/// the workspace and toolchain.
+fn check_profile_freshness(
+    ctx: &CargoContext,
+    pgo_dir: &Path,
+    require_fresh_profiles: bool,
+) -> anyhow::Result<()> {
+    let Some(recorded) = ProfileFingerprint::load(pgo_dir)? else {
+        if require_fresh_profiles {
+            return Err(anyhow!(
+                "Cannot check if the PGO profiles are fresh, because no fingerprint was found at {}. \
+Build the instrumented binary using `cargo pgo instrument` first.",
+                cli_format_path(pgo_dir.display())
+            ));
+        }
+        log::debug!("No profile fingerprint was found, profile freshness will not be checked.");
+        return Ok(());
+    };
+
+    let current = ProfileFingerprint::current(ctx, &[])?;
+    let drift = recorded.drift(&current);
+    if drift.is_empty() {
+        return Ok(());
+    }
+
+    let mut message =
+        "The workspace or the toolchain has changed since the PGO profiles were gathered:"
+            .to_string();
+    for change in &drift {
+        message.push_str(&format!("\n  - {change}"));
+    }
+    if require_fresh_profiles {
+        Err(anyhow!(
+            "{message}\nRegenerate the profiles using `cargo pgo instrument`."
+        ))
+    } else {
+        log::warn!(
+            "{message}\nThe profiles might be stale, which makes the optimization less effective. \
+Regenerate them using `cargo pgo instrument`."
+        );
+        Ok(())
+    }
+}
+
+/// Merged PGO profile that was used for an optimized build.
+#[derive(Debug, Clone)]
+pub struct MergedProfile {
+    /// Path to the merged profile.
+    pub path: PathBuf,
+    /// Hash of the content of the merged profile.
+    pub hash: String,
+    /// Number of profile files that were merged into the profile. It is zero if an already merged
+    /// profile was used.
+    pub file_count: usize,
+    /// Total size of the profile files that were merged into the profile, in bytes.
+    pub total_size: u64,
+    /// Weights of the subdirectories of the PGO profile directory used for merging.
+    pub weights: ProfileWeights,
+}
+
+/// Result of a PGO-optimized build.
+#[derive(Debug)]
+pub struct PgoOptimizeOutput {
+    /// Optimized executables and shared libraries.
+    pub artifacts: Vec<BuiltArtifact>,
+    /// Merged profile that was used for the optimization.
+    pub profile: MergedProfile,
+    /// Functions that did not have any profile data during the optimized build.
+    pub missing_profiles: MissingProfileReport,
+    /// Total number of functions in the build, only computed if some functions were missing
+    /// profile data.
+    pub total_functions: Option<usize>,
+}
+
+pub fn pgo_optimize(ctx: &CargoContext, args: PgoOptimizeArgs) -> anyhow::Result<()> {
+    optimize(ctx, args)?;
+    Ok(())
+}
+
+/// Performs a PGO-optimized build and returns the optimized artifacts and the used profile.
+pub(crate) fn optimize(
+    ctx: &CargoContext,
+    args: PgoOptimizeArgs,
+) -> anyhow::Result<PgoOptimizeOutput> {
     let pgo_dir = ctx.get_pgo_directory()?;
-    let pgo_env = get_pgo_env()?;
 
-    let flags = prepare_pgo_optimization_flags(&pgo_env, &pgo_dir)?;
+    let (profile, stats) = match args.profile_file {
+        Some(profile) => (
+            prepare_profile_file(&profile, &pgo_dir)?,
+            ProfileStats::default(),
+        ),
+        None => {
+            check_profile_freshness(ctx, &pgo_dir, args.require_fresh_profiles)?;
+            let pgo_env = find_checked_pgo_env(args.allow_llvm_mismatch)?;
+            let weights = ctx.resolve_profile_weights(args.profile_weights);
+            let (profile, stats) = prepare_pgo_profile_with_stats(&pgo_env, &pgo_dir, &weights)?;
+            let profile = if args.cs {
+                merge_cs_profiles(&pgo_env, &profile, &ctx.get_cs_pgo_directory()?)?
+            } else {
+                profile
+            };
+            (profile, stats)
+        }
+    };
+    let merged_profile = MergedProfile {
+        hash: hash_file(&profile)?,
+        path: profile.clone(),
+        file_count: stats.file_count(),
+        total_size: stats.total_size,
+        weights: stats.weights,
+    };
+    emit(Event::MergedProfile {
+        tool: Tool::Pgo,
+        path: &merged_profile.path,
+        hash: &merged_profile.hash,
+    });
+    let flags = if args.cs {
+        cs_use_flags(&profile, &args.cs_linker)
+    } else {
+        pgo_use_flags(&profile)
+    };
 
-    let mut cargo = cargo_command_with_rustflags(args.command, flags, args.cargo_args)?;
+    let mut cargo =
+        cargo_command_with_rustflags(args.command, flags, ctx.resolve_cargo_args(args.cargo_args))?;
 
-    let mut counter = MissingProfileCounter::default();
+    let mut artifacts = vec![];
+    let mut missing_profiles = MissingProfiles::default();
     for message in cargo.messages() {
         let message = message?;
         match message {
             Message::CompilerArtifact(artifact) => {
+                let optimized = match artifact.executable {
+                    Some(ref executable) => Some(executable),
+                    None => get_shared_library(&artifact),
+                };
+                if let Some(path) = optimized {
+                    emit(Event::Optimized {
+                        tool: Tool::Pgo,
+                        target: &artifact.target.name,
+                        kind: get_artifact_kind(&artifact),
+                        path: path.as_std_path(),
+                    });
+                    artifacts.push(BuiltArtifact {
+                        name: artifact.target.name.clone(),
+                        path: path.clone().into_std_path_buf(),
+                    });
+                }
                 if let CargoCommand::Build = args.command {
                     if artifact.executable.is_some() {
                         log::info!(
@@ -86,7 +366,12 @@ pub fn pgo_optimize(ctx: CargoContext, args: PgoOptimizeArgs) -> anyhow::Result<
                 }
             }
             Message::BuildFinished(res) => {
-                if res.success {
+                emit(Event::BuildFinished {
+                    success: res.success,
+                });
+                if args.quiet || output_format() == OutputFormat::Json {
+                    // Keep stdout reserved for the JSON events
+                } else if res.success {
                     println!("{}", "PGO optimized build successfully finished.".green());
                 } else {
                     println!("{}", "PGO optimized build has failed.".red());
@@ -99,59 +384,149 @@ pub fn pgo_optimize(ctx: CargoContext, args: PgoOptimizeArgs) -> anyhow::Result<
                         profile.module,
                         profile.function
                     );
-                    counter.handle_missing_profile(profile);
-                } else {
+                    missing_profiles.add(MissingFunction {
+                        function: format!("{:#}", profile.function),
+                        llvm_module: profile.module.to_string(),
+                    });
+                } else if !args.quiet {
                     handle_metadata_message(Message::CompilerMessage(msg));
                 }
             }
+            _ if args.quiet => {}
             _ => handle_metadata_message(message),
         }
     }
 
     cargo.check_status()?;
 
-    if counter.counter > 0 {
-        log::warn!(
-            "PGO profile data was not found for {} {}.",
-            counter.counter,
-            pluralize("function", counter.counter)
-        );
+    let report = missing_profiles.report();
+    let mut total_functions = None;
+    if report.total > 0 {
+        // The functions in the profile and the functions without profile data are disjoint
+        total_functions = find_pgo_env()
+            .ok()
+            .and_then(|pgo_env| count_profile_functions(&pgo_env, &profile).ok())
+            .map(|count| count + report.total);
+        emit(Event::MissingProfiles {
+            count: report.total,
+            total_functions,
+        });
+        print_missing_profile_summary(&report, MISSING_PROFILE_SUMMARY_MODULES, total_functions);
+    }
+    if let Some(path) = args.missing_profile_report {
+        report.write(&path, args.missing_profile_report_format)?;
     }
 
-    Ok(())
+    Ok(PgoOptimizeOutput {
+        artifacts,
+        profile: merged_profile,
+        missing_profiles: report,
+        total_functions,
+    })
+}
+
+#[derive(Debug)]
+pub(crate) struct ProfileFile {
+    path: PathBuf,
+    /// Subdirectory of the PGO profile directory in which the file is stored, if any.
+    group: Option<String>,
+    weight: u32,
 }
 
 #[derive(Debug, Default)]
-struct ProfileStats {
-    profile_files: Vec<PathBuf>,
+pub(crate) struct ProfileStats {
+    profile_files: Vec<ProfileFile>,
     total_size: u64,
+    /// Weights of subdirectories that were used for this set of profiles.
+    weights: ProfileWeights,
 }
 
 impl ProfileStats {
     fn file_count(&self) -> usize {
         self.profile_files.len()
     }
+
+    /// Adds an already merged profile, which will be merged together with the other profiles.
+    pub(crate) fn add_merged_profile(&mut self, profile: &Path) -> anyhow::Result<()> {
+        self.total_size += std::fs::metadata(profile)?.len();
+        self.profile_files.push(ProfileFile {
+            path: profile.to_path_buf(),
+            group: None,
+            weight: 1,
+        });
+        Ok(())
+    }
 }
 
 /// Check if the directory with profiles is non-empty and prints basic profile statistics.
-fn gather_pgo_profile_stats(pgo_dir: &Path) -> anyhow::Result<ProfileStats> {
-    let mut stats = ProfileStats::default();
+/// Each profile is assigned a weight based on the subdirectory of `pgo_dir` in which it is stored.
+pub(crate) fn gather_pgo_profile_stats(
+    pgo_dir: &Path,
+    weights: &ProfileWeights,
+) -> anyhow::Result<ProfileStats> {
+    if let Some((directory, _)) = weights.iter().find(|(_, weight)| **weight == 0) {
+        return Err(anyhow!(
+            "Weight of profiles in `{directory}` has to be a positive number"
+        ));
+    }
 
-    for file in gather_files_with_extension(pgo_dir, "profraw") {
-        log::debug!("Found profile file {}.", file.display());
+    let mut stats = ProfileStats {
+        weights: weights.clone(),
+        ..Default::default()
+    };
+
+    // Already merged profiles (e.g. imported from a profile bundle) are merged together with the
+    // raw profiles. Profiles merged by `cargo-pgo` itself are skipped.
+    let merged_profiles = gather_files_with_extension(pgo_dir, "profdata")
+        .into_iter()
+        .filter(|file| !is_merged_profile(file));
+
+    for file in gather_files_with_extension(pgo_dir, "profraw")
+        .into_iter()
+        .chain(merged_profiles)
+    {
+        let group = profile_group(pgo_dir, &file);
+        let weight = group
+            .as_ref()
+            .and_then(|group| weights.get(group))
+            .copied()
+            .unwrap_or(1);
+        log::debug!("Found profile file {} (weight {weight}).", file.display());
         stats.total_size += std::fs::metadata(&file)?.len();
-        stats.profile_files.push(file);
+        stats.profile_files.push(ProfileFile {
+            path: file,
+            group,
+            weight,
+        });
     }
 
     Ok(stats)
 }
 
-fn print_pgo_profile_stats(stats: &ProfileStats, pgo_dir: &Path) -> anyhow::Result<()> {
+/// Checks if the file is a profile produced by [`merge_profiles`].
+pub(crate) fn is_merged_profile(file: &Path) -> bool {
+    file.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(MERGED_PROFILE_PREFIX))
+}
+
+/// Returns the name of the subdirectory of `pgo_dir` in which the profile `file` is stored.
+fn profile_group(pgo_dir: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(pgo_dir).ok()?;
+    let mut components = relative.components();
+    let first = components.next()?;
+    // The file is stored directly in `pgo_dir`
+    components.next()?;
+    Some(first.as_os_str().to_string_lossy().into_owned())
+}
+
+pub(crate) fn print_pgo_profile_stats(stats: &ProfileStats, pgo_dir: &Path) -> anyhow::Result<()> {
     if stats.file_count() == 0 {
-        return Err(anyhow!(
+        return Err(PgoError::NoProfiles(format!(
             "No profile files were found at {}. Did you execute your instrumented program?",
             cli_format_path(pgo_dir.display())
-        ));
+        ))
+        .into());
     }
 
     log::info!(
@@ -165,16 +540,70 @@ fn print_pgo_profile_stats(stats: &ProfileStats, pgo_dir: &Path) -> anyhow::Resu
         format_size(stats.total_size, BINARY).yellow(),
         cli_format_path(pgo_dir.display())
     );
+
+    if !stats.weights.is_empty() {
+        let mut groups: BTreeMap<Option<&str>, (u32, usize)> = BTreeMap::new();
+        for file in &stats.profile_files {
+            groups
+                .entry(file.group.as_deref())
+                .or_insert((file.weight, 0))
+                .1 += 1;
+        }
+
+        let mut summary = String::new();
+        for (group, (weight, count)) in &groups {
+            summary.push_str(&format!(
+                "\n  {}: weight {} ({} {})",
+                group.unwrap_or("<other>").blue(),
+                weight.to_string().yellow(),
+                count,
+                pluralize("file", *count)
+            ));
+        }
+        log::info!("PGO profiles will be merged with the following weights:{summary}");
+
+        for directory in stats.weights.keys() {
+            if !groups.contains_key(&Some(directory.as_str())) {
+                log::warn!(
+                    "A weight was specified for profiles in `{}`, but no profiles were found at {}.",
+                    directory,
+                    cli_format_path(pgo_dir.join(directory).display())
+                );
+            }
+        }
+    }
     Ok(())
 }
 
-pub fn get_pgo_env() -> anyhow::Result<PgoEnv> {
-    let pgo_env =
-        find_pgo_env().map_err(|error| anyhow!("{}\n{}", error, llvm_profdata_install_hint()))?;
+/// Finds `llvm-profdata` and checks that it uses the same major LLVM version as `rustc`.
+/// A mismatch is an error, unless `allow_llvm_mismatch` is set, in which case only a warning
+/// is printed.
+pub(crate) fn find_checked_pgo_env(allow_llvm_mismatch: bool) -> anyhow::Result<PgoEnv> {
+    let pgo_env = find_pgo_env().map_err(|error| PgoError::ToolNotFound {
+        tool: "llvm-profdata".to_string(),
+        message: format!("{}\n{}", error, llvm_profdata_install_hint()),
+    })?;
     log::debug!(
         "Found `llvm-profdata` at {}.",
         pgo_env.llvm_profdata.display()
     );
+    check_pgo_env(pgo_env, rustc_llvm_version(), allow_llvm_mismatch)
+}
+
+fn check_pgo_env(
+    pgo_env: PgoEnv,
+    rustc_llvm: Option<u32>,
+    allow_llvm_mismatch: bool,
+) -> anyhow::Result<PgoEnv> {
+    if let Err(message) = check_llvm_version(&pgo_env, rustc_llvm) {
+        if !allow_llvm_mismatch {
+            return Err(PgoError::LlvmVersionMismatch(format!(
+                "{message}\nPass `--allow-llvm-mismatch` to continue anyway."
+            ))
+            .into());
+        }
+        log::warn!("{message}");
+    }
     Ok(pgo_env)
 }
 
@@ -184,7 +613,7 @@ pub fn get_pgo_env() -> anyhow::Result<PgoEnv> {
 /// its given hash, so that if the contents of the profile change, the names of the profile will
 /// also change. This is done to properly invalidate the `rustc` compilation session
 /// (https://github.com/rust-lang/rust/issues/100397).
-fn merge_profiles(
+pub(crate) fn merge_profiles(
     pgo_env: &PgoEnv,
     stats: &ProfileStats,
     pgo_dir: &Path,
@@ -196,23 +625,27 @@ fn merge_profiles(
     let mut command = Command::new(&pgo_env.llvm_profdata);
     command.args(["merge", "-o", &profile_tmp_path.display().to_string()]);
     for file in &stats.profile_files {
-        command.arg(file);
+        if file.weight == 1 {
+            command.arg(&file.path);
+        } else {
+            command.arg(format!(
+                "--weighted-input={},{}",
+                file.weight,
+                file.path.display()
+            ));
+        }
     }
 
     let output = command.output()?;
     if !output.status.success() {
-        return Err(anyhow!(
+        return Err(PgoError::ProfileMerge(format!(
             "Failed to merge PGO profile(s): {}.",
             String::from_utf8_lossy(&output.stderr).red()
-        ));
+        ))
+        .into());
     }
 
-    // Calculate hash
-    let hash = hash_file(&profile_tmp_path)
-        .map_err(|error| anyhow::anyhow!("Cannot hash merged profile file: {:?}", error))?;
-
-    let profile_name = format!("merged-{hash}.profdata");
-    let target_profile = pgo_dir.join(profile_name);
+    let target_profile = hashed_profile_path(&profile_tmp_path, pgo_dir)?;
 
     // Move the merged profile to PGO profile directory
     move_file(&profile_tmp_path, &target_profile)?;
@@ -224,20 +657,28 @@ fn merge_profiles(
     Ok(target_profile)
 }
 
-struct PgoMissingProfile<'msg> {
-    module: &'msg str,
-    function: Demangle<'msg>,
+/// Emits an event describing the merged `profile` that will be used for optimization.
+pub(crate) fn emit_merged_profile(tool: Tool, profile: &Path) -> anyhow::Result<()> {
+    if output_format() == OutputFormat::Json {
+        emit(Event::MergedProfile {
+            tool,
+            path: profile,
+            hash: &hash_file(profile)?,
+        });
+    }
+    Ok(())
 }
 
-#[derive(Debug, Default)]
-struct MissingProfileCounter {
-    counter: usize,
+/// Returns a path of a merged profile in `pgo_dir` whose name contains the hash of `profile`.
+fn hashed_profile_path(profile: &Path, pgo_dir: &Path) -> anyhow::Result<PathBuf> {
+    let hash = hash_file(profile)
+        .map_err(|error| anyhow::anyhow!("Cannot hash merged profile file: {:?}", error))?;
+    Ok(pgo_dir.join(format!("{MERGED_PROFILE_PREFIX}{hash}.profdata")))
 }
 
-impl MissingProfileCounter {
-    fn handle_missing_profile(&mut self, _profile: PgoMissingProfile) {
-        self.counter += 1;
-    }
+struct PgoMissingProfile<'msg> {
+    module: &'msg str,
+    function: Demangle<'msg>,
 }
 
 fn get_pgo_missing_profile(message: &CompilerMessage) -> Option<PgoMissingProfile<'_>> {
@@ -258,3 +699,62 @@ fn get_pgo_missing_profile(message: &CompilerMessage) -> Option<PgoMissingProfil
         PgoMissingProfile { module, function }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::PgoError;
+    use crate::pgo::env::PgoEnv;
+    use crate::pgo::optimize::{check_pgo_env, parse_profile_weight, profile_group};
+    use std::path::{Path, PathBuf};
+
+    fn pgo_env(version: Option<u32>) -> PgoEnv {
+        PgoEnv {
+            llvm_profdata: PathBuf::from("/usr/bin/llvm-profdata"),
+            version,
+        }
+    }
+
+    #[test]
+    fn llvm_mismatch_fails_by_default() {
+        let error = check_pgo_env(pgo_env(Some(14)), Some(19), false).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PgoError>(),
+            Some(PgoError::LlvmVersionMismatch(_))
+        ));
+        assert!(error.to_string().contains("--allow-llvm-mismatch"));
+    }
+
+    #[test]
+    fn llvm_mismatch_allowed() {
+        let env = check_pgo_env(pgo_env(Some(14)), Some(19), true).unwrap();
+        assert_eq!(env.version, Some(14));
+    }
+
+    #[test]
+    fn llvm_versions_match() {
+        assert!(check_pgo_env(pgo_env(Some(19)), Some(19), false).is_ok());
+        assert!(check_pgo_env(pgo_env(None), Some(19), false).is_ok());
+    }
+
+    #[test]
+    fn parse_weight() {
+        assert_eq!(parse_profile_weight("api=4"), Ok(("api".to_string(), 4)));
+        assert!(parse_profile_weight("api").is_err());
+        assert!(parse_profile_weight("api=-1").is_err());
+        assert!(parse_profile_weight("=1").is_err());
+    }
+
+    #[test]
+    fn group_of_profile() {
+        let dir = Path::new("/profiles");
+        assert_eq!(profile_group(dir, Path::new("/profiles/a.profraw")), None);
+        assert_eq!(
+            profile_group(dir, Path::new("/profiles/api/a.profraw")),
+            Some("api".to_string())
+        );
+        assert_eq!(
+            profile_group(dir, Path::new("/profiles/api/nested/a.profraw")),
+            Some("api".to_string())
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use colored::Colorize;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::cli_format_path;
+use crate::error::PgoError;
+use crate::pgo::optimize::find_checked_pgo_env;
+use crate::pgo::profile::find_or_merge_profile;
+use crate::utils::file::{gather_files_with_extension, hash_file};
+use crate::utils::str::pluralize;
+use crate::workspace::CargoContext;
+use crate::{ensure_directory, get_default_target};
+
+/// Version of the profile bundle format.
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const PROFILE_FILE_NAME: &str = "profile.profdata";
+
+#[derive(clap::Parser, Debug)]
+pub struct ProfileExportArgs {
+    /// Path of the created bundle (a `.tar.gz` archive).
+    output: PathBuf,
+
+    /// Path to a `.profdata` file that should be exported. If not specified, the newest merged
+    /// profile from the PGO profile directory will be used (profiles will be merged if needed).
+    #[clap(long)]
+    profile: Option<PathBuf>,
+
+    /// Target triple for which the profile was gathered. Defaults to the host target.
+    #[clap(long)]
+    target: Option<String>,
+
+    /// Override the PGO profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+}
+
+impl ProfileExportArgs {
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+}
+
+#[derive(clap::Parser, Debug)]
+pub struct ProfileImportArgs {
+    /// Path to a bundle created by `cargo pgo profile export`.
+    bundle: PathBuf,
+
+    /// Target triple for which the profile will be used. Defaults to the host target.
+    #[clap(long)]
+    target: Option<String>,
+
+    /// Override the PGO profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+}
+
+impl ProfileImportArgs {
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+}
+
+/// Describes the environment in which a bundled PGO profile was created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileManifest {
+    pub format_version: u32,
+    pub cargo_pgo_version: String,
+    pub rustc_version: String,
+    /// Version of LLVM used by `rustc`, e.g. `18.1`.
+    pub llvm_version: Option<String>,
+    pub target: String,
+    /// Names of the workspace packages from which the profile was gathered.
+    pub crates: Vec<String>,
+    /// Git commit of the workspace, if it is stored in a git repository.
+    pub git_commit: Option<String>,
+    /// Hash of the bundled `.profdata` file.
+    pub profile_hash: String,
+}
+
+impl ProfileManifest {
+    /// Creates a manifest describing the current environment.
+    fn current(
+        ctx: &CargoContext,
+        target: Option<String>,
+        profile_hash: String,
+    ) -> anyhow::Result<Self> {
+        let version = rustc_version::version_meta()?;
+        Ok(ProfileManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            cargo_pgo_version: env!("CARGO_PKG_VERSION").to_string(),
+            rustc_version: version.short_version_string,
+            llvm_version: version
+                .llvm_version
+                .map(|llvm| format!("{}.{}", llvm.major, llvm.minor)),
+            target: match target {
+                Some(target) => target,
+                None => get_default_target()?,
+            },
+            crates: ctx
+                .packages()
+                .iter()
+                .map(|package| package.name.clone())
+                .collect(),
+            git_commit: ctx.git_commit(),
+            profile_hash,
+        })
+    }
+}
+
+/// Packages a merged PGO profile together with a manifest describing the environment in which
+/// it was created into a single archive.
+pub fn pgo_profile_export(ctx: &CargoContext, args: ProfileExportArgs) -> anyhow::Result<()> {
+    let profile = match args.profile {
+        Some(profile) => profile,
+        None => {
+            let pgo_env = find_checked_pgo_env(args.allow_llvm_mismatch)?;
+            find_or_merge_profile(
+                &pgo_env,
+                &ctx.get_pgo_directory()?,
+                &ctx.resolve_profile_weights(vec![]),
+            )?
+        }
+    };
+    let hash = hash_file(&profile).map_err(|error| {
+        anyhow!(
+            "Cannot read profile {}: {}",
+            cli_format_path(profile.display()),
+            error
+        )
+    })?;
+    let manifest = ProfileManifest::current(ctx, args.target, hash)?;
+
+    let file = File::create(&args.output).map_err(|error| {
+        anyhow!(
+            "Cannot create bundle {}: {}",
+            cli_format_path(args.output.display()),
+            error
+        )
+    })?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let manifest_content = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_FILE_NAME, manifest_content.as_slice())?;
+    archive.append_path_with_name(&profile, PROFILE_FILE_NAME)?;
+    archive.into_inner()?.finish()?;
+
+    log::info!(
+        "PGO profile {} was exported to {} (rustc {}, target {}).",
+        cli_format_path(profile.display()),
+        cli_format_path(args.output.display()),
+        manifest.rustc_version.blue(),
+        manifest.target.blue()
+    );
+    Ok(())
+}
+
+/// Extracts a PGO profile from a bundle into the PGO profile directory, after checking that
+/// it is compatible with the current environment.
+pub fn pgo_profile_import(ctx: &CargoContext, args: ProfileImportArgs) -> anyhow::Result<()> {
+    let file = File::open(&args.bundle).map_err(|error| {
+        anyhow!(
+            "Cannot open bundle {}: {}",
+            cli_format_path(args.bundle.display()),
+            error
+        )
+    })?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    let tempdir = tempfile::tempdir()?;
+    let profile_tmp_path = tempdir.path().join(PROFILE_FILE_NAME);
+    let mut manifest: Option<ProfileManifest> = None;
+    let mut has_profile = false;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        if path == Path::new(MANIFEST_FILE_NAME) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            manifest = Some(
+                serde_json::from_str(&content)
+                    .map_err(|error| anyhow!("Cannot parse bundle manifest: {error}"))?,
+            );
+        } else if path == Path::new(PROFILE_FILE_NAME) {
+            entry.unpack(&profile_tmp_path)?;
+            has_profile = true;
+        }
+    }
+
+    let invalid_bundle = |missing: &str| {
+        anyhow!(
+            "{} is not a valid profile bundle, it does not contain {}",
+            cli_format_path(args.bundle.display()),
+            missing
+        )
+    };
+    let manifest = manifest.ok_or_else(|| invalid_bundle(MANIFEST_FILE_NAME))?;
+    if !has_profile {
+        return Err(invalid_bundle(PROFILE_FILE_NAME));
+    }
+
+    let hash = hash_file(&profile_tmp_path)?;
+    if hash != manifest.profile_hash {
+        return Err(anyhow!(
+            "The profile in bundle {} is corrupted: its hash does not match the manifest",
+            cli_format_path(args.bundle.display())
+        ));
+    }
+
+    let current = ProfileManifest::current(ctx, args.target, hash.clone())?;
+    for warning in check_manifest(&manifest, &current)? {
+        log::warn!("{warning}");
+    }
+
+    let pgo_dir = ctx.get_pgo_directory()?;
+    ensure_directory(&pgo_dir)?;
+    let raw_profiles = gather_files_with_extension(&pgo_dir, "profraw").len();
+    if raw_profiles > 0 {
+        log::warn!(
+            "The PGO profile directory {} contains {} `.profraw` {}, which will be merged together \
+with the imported profile by `cargo pgo optimize`. Remove them if only the imported profile should be used.",
+            cli_format_path(pgo_dir.display()),
+            raw_profiles,
+            pluralize("file", raw_profiles)
+        );
+    }
+    let target_profile = pgo_dir.join(format!("imported-{hash}.profdata"));
+    std::fs::copy(&profile_tmp_path, &target_profile)?;
+
+    log::info!(
+        "PGO profile was imported to {}. It will be used by `cargo pgo optimize`.",
+        cli_format_path(target_profile.display())
+    );
+    Ok(())
+}
+
+/// Checks that a profile created in the environment described by `bundle` can be used in the
+/// `current` environment. Returns warnings about differences that do not prevent its usage.
+fn check_manifest(
+    bundle: &ProfileManifest,
+    current: &ProfileManifest,
+) -> anyhow::Result<Vec<String>> {
+    if bundle.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(anyhow!(
+            "The profile bundle was created by a newer version of `cargo-pgo` ({}), please upgrade `cargo-pgo`",
+            bundle.cargo_pgo_version
+        ));
+    }
+
+    let mut warnings = vec![];
+    match (&bundle.llvm_version, &current.llvm_version) {
+        (Some(bundle_llvm), Some(current_llvm)) => {
+            if llvm_major(bundle_llvm) != llvm_major(current_llvm) {
+                return Err(PgoError::LlvmVersionMismatch(format!(
+                    "The profile bundle was created with LLVM {} ({}), but the current `rustc` uses LLVM {} ({}). \
+PGO profiles cannot be used across different major LLVM versions. Regenerate the profiles with the current \
+toolchain, or use a toolchain with LLVM {}.",
+                    bundle_llvm,
+                    bundle.rustc_version,
+                    current_llvm,
+                    current.rustc_version,
+                    bundle_llvm
+                ))
+                .into());
+            }
+        }
+        _ => warnings.push(
+            "Cannot determine the LLVM version of the profile bundle or of the current `rustc`, the profile might not be compatible."
+                .to_string(),
+        ),
+    }
+
+    if bundle.rustc_version != current.rustc_version {
+        warnings.push(format!(
+            "The profile bundle was created with {}, but the current compiler is {}.",
+            bundle.rustc_version, current.rustc_version
+        ));
+    }
+    if bundle.target != current.target {
+        warnings.push(format!(
+            "The profile bundle was created for target {}, but it will be used for target {}.",
+            bundle.target, current.target
+        ));
+    }
+    if !bundle.crates.is_empty()
+        && !bundle
+            .crates
+            .iter()
+            .any(|krate| current.crates.contains(krate))
+    {
+        warnings.push(format!(
+            "The profile bundle was created for crate(s) {}, which are not present in the current workspace.",
+            bundle.crates.join(", ")
+        ));
+    }
+    if let (Some(bundle_commit), Some(current_commit)) = (&bundle.git_commit, &current.git_commit) {
+        if bundle_commit != current_commit {
+            warnings.push(format!(
+                "The profile bundle was created at commit {bundle_commit}, but the current commit is {current_commit}. \
+Functions changed since then will not be optimized with PGO."
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+fn llvm_major(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::PgoError;
+    use crate::pgo::profile::bundle::{BUNDLE_FORMAT_VERSION, ProfileManifest, check_manifest};
+
+    fn manifest(llvm: &str, target: &str) -> ProfileManifest {
+        ProfileManifest {
+            format_version: BUNDLE_FORMAT_VERSION,
+            cargo_pgo_version: "0.3.0".to_string(),
+            rustc_version: format!("rustc (LLVM {llvm})"),
+            llvm_version: Some(llvm.to_string()),
+            target: target.to_string(),
+            crates: vec!["foo".to_string()],
+            git_commit: None,
+            profile_hash: "hash".to_string(),
+        }
+    }
+
+    #[test]
+    fn compatible_manifest() {
+        let bundle = manifest("18.1", "x86_64-unknown-linux-gnu");
+        assert!(check_manifest(&bundle, &bundle).unwrap().is_empty());
+    }
+
+    #[test]
+    fn llvm_minor_mismatch_warns() {
+        let warnings = check_manifest(
+            &manifest("18.0", "x86_64-unknown-linux-gnu"),
+            &manifest("18.1", "x86_64-unknown-linux-gnu"),
+        )
+        .unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn llvm_major_mismatch_fails() {
+        let error = check_manifest(
+            &manifest("17.0", "x86_64-unknown-linux-gnu"),
+            &manifest("18.1", "x86_64-unknown-linux-gnu"),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("LLVM 17.0"));
+        assert!(matches!(
+            error.downcast_ref::<PgoError>(),
+            Some(PgoError::LlvmVersionMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn target_mismatch_warns() {
+        let warnings = check_manifest(
+            &manifest("18.1", "aarch64-unknown-linux-gnu"),
+            &manifest("18.1", "x86_64-unknown-linux-gnu"),
+        )
+        .unwrap();
+        assert!(warnings[0].contains("aarch64-unknown-linux-gnu"));
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::cli::cli_format_path;
+use crate::output::{print_json, print_text};
+use crate::pgo::env::PgoEnv;
+use crate::pgo::optimize::{ProfileWeights, find_checked_pgo_env};
+use crate::pgo::profile::{ProfileFunction, find_or_merge_profile, load_profile_functions};
+use crate::run_command;
+
+#[derive(clap::Parser, Debug)]
+pub struct ProfileDiffArgs {
+    /// Base profile: a `.profdata` file or a directory with PGO profiles.
+    base: PathBuf,
+
+    /// Compared profile: a `.profdata` file or a directory with PGO profiles.
+    test: PathBuf,
+
+    /// Functions whose share of the total profile count changed by at least this factor
+    /// will be reported as changed. Has to be larger than `1`.
+    #[clap(long, default_value_t = 2.0, value_parser = parse_threshold)]
+    threshold: f64,
+
+    /// How many functions should be shown in each category. Use `0` to show all functions.
+    #[clap(long, default_value_t = 20)]
+    top: usize,
+
+    /// Print the differences in JSON format.
+    #[clap(long)]
+    json: bool,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+}
+
+/// Relative hotness of a function in a profile.
+#[derive(Debug, Serialize)]
+struct FunctionDiff {
+    name: String,
+    #[serde(rename = "crate")]
+    krate: Option<String>,
+    /// Share of the function on the total count of the base profile (in percent).
+    base_share: f64,
+    /// Share of the function on the total count of the compared profile (in percent).
+    test_share: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct CrateDiff {
+    name: String,
+    base_share: f64,
+    test_share: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct OverlapStat {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ProfileDiff {
+    base: PathBuf,
+    test: PathBuf,
+    /// Program-level statistics reported by `llvm-profdata overlap`.
+    overlap: Vec<OverlapStat>,
+    crates: Vec<CrateDiff>,
+    appeared: Vec<FunctionDiff>,
+    disappeared: Vec<FunctionDiff>,
+    changed: Vec<FunctionDiff>,
+}
+
+/// Compares two PGO profiles and prints functions whose hotness has changed.
+pub fn pgo_profile_diff(args: ProfileDiffArgs) -> anyhow::Result<()> {
+    let pgo_env = find_checked_pgo_env(args.allow_llvm_mismatch)?;
+    let base = resolve_profile(&pgo_env, &args.base)?;
+    let test = resolve_profile(&pgo_env, &args.test)?;
+
+    let overlap = profile_overlap(&pgo_env, &base, &test)?;
+    let base_functions = load_profile_functions(&pgo_env, &base)?;
+    let test_functions = load_profile_functions(&pgo_env, &test)?;
+
+    let mut diff = diff_profiles(&base_functions, &test_functions, args.threshold);
+    if args.top > 0 {
+        diff.appeared.truncate(args.top);
+        diff.disappeared.truncate(args.top);
+        diff.changed.truncate(args.top);
+    }
+
+    let diff = ProfileDiff {
+        base,
+        test,
+        overlap,
+        crates: diff.crates,
+        appeared: diff.appeared,
+        disappeared: diff.disappeared,
+        changed: diff.changed,
+    };
+
+    if args.json {
+        print_json(&diff)?;
+    } else {
+        print_diff(&diff);
+    }
+    Ok(())
+}
+
+/// Returns the path to a `.profdata` file. If `path` is a directory, the newest merged profile
+/// from it will be used (the profiles will be merged if needed).
+fn resolve_profile(pgo_env: &PgoEnv, path: &Path) -> anyhow::Result<PathBuf> {
+    if path.is_dir() {
+        find_or_merge_profile(pgo_env, path, &ProfileWeights::default())
+    } else if path.is_file() {
+        Ok(path.to_path_buf())
+    } else {
+        Err(anyhow!(
+            "Profile {} does not exist",
+            cli_format_path(path.display())
+        ))
+    }
+}
+
+fn profile_overlap(pgo_env: &PgoEnv, base: &Path, test: &Path) -> anyhow::Result<Vec<OverlapStat>> {
+    let output = run_command(
+        &pgo_env.llvm_profdata,
+        &["overlap".as_ref(), base.as_os_str(), test.as_os_str()],
+    )?
+    .ok()
+    .map_err(|error| anyhow!("Cannot compute overlap of PGO profiles: {}", error))?;
+    Ok(parse_overlap(&output.stdout))
+}
+
+/// Parses the program-level section of `llvm-profdata overlap` output.
+fn parse_overlap(output: &str) -> Vec<OverlapStat> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Program level:"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| OverlapStat {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        })
+        .collect()
+}
+
+struct FunctionDiffs {
+    crates: Vec<CrateDiff>,
+    appeared: Vec<FunctionDiff>,
+    disappeared: Vec<FunctionDiff>,
+    changed: Vec<FunctionDiff>,
+}
+
+fn parse_threshold(value: &str) -> Result<f64, String> {
+    let threshold = value
+        .parse::<f64>()
+        .map_err(|error| format!("invalid threshold `{value}`: {error}"))?;
+    if threshold.is_nan() || threshold <= 1.0 {
+        return Err(format!("threshold has to be larger than 1, got `{value}`"));
+    }
+    Ok(threshold)
+}
+
+/// Computes the share of each function (by its maximum block count) on the total count of the
+/// profile, in percent. Functions are identified by their demangled name without the symbol hash,
+/// which changes between builds.
+fn function_shares(functions: &[ProfileFunction]) -> HashMap<&str, (&ProfileFunction, f64)> {
+    let total: u64 = functions
+        .iter()
+        .map(|function| function.max_block_count)
+        .sum();
+    let mut shares: HashMap<&str, (&ProfileFunction, f64)> = HashMap::new();
+    for function in functions {
+        let share = match total {
+            0 => 0.0,
+            total => function.max_block_count as f64 / total as f64 * 100.0,
+        };
+        let entry = shares
+            .entry(function.name.as_str())
+            .or_insert((function, 0.0));
+        entry.1 += share;
+    }
+    shares
+}
+
+fn diff_profiles(
+    base: &[ProfileFunction],
+    test: &[ProfileFunction],
+    threshold: f64,
+) -> FunctionDiffs {
+    let base_shares = function_shares(base);
+    let test_shares = function_shares(test);
+
+    let mut crates: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    let mut appeared = vec![];
+    let mut disappeared = vec![];
+    let mut changed = vec![];
+
+    let mut names: Vec<&str> = base_shares
+        .keys()
+        .chain(test_shares.keys())
+        .copied()
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    for name in names {
+        let base = base_shares.get(name);
+        let test = test_shares.get(name);
+        let function = base.or(test).unwrap().0;
+        let base_share = base.map(|(_, share)| *share).unwrap_or_default();
+        let test_share = test.map(|(_, share)| *share).unwrap_or_default();
+
+        let krate = function.krate.clone();
+        let entry = crates
+            .entry(krate.clone().unwrap_or_else(|| "<unknown>".to_string()))
+            .or_default();
+        entry.0 += base_share;
+        entry.1 += test_share;
+
+        let diff = FunctionDiff {
+            name: function.name.clone(),
+            krate,
+            base_share,
+            test_share,
+        };
+        match (base_share > 0.0, test_share > 0.0) {
+            (false, true) => appeared.push(diff),
+            (true, false) => disappeared.push(diff),
+            (true, true) => {
+                let ratio = test_share / base_share;
+                if ratio >= threshold || ratio <= 1.0 / threshold {
+                    changed.push(diff);
+                }
+            }
+            (false, false) => {}
+        }
+    }
+
+    appeared.sort_by(|a, b| b.test_share.total_cmp(&a.test_share));
+    disappeared.sort_by(|a, b| b.base_share.total_cmp(&a.base_share));
+    changed.sort_by(|a, b| {
+        (b.test_share - b.base_share)
+            .abs()
+            .total_cmp(&(a.test_share - a.base_share).abs())
+    });
+
+    let mut crates: Vec<CrateDiff> = crates
+        .into_iter()
+        .map(|(name, (base_share, test_share))| CrateDiff {
+            name,
+            base_share,
+            test_share,
+        })
+        .collect();
+    crates.sort_by(|a, b| {
+        (b.test_share - b.base_share)
+            .abs()
+            .total_cmp(&(a.test_share - a.base_share).abs())
+    });
+
+    FunctionDiffs {
+        crates,
+        appeared,
+        disappeared,
+        changed,
+    }
+}
+
+fn print_diff(diff: &ProfileDiff) {
+    print_text!(
+        "Comparing base profile {} with profile {}.",
+        cli_format_path(diff.base.display()),
+        cli_format_path(diff.test.display())
+    );
+
+    print_text!("\n{}", "Overlap".bold());
+    for stat in &diff.overlap {
+        print_text!("  {}: {}", stat.name, stat.value);
+    }
+
+    print_text!("\n{}", "Crates (share of total count)".bold());
+    for krate in &diff.crates {
+        print_text!(
+            "  {:>7.2}% -> {:>7.2}%  {}",
+            krate.base_share,
+            krate.test_share,
+            krate.name.blue()
+        );
+    }
+
+    print_functions("Appeared functions", &diff.appeared);
+    print_functions("Disappeared functions", &diff.disappeared);
+    print_functions("Changed functions", &diff.changed);
+}
+
+fn print_functions(title: &str, functions: &[FunctionDiff]) {
+    print_text!("\n{} ({})", title.bold(), functions.len());
+    for function in functions {
+        print_text!(
+            "  {:>7.2}% -> {:>7.2}%  {}",
+            function.base_share,
+            function.test_share,
+            function.name.blue()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgo::profile::ProfileFunction;
+    use crate::pgo::profile::diff::{diff_profiles, parse_overlap, parse_threshold};
+
+    fn function(name: &str, count: u64) -> ProfileFunction {
+        ProfileFunction {
+            name: name.to_string(),
+            mangled_name: name.to_string(),
+            krate: name.split("::").next().map(|krate| krate.to_string()),
+            entry_count: count,
+            max_block_count: count,
+        }
+    }
+
+    #[test]
+    fn parse_overlap_output() {
+        let output = r#"Profile overlap information for base_profile: a and test_profile: b
+Program level:
+  # of functions overlap: 7
+  Edge profile overlap: 49.000%
+"#;
+        let overlap = parse_overlap(output);
+        assert_eq!(overlap.len(), 2);
+        assert_eq!(overlap[0].name, "# of functions overlap");
+        assert_eq!(overlap[0].value, "7");
+        assert_eq!(overlap[1].name, "Edge profile overlap");
+        assert_eq!(overlap[1].value, "49.000%");
+    }
+
+    #[test]
+    fn diff_functions() {
+        let base = vec![
+            function("foo::a", 50),
+            function("foo::b", 25),
+            function("bar::c", 25),
+        ];
+        let test = vec![
+            function("foo::a", 50),
+            function("foo::b", 5),
+            function("bar::d", 45),
+        ];
+        let diff = diff_profiles(&base, &test, 2.0);
+        assert_eq!(diff.appeared.len(), 1);
+        assert_eq!(diff.appeared[0].name, "bar::d");
+        assert_eq!(diff.disappeared.len(), 1);
+        assert_eq!(diff.disappeared[0].name, "bar::c");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].name, "foo::b");
+
+        let foo = diff
+            .crates
+            .iter()
+            .find(|krate| krate.name == "foo")
+            .unwrap();
+        assert_eq!(foo.base_share, 75.0);
+        assert_eq!(foo.test_share, 55.0);
+    }
+
+    #[test]
+    fn diff_functions_across_builds() {
+        let legacy = |hash: &str| ProfileFunction {
+            mangled_name: format!("_ZN3foo1a17h{hash}E"),
+            ..function("foo::a", 10)
+        };
+        let diff = diff_profiles(
+            &[legacy("0123456789abcdef")],
+            &[legacy("fedcba9876543210")],
+            2.0,
+        );
+        assert!(diff.appeared.is_empty());
+        assert!(diff.disappeared.is_empty());
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn threshold_larger_than_one() {
+        assert_eq!(parse_threshold("1.5"), Ok(1.5));
+        assert!(parse_threshold("1").is_err());
+        assert!(parse_threshold("0.5").is_err());
+        assert!(parse_threshold("NaN").is_err());
+        assert!(parse_threshold("foo").is_err());
+    }
+}
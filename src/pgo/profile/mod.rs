@@ -0,0 +1,351 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use rustc_demangle::demangle;
+use serde::Serialize;
+
+use crate::cli::cli_format_path;
+use crate::error::PgoError;
+use crate::pgo::env::PgoEnv;
+use crate::pgo::missing::function_crate;
+use crate::pgo::optimize::{
+    ProfileWeights, gather_pgo_profile_stats, is_merged_profile, merge_profiles,
+    print_pgo_profile_stats,
+};
+use crate::run_command;
+use crate::utils::file::gather_files_with_extension;
+
+pub mod bundle;
+pub mod diff;
+pub mod show;
+
+/// Execution counts of a single function stored in a PGO profile.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileFunction {
+    /// Demangled name of the function.
+    pub name: String,
+    /// Name of the function as it is stored in the profile.
+    pub mangled_name: String,
+    /// Crate in which the function is defined, if it can be determined from its name.
+    #[serde(rename = "crate")]
+    pub krate: Option<String>,
+    /// Number of times the function was executed (count of its first counter).
+    pub entry_count: u64,
+    /// Largest count of all blocks of the function.
+    pub max_block_count: u64,
+}
+
+/// Finds the newest profile merged by [`merge_profiles`] in `pgo_dir`. If there is no merged
+/// profile, or if some input profile (a raw profile or e.g. an imported `.profdata` file) is newer
+/// than it, the input profiles will be merged first.
+pub(crate) fn find_or_merge_profile(
+    pgo_env: &PgoEnv,
+    pgo_dir: &Path,
+    weights: &ProfileWeights,
+) -> anyhow::Result<PathBuf> {
+    match find_merged_profile(pgo_dir)? {
+        MergedProfileState::UpToDate(merged) => Ok(merged),
+        MergedProfileState::Outdated => {
+            log::info!("Merging PGO profiles, because no up-to-date merged profile was found.");
+            let stats = gather_pgo_profile_stats(pgo_dir, weights)?;
+            print_pgo_profile_stats(&stats, pgo_dir)?;
+            merge_profiles(pgo_env, &stats, pgo_dir)
+        }
+        MergedProfileState::NoProfiles => Err(PgoError::NoProfiles(format!(
+            "No profile files were found at {}. Did you execute your instrumented program?",
+            cli_format_path(pgo_dir.display())
+        ))
+        .into()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum MergedProfileState {
+    UpToDate(PathBuf),
+    Outdated,
+    NoProfiles,
+}
+
+/// Checks if the newest merged profile in `pgo_dir` is newer than all input profiles.
+fn find_merged_profile(pgo_dir: &Path) -> anyhow::Result<MergedProfileState> {
+    let (merged, other): (Vec<_>, Vec<_>) = gather_files_with_extension(pgo_dir, "profdata")
+        .into_iter()
+        .partition(|file| is_merged_profile(file));
+    let merged = newest_file(merged)?;
+    let input = newest_file(
+        gather_files_with_extension(pgo_dir, "profraw")
+            .into_iter()
+            .chain(other)
+            .collect(),
+    )?;
+
+    Ok(match (merged, input) {
+        (Some((merged, merged_time)), Some((_, input_time))) if merged_time >= input_time => {
+            MergedProfileState::UpToDate(merged)
+        }
+        (Some((merged, _)), None) => MergedProfileState::UpToDate(merged),
+        (_, Some(_)) => MergedProfileState::Outdated,
+        (None, None) => MergedProfileState::NoProfiles,
+    })
+}
+
+/// Returns the most recently modified file from `files`, along with its modification time.
+pub(crate) fn newest_file(files: Vec<PathBuf>) -> anyhow::Result<Option<(PathBuf, SystemTime)>> {
+    let mut newest: Option<(PathBuf, SystemTime)> = None;
+    for file in files {
+        let modified = std::fs::metadata(&file)?.modified()?;
+        if newest
+            .as_ref()
+            .is_none_or(|(_, newest_time)| modified > *newest_time)
+        {
+            newest = Some((file, modified));
+        }
+    }
+    Ok(newest)
+}
+
+/// Loads execution counts of all functions stored in the given `.profdata` file.
+pub(crate) fn load_profile_functions(
+    pgo_env: &PgoEnv,
+    profile: &Path,
+) -> anyhow::Result<Vec<ProfileFunction>> {
+    let output = run_command(
+        &pgo_env.llvm_profdata,
+        &[
+            "show".as_ref(),
+            "--all-functions".as_ref(),
+            "--counts".as_ref(),
+            profile.as_os_str(),
+        ],
+    )?
+    .ok()
+    .map_err(|error| {
+        anyhow!(
+            "Cannot read PGO profile {}: {}",
+            cli_format_path(profile.display()),
+            error
+        )
+    })?;
+    Ok(parse_profile_functions(&output.stdout))
+}
+
+/// Returns the number of functions stored in the given `.profdata` file.
+pub(crate) fn count_profile_functions(pgo_env: &PgoEnv, profile: &Path) -> anyhow::Result<usize> {
+    let output = run_command(
+        &pgo_env.llvm_profdata,
+        &["show".as_ref(), profile.as_os_str()],
+    )?
+    .ok()
+    .map_err(|error| {
+        anyhow!(
+            "Cannot read PGO profile {}: {}",
+            cli_format_path(profile.display()),
+            error
+        )
+    })?;
+    parse_total_functions(&output.stdout).ok_or_else(|| {
+        anyhow!(
+            "Cannot find the number of functions in PGO profile {}",
+            cli_format_path(profile.display())
+        )
+    })
+}
+
+/// Parses the `Total functions: <N>` line of the output of `llvm-profdata show`.
+fn parse_total_functions(output: &str) -> Option<usize> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Total functions:"))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// Parses the output of `llvm-profdata show --all-functions --counts`.
+fn parse_profile_functions(output: &str) -> Vec<ProfileFunction> {
+    let mut functions: Vec<RawFunction> = vec![];
+
+    let lines = output
+        .lines()
+        .skip_while(|line| !line.starts_with("Counters:"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '));
+    for line in lines {
+        if !line.starts_with("    ") {
+            if let Some(name) = line.trim().strip_suffix(':') {
+                functions.push(RawFunction {
+                    name,
+                    function_count: None,
+                    block_counts: vec![],
+                });
+            }
+            continue;
+        }
+        let Some(function) = functions.last_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Function count:") {
+            function.function_count = value.trim().parse().ok();
+        } else if let Some(value) = line.strip_prefix("Block counts:") {
+            function.block_counts = value
+                .trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .filter_map(|count| count.trim().parse().ok())
+                .collect();
+        }
+    }
+    functions
+        .into_iter()
+        .map(RawFunction::into_profile_function)
+        .collect()
+}
+
+struct RawFunction<'a> {
+    name: &'a str,
+    function_count: Option<u64>,
+    block_counts: Vec<u64>,
+}
+
+impl RawFunction<'_> {
+    fn into_profile_function(self) -> ProfileFunction {
+        // Functions with internal linkage are prefixed with the name of their module
+        let mangled_name = self.name.rsplit(';').next().unwrap_or(self.name);
+        let demangled = format!("{:#}", demangle(mangled_name));
+
+        // IR-level profiles do not store the function count explicitly, `llvm-profdata` uses
+        // the first counter instead.
+        let entry_count = self
+            .function_count
+            .or_else(|| self.block_counts.first().copied())
+            .unwrap_or_default();
+        let max_block_count = self
+            .block_counts
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or_default()
+            .max(entry_count);
+
+        ProfileFunction {
+            krate: function_crate(&demangled),
+            name: demangled,
+            mangled_name: mangled_name.to_string(),
+            entry_count,
+            max_block_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use crate::pgo::profile::{
+        MergedProfileState, find_merged_profile, parse_profile_functions, parse_total_functions,
+    };
+
+    fn profile(dir: &Path, name: &str, age: u64) {
+        let file = std::fs::File::create(dir.join(name)).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age))
+            .unwrap();
+    }
+
+    #[test]
+    fn merged_profile_ignores_other_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            find_merged_profile(dir.path()).unwrap(),
+            MergedProfileState::NoProfiles
+        );
+
+        profile(dir.path(), "imported-1.profdata", 20);
+        assert_eq!(
+            find_merged_profile(dir.path()).unwrap(),
+            MergedProfileState::Outdated
+        );
+
+        profile(dir.path(), "merged-2.profdata", 10);
+        assert_eq!(
+            find_merged_profile(dir.path()).unwrap(),
+            MergedProfileState::UpToDate(dir.path().join("merged-2.profdata"))
+        );
+
+        // A newer stray `.profdata` file is not picked as the merged profile
+        profile(dir.path(), "custom.profdata", 5);
+        assert_eq!(
+            find_merged_profile(dir.path()).unwrap(),
+            MergedProfileState::Outdated
+        );
+    }
+
+    #[test]
+    fn merged_profile_older_than_raw_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        profile(dir.path(), "merged-1.profdata", 10);
+        profile(dir.path(), "default.profraw", 5);
+        assert_eq!(
+            find_merged_profile(dir.path()).unwrap(),
+            MergedProfileState::Outdated
+        );
+    }
+
+    #[test]
+    fn parse_total_functions_count() {
+        let output = r#"Instrumentation level: IR  entry_first = 0
+Total functions: 9
+Maximum function count: 1
+"#;
+        assert_eq!(parse_total_functions(output), Some(9));
+        assert_eq!(parse_total_functions(""), None);
+    }
+
+    #[test]
+    fn parse_show_output() {
+        let output = r#"Counters:
+  _ZN3std2rt10lang_start17h6fe033dfa16ff4f5E:
+    Hash: 0x0a4d0ad3efffffff
+    Counters: 1
+    Block counts: [0]
+  foo.27f10cd75d6a58e6-cgu.0;_ZN3foo5inner3bar17h215772d34ac21ba5E:
+    Hash: 0x025f5c817fffffff
+    Counters: 3
+    Block counts: [2, 10, 1]
+  main:
+    Hash: 0x0a4d0ad3efffffff
+    Counters: 1
+    Block counts: [1]
+Instrumentation level: IR  entry_first = 0  instrument_loop_entries = 0
+Functions shown: 3
+"#;
+        let functions = parse_profile_functions(output);
+        assert_eq!(functions.len(), 3);
+        assert_eq!(functions[0].name, "std::rt::lang_start");
+        assert_eq!(functions[0].krate.as_deref(), Some("std"));
+        assert_eq!(functions[1].name, "foo::inner::bar");
+        assert_eq!(
+            functions[1].mangled_name,
+            "_ZN3foo5inner3bar17h215772d34ac21ba5E"
+        );
+        assert_eq!(functions[1].entry_count, 2);
+        assert_eq!(functions[1].max_block_count, 10);
+        assert_eq!(functions[2].name, "main");
+        assert_eq!(functions[2].krate, None);
+    }
+
+    #[test]
+    fn parse_show_output_function_count() {
+        let output = r#"Counters:
+  foo:
+    Hash: 0x0000000000000001
+    Counters: 2
+    Function count: 5
+    Block counts: [3]
+"#;
+        let functions = parse_profile_functions(output);
+        assert_eq!(functions[0].entry_count, 5);
+        assert_eq!(functions[0].max_block_count, 5);
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::cli::cli_format_path;
+use crate::output::{print_json, print_text};
+use crate::pgo::optimize::find_checked_pgo_env;
+use crate::pgo::profile::{ProfileFunction, find_or_merge_profile, load_profile_functions};
+use crate::workspace::CargoContext;
+
+#[derive(Debug, Copy, Clone, clap::ValueEnum)]
+pub enum ProfileSortKey {
+    /// Sort functions by the number of times they were executed.
+    Entry,
+    /// Sort functions by the largest count of their blocks.
+    MaxBlock,
+}
+
+#[derive(clap::Parser, Debug)]
+pub struct ProfileShowArgs {
+    /// Path to a `.profdata` file. If not specified, the newest merged profile from the PGO
+    /// profile directory will be used (profiles will be merged if needed).
+    profile: Option<PathBuf>,
+
+    /// Override the PGO profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// How many functions should be shown. Use `0` to show all functions.
+    #[clap(long, default_value_t = 20)]
+    top: usize,
+
+    /// Count by which the functions will be sorted.
+    #[clap(long, value_enum, default_value = "entry")]
+    sort: ProfileSortKey,
+
+    /// Only show functions from the given crate. Can be passed multiple times.
+    #[clap(long = "crate")]
+    crates: Vec<String>,
+
+    /// Print the functions in JSON format.
+    #[clap(long)]
+    json: bool,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+}
+
+impl ProfileShowArgs {
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+}
+
+#[derive(Serialize)]
+struct ProfileShowOutput {
+    profile: PathBuf,
+    total_functions: usize,
+    functions: Vec<ProfileFunction>,
+}
+
+/// Prints the hottest functions of a PGO profile, with demangled names.
+pub fn pgo_profile_show(ctx: &CargoContext, args: ProfileShowArgs) -> anyhow::Result<()> {
+    let pgo_env = find_checked_pgo_env(args.allow_llvm_mismatch)?;
+    let profile = match args.profile {
+        Some(profile) => profile,
+        None => find_or_merge_profile(
+            &pgo_env,
+            &ctx.get_pgo_directory()?,
+            &ctx.resolve_profile_weights(vec![]),
+        )?,
+    };
+
+    let mut functions = load_profile_functions(&pgo_env, &profile)?;
+    let total_functions = functions.len();
+
+    if !args.crates.is_empty() {
+        functions.retain(|function| {
+            function
+                .krate
+                .as_ref()
+                .is_some_and(|krate| args.crates.contains(krate))
+        });
+    }
+    let sort_key = |function: &ProfileFunction| match args.sort {
+        ProfileSortKey::Entry => function.entry_count,
+        ProfileSortKey::MaxBlock => function.max_block_count,
+    };
+    functions.sort_by(|a, b| {
+        sort_key(b)
+            .cmp(&sort_key(a))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    if args.top > 0 {
+        functions.truncate(args.top);
+    }
+
+    if args.json {
+        let output = ProfileShowOutput {
+            profile,
+            total_functions,
+            functions,
+        };
+        print_json(&output)?;
+        return Ok(());
+    }
+
+    print_text!(
+        "PGO profile {} contains {} functions, showing {}.",
+        cli_format_path(profile.display()),
+        total_functions,
+        functions.len()
+    );
+
+    let entry_header = "Entry count";
+    let max_header = "Max block count";
+    let entry_width = column_width(&functions, entry_header, |f| f.entry_count);
+    let max_width = column_width(&functions, max_header, |f| f.max_block_count);
+
+    print_text!(
+        "{}",
+        format!("{entry_header:>entry_width$}  {max_header:>max_width$}  Function").bold()
+    );
+    for function in &functions {
+        print_text!(
+            "{:>entry_width$}  {:>max_width$}  {}",
+            function.entry_count,
+            function.max_block_count,
+            function.name.blue()
+        );
+    }
+
+    Ok(())
+}
+
+fn column_width<F: Fn(&ProfileFunction) -> u64>(
+    functions: &[ProfileFunction],
+    header: &str,
+    value: F,
+) -> usize {
+    functions
+        .iter()
+        .map(|function| value(function).to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max(header.len())
+}
@@ -0,0 +1,82 @@
+use cargo_metadata::Message;
+use colored::Colorize;
+
+use crate::build::{CargoCommand, cargo_command_with_rustflags, handle_metadata_message};
+use crate::cli::cli_format_path;
+use crate::pgo::sample::{
+    PERF_DATA_FILE_NAME, is_nightly_compiler, profiled_binary_path, sample_common_rustflags,
+};
+use crate::workspace::CargoContext;
+use crate::{clear_directory, ensure_directory};
+
+#[derive(clap::Parser, Debug)]
+pub struct SampleBuildArgs {
+    /// Do not remove perf data that were recorded during previous runs.
+    #[clap(long, action)]
+    keep_profiles: bool,
+
+    /// Additional arguments that will be passed to `cargo build`.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl SampleBuildArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+}
+
+/// Builds a (non-instrumented) release binary with debug info, which can be profiled with `perf`.
+/// A copy of each built binary is stored in the sample profile directory, so that the recorded
+/// perf data can be later converted even if the original binary is overwritten.
+pub fn sample_build(ctx: &CargoContext, args: SampleBuildArgs) -> anyhow::Result<()> {
+    let sample_dir = ctx.get_sample_directory()?;
+    if !args.keep_profiles {
+        log::info!("Sample profile directory will be cleared.");
+        clear_directory(&sample_dir)?;
+    }
+
+    let flags = sample_common_rustflags(is_nightly_compiler()?);
+    let mut cargo = cargo_command_with_rustflags(
+        CargoCommand::Build,
+        flags,
+        ctx.resolve_cargo_args(args.cargo_args),
+    )?;
+
+    for message in cargo.messages() {
+        let message = message?;
+        match message {
+            Message::CompilerArtifact(artifact) => {
+                if let Some(ref executable) = artifact.executable {
+                    let binary_dir = sample_dir.join(&artifact.target.name);
+                    ensure_directory(&binary_dir)?;
+                    std::fs::copy(executable, profiled_binary_path(&binary_dir))?;
+
+                    log::info!("Binary {} built successfully.", artifact.target.name.blue());
+                    log::info!(
+                        "Now record it on your workload using the following command (you can \
+record multiple files with the `.data` extension into the same directory):\n{}",
+                        format!(
+                            "perf record -b -o {} -- {}",
+                            binary_dir.join(PERF_DATA_FILE_NAME).display(),
+                            cli_format_path(executable)
+                        )
+                        .blue()
+                    );
+                }
+            }
+            Message::BuildFinished(res) => {
+                if res.success {
+                    log::info!("Build for sampling finished {}.", "successfully".green());
+                } else {
+                    log::error!("Build for sampling has {}.", "failed".red());
+                }
+            }
+            _ => handle_metadata_message(message),
+        }
+    }
+
+    cargo.check_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{PgoError, tool_not_found};
+use crate::pgo::env::find_llvm_tool;
+use crate::resolve_binary;
+
+/// Binaries required for sampling-based PGO.
+#[derive(Debug)]
+pub struct SampleEnv {
+    /// Converter of `perf` data to LLVM sample profiles. It is invoked with the same arguments as
+    /// `llvm-profgen`.
+    pub converter: PathBuf,
+}
+
+pub(crate) fn find_perf() -> anyhow::Result<PathBuf> {
+    resolve_binary(Path::new("perf")).map_err(|error| tool_not_found("perf", error))
+}
+
+/// Finds the converter of `perf` data. If `converter` is specified (on the command line or in the
+/// configuration), it is used. Otherwise `llvm-profgen` is searched for in `llvm-tools-preview`
+/// and in PATH.
+pub(crate) fn find_sample_converter(converter: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if let Some(converter) = converter {
+        return resolve_binary(converter).map_err(|error| {
+            PgoError::ToolNotFound {
+                tool: converter.display().to_string(),
+                message: format!(
+                    "Cannot find sample profile converter {}: {:?}",
+                    converter.display(),
+                    error
+                ),
+            }
+            .into()
+        });
+    }
+    if let Some(llvm_profgen) = find_llvm_tool("llvm-profgen")? {
+        return Ok(llvm_profgen);
+    }
+    resolve_binary(Path::new("llvm-profgen")).map_err(|error| tool_not_found("llvm-profgen", error))
+}
+
+pub fn find_sample_env(converter: Option<&Path>) -> anyhow::Result<SampleEnv> {
+    let converter = find_sample_converter(converter)?;
+    Ok(SampleEnv { converter })
+}
@@ -0,0 +1,44 @@
+use rustc_version::Channel;
+use std::path::{Path, PathBuf};
+
+pub mod build;
+pub(crate) mod env;
+pub mod optimize;
+
+pub fn llvm_profgen_install_hint() -> &'static str {
+    "Build LLVM with `llvm-profgen` and add its `bin` directory to PATH, or configure a custom converter using `sample-converter`."
+}
+
+pub fn perf_install_hint() -> &'static str {
+    "Install `perf` using the package manager of your Linux distribution."
+}
+
+/// Name of the file in which the perf data of a binary should be recorded.
+const PERF_DATA_FILE_NAME: &str = "perf.data";
+
+/// Returns the path of the copy of the profiled binary stored in `binary_dir`, the directory
+/// named after the binary in the sample profile directory. The copy keeps the file name of the
+/// original executable, because the converter matches the samples recorded in the perf data to
+/// the binary by its file name.
+fn profiled_binary_path(binary_dir: &Path) -> PathBuf {
+    let name = binary_dir.file_name().unwrap_or_default().to_string_lossy();
+    binary_dir.join(format!("{name}{}", std::env::consts::EXE_SUFFIX))
+}
+
+/// Sample profiles are matched to the source code using debug info, so both the profiled and the
+/// optimized build have to be compiled with the same debug info flags.
+fn sample_common_rustflags(nightly: bool) -> Vec<String> {
+    let mut flags = vec!["-Cdebuginfo=line-tables-only".to_string()];
+    if nightly {
+        flags.push("-Zdebug-info-for-profiling".to_string());
+    }
+    flags
+}
+
+/// Sample profiles can only be used with an unstable `rustc` flag.
+fn is_nightly_compiler() -> anyhow::Result<bool> {
+    Ok(matches!(
+        rustc_version::version_meta()?.channel,
+        Channel::Nightly | Channel::Dev
+    ))
+}
@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::anyhow;
+use cargo_metadata::Message;
+use colored::Colorize;
+
+use crate::build::{
+    CargoCommand, cargo_command_with_rustflags, get_artifact_kind, handle_metadata_message,
+};
+use crate::cli::cli_format_path;
+use crate::error::PgoError;
+use crate::pgo::env::PgoEnv;
+use crate::pgo::optimize::find_checked_pgo_env;
+use crate::pgo::sample::env::{SampleEnv, find_sample_env};
+use crate::pgo::sample::{is_nightly_compiler, profiled_binary_path, sample_common_rustflags};
+use crate::utils::file::{gather_files_with_extension, hash_file, move_file};
+use crate::utils::str::{capitalize, pluralize};
+use crate::workspace::CargoContext;
+
+/// Extension of files with perf data recorded by `perf record`.
+const PERF_DATA_EXTENSION: &str = "data";
+
+#[derive(clap::Parser, Debug)]
+pub struct SampleOptimizeArgs {
+    /// Cargo command that will be used for the optimized compilation.
+    #[clap(value_enum, default_value = "build")]
+    command: CargoCommand,
+
+    /// Converter of perf data to LLVM sample profiles. It is invoked with the same arguments as
+    /// `llvm-profgen`. Overrides `sample-converter` from the configuration.
+    #[clap(long)]
+    converter: Option<PathBuf>,
+
+    /// Use the given perf data file instead of the files recorded into the sample profile
+    /// directory. Requires `--binary`.
+    #[clap(long, requires = "binary")]
+    perf_data: Option<PathBuf>,
+
+    /// Binary that was profiled to record the file passed to `--perf-data`.
+    #[clap(long, requires = "perf_data")]
+    binary: Option<PathBuf>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+
+    /// Additional arguments that will be passed to the Cargo command.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl SampleOptimizeArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+}
+
+/// A perf data file along with the binary whose execution it recorded.
+#[derive(Debug)]
+struct PerfRecording {
+    binary: PathBuf,
+    perf_data: PathBuf,
+}
+
+/// Converts perf data into an LLVM sample profile and uses it to build an optimized binary.
+pub fn sample_optimize(ctx: &CargoContext, args: SampleOptimizeArgs) -> anyhow::Result<()> {
+    if !is_nightly_compiler()? {
+        return Err(anyhow!(
+            "Sampling-based PGO requires a nightly compiler, because the `-Zprofile-sample-use` \
+flag is unstable. Use e.g. `cargo +nightly pgo sample optimize`."
+        ));
+    }
+
+    let sample_dir = ctx.get_sample_directory()?;
+    let converter = args
+        .converter
+        .as_deref()
+        .or(ctx.config().sample_converter.as_deref());
+    let sample_env = find_sample_env(converter)?;
+    let pgo_env = find_checked_pgo_env(args.allow_llvm_mismatch)?;
+
+    let recordings = match (args.perf_data, args.binary) {
+        (Some(perf_data), Some(binary)) => vec![PerfRecording { binary, perf_data }],
+        _ => gather_perf_recordings(&sample_dir)?,
+    };
+    if recordings.is_empty() {
+        return Err(PgoError::NoProfiles(format!(
+            "No perf data were found in {}. Record the binaries built by `cargo pgo sample build` \
+with `perf record` first.",
+            cli_format_path(sample_dir.display())
+        ))
+        .into());
+    }
+
+    let profile = create_sample_profile(&sample_env, &pgo_env, &recordings, &sample_dir)?;
+
+    let mut flags = sample_common_rustflags(true);
+    flags.push(format!("-Zprofile-sample-use={}", profile.display()));
+    let mut cargo =
+        cargo_command_with_rustflags(args.command, flags, ctx.resolve_cargo_args(args.cargo_args))?;
+
+    for message in cargo.messages() {
+        let message = message?;
+        match message {
+            Message::CompilerArtifact(artifact) => {
+                if let Some(executable) = artifact.executable.as_deref() {
+                    if let CargoCommand::Build = args.command {
+                        log::info!(
+                            "{} {} successfully optimized with a sample profile. You can find it at {}.",
+                            capitalize(get_artifact_kind(&artifact)).yellow(),
+                            artifact.target.name.blue(),
+                            cli_format_path(executable)
+                        );
+                    }
+                }
+            }
+            Message::BuildFinished(res) => {
+                if res.success {
+                    log::info!(
+                        "Sample profile optimized build finished {}.",
+                        "successfully".green()
+                    );
+                } else {
+                    log::error!("Sample profile optimized build has {}.", "failed".red());
+                }
+            }
+            _ => handle_metadata_message(message),
+        }
+    }
+
+    cargo.check_status()?;
+
+    Ok(())
+}
+
+/// Finds perf data recorded into the subdirectories of `sample_dir` created by
+/// `cargo pgo sample build`. Each subdirectory contains a copy of the profiled binary, see
+/// [`profiled_binary_path`].
+fn gather_perf_recordings(sample_dir: &Path) -> anyhow::Result<Vec<PerfRecording>> {
+    let mut recordings = vec![];
+    let mut binary_dirs = std::fs::read_dir(sample_dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    binary_dirs.sort();
+
+    for binary_dir in binary_dirs.into_iter().filter(|path| path.is_dir()) {
+        let mut perf_data = gather_files_with_extension(&binary_dir, PERF_DATA_EXTENSION);
+        if perf_data.is_empty() {
+            continue;
+        }
+        perf_data.sort();
+
+        let binary = profiled_binary_path(&binary_dir);
+        if !binary.is_file() {
+            return Err(anyhow!(
+                "Cannot find the profiled binary {}. Rebuild it with `cargo pgo sample build`.",
+                cli_format_path(binary.display())
+            ));
+        }
+        recordings.extend(perf_data.into_iter().map(|perf_data| PerfRecording {
+            binary: binary.clone(),
+            perf_data,
+        }));
+    }
+    Ok(recordings)
+}
+
+/// Converts the perf `recordings` into sample profiles, merges them and stores the result into
+/// `sample_dir`. Returns the path to the merged sample profile.
+fn create_sample_profile(
+    sample_env: &SampleEnv,
+    pgo_env: &PgoEnv,
+    recordings: &[PerfRecording],
+    sample_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let tempdir = tempfile::tempdir()?;
+
+    let mut profiles = vec![];
+    for (index, recording) in recordings.iter().enumerate() {
+        let profile = tempdir.path().join(format!("{index}.prof"));
+        log::debug!(
+            "Converting perf data {} of {} to {}.",
+            recording.perf_data.display(),
+            recording.binary.display(),
+            profile.display()
+        );
+        let output = Command::new(&sample_env.converter)
+            .arg(format!("--binary={}", recording.binary.display()))
+            .arg(format!("--perfdata={}", recording.perf_data.display()))
+            .arg(format!("--output={}", profile.display()))
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to convert perf data {}: {}",
+                cli_format_path(recording.perf_data.display()),
+                String::from_utf8_lossy(&output.stderr).red()
+            ));
+        }
+        profiles.push(profile);
+    }
+
+    let merged_path = tempdir.path().join("merged.prof");
+    let output = Command::new(&pgo_env.llvm_profdata)
+        .args(["merge", "--sample", "-o"])
+        .arg(&merged_path)
+        .args(&profiles)
+        .output()?;
+    if !output.status.success() {
+        return Err(PgoError::ProfileMerge(format!(
+            "Failed to merge sample profile(s): {}.",
+            String::from_utf8_lossy(&output.stderr).red()
+        ))
+        .into());
+    }
+
+    let hash = hash_file(&merged_path)?;
+    let target_profile = sample_dir.join(format!("merged-{hash}.prof"));
+    move_file(&merged_path, &target_profile)?;
+
+    log::info!(
+        "Converted {} perf data {} to sample profile {}.",
+        recordings.len(),
+        pluralize("file", recordings.len()),
+        cli_format_path(target_profile.display())
+    );
+    Ok(target_profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgo::sample::optimize::gather_perf_recordings;
+
+    #[test]
+    fn gather_recordings_with_old_perf_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary_dir = dir.path().join("foo");
+        std::fs::create_dir(&binary_dir).unwrap();
+        std::fs::write(binary_dir.join("perf.data.old"), "").unwrap();
+        std::fs::write(binary_dir.join("perf.data"), "").unwrap();
+        std::fs::write(binary_dir.join("run2.data"), "").unwrap();
+        let binary = binary_dir.join(format!("foo{}", std::env::consts::EXE_SUFFIX));
+        std::fs::write(&binary, "").unwrap();
+
+        let recordings = gather_perf_recordings(dir.path()).unwrap();
+        assert_eq!(recordings.len(), 2);
+        for recording in &recordings {
+            assert_eq!(recording.binary, binary);
+        }
+        assert_eq!(recordings[0].perf_data, binary_dir.join("perf.data"));
+        assert_eq!(recordings[1].perf_data, binary_dir.join("run2.data"));
+    }
+
+    #[test]
+    fn gather_recordings_missing_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary_dir = dir.path().join("foo");
+        std::fs::create_dir(&binary_dir).unwrap();
+        std::fs::write(binary_dir.join("perf.data"), "").unwrap();
+        assert!(gather_perf_recordings(dir.path()).is_err());
+    }
+}
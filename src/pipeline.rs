@@ -0,0 +1,223 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use colored::Colorize;
+
+use crate::bolt::cli::BoltArgs;
+use crate::bolt::instrument::BoltInstrumentArgs;
+use crate::bolt::optimize::{BoltOptimizeArgs, bolt_optimize};
+use crate::build::{BuiltArtifact, CargoCommand};
+use crate::cli::cli_format_path;
+use crate::error::PgoError;
+use crate::pgo::instrument::PgoInstrumentArgs;
+use crate::pgo::missing::MissingProfileReportFormat;
+use crate::pgo::optimize::{PgoOptimizeArgs, pgo_optimize};
+use crate::utils::file::gather_files_with_extension;
+use crate::workload::{Workload, run_workload};
+use crate::workspace::CargoContext;
+
+#[derive(clap::Parser, Debug)]
+pub struct PipelineArgs {
+    /// Command that will be executed to gather profiles. Can be passed multiple times.
+    /// Use `{bin}` (or `{bin:<name>}` if multiple binaries are built) as a placeholder for the
+    /// path to the instrumented binary, e.g. `--workload "{bin} --input data.txt"`.
+    /// If no workload is passed, workloads from the `cargo-pgo` configuration will be used.
+    #[clap(long = "workload")]
+    workloads: Vec<Workload>,
+
+    /// After building the PGO-optimized binary, also instrument it with BOLT, run the workloads
+    /// again and optimize it with BOLT.
+    #[clap(long)]
+    bolt: bool,
+
+    /// Override the PGO profile path.
+    #[clap(long)]
+    profiles_dir: Option<PathBuf>,
+
+    /// Override the BOLT profile path.
+    #[clap(long)]
+    bolt_profiles_dir: Option<PathBuf>,
+
+    /// Only print a warning instead of failing if `llvm-profdata` uses a different major LLVM
+    /// version than `rustc`.
+    #[clap(long)]
+    allow_llvm_mismatch: bool,
+
+    #[clap(flatten)]
+    bolt_args: BoltArgs,
+
+    /// Additional arguments that will be passed to `cargo build`.
+    #[arg(last(true))]
+    cargo_args: Vec<String>,
+}
+
+impl PipelineArgs {
+    pub fn cargo_args(&self) -> &[String] {
+        &self.cargo_args
+    }
+
+    pub fn profiles_dir(&self) -> &Option<PathBuf> {
+        &self.profiles_dir
+    }
+
+    pub fn bolt_profiles_dir(&self) -> &Option<PathBuf> {
+        &self.bolt_profiles_dir
+    }
+}
+
+/// Builds an instrumented binary, executes the workloads on it and then builds an optimized
+/// binary, all in a single step.
+pub fn pgo_pipeline(ctx: &CargoContext, args: PipelineArgs) -> anyhow::Result<()> {
+    let pgo_dir = ctx.get_pgo_directory()?;
+    let workloads = resolve_workloads(ctx, args.workloads)?;
+
+    let artifacts = crate::pgo::instrument::instrument(
+        ctx,
+        PgoInstrumentArgs {
+            command: CargoCommand::Build,
+            keep_profiles: false,
+            profiles_dir: None,
+            quiet: false,
+            cargo_args: args.cargo_args.clone(),
+        },
+    )?;
+
+    let mut profile_weights = vec![];
+    for (index, workload) in workloads.iter().enumerate() {
+        if let Some(weight) = run_pgo_workload(workload, index, &artifacts, &pgo_dir)? {
+            profile_weights.push(weight);
+        }
+    }
+
+    pgo_optimize(
+        ctx,
+        PgoOptimizeArgs {
+            command: CargoCommand::Build,
+            profiles_dir: None,
+            missing_profile_report: None,
+            missing_profile_report_format: MissingProfileReportFormat::Text,
+            profile_weights: profile_weights.clone(),
+            profile_file: None,
+            require_fresh_profiles: false,
+            cs: false,
+            cs_linker: "clang".to_string(),
+            allow_llvm_mismatch: args.allow_llvm_mismatch,
+            quiet: false,
+            cargo_args: args.cargo_args.clone(),
+        },
+    )?;
+
+    if !args.bolt {
+        return Ok(());
+    }
+
+    let bolt_dir = ctx.get_bolt_directory()?;
+    let artifacts: Vec<_> = crate::bolt::instrument::instrument(
+        ctx,
+        BoltInstrumentArgs {
+            command: CargoCommand::Build,
+            with_pgo: true,
+            profile_weights: profile_weights.clone(),
+            allow_llvm_mismatch: args.allow_llvm_mismatch,
+            keep_profiles: false,
+            profiles_dir: None,
+            bolt_args: args.bolt_args.clone(),
+            cargo_args: args.cargo_args.clone(),
+        },
+    )?
+    .into_iter()
+    .map(|(artifact, _)| artifact)
+    .collect();
+
+    for workload in &workloads {
+        let profile_count = gather_files_with_extension(&bolt_dir, "fdata").len();
+        run_workload(workload, &artifacts, &[])?;
+
+        if gather_files_with_extension(&bolt_dir, "fdata").len() <= profile_count {
+            return Err(PgoError::NoProfiles(format!(
+                "Workload `{}` did not produce any BOLT profiles at {}. Does it execute the instrumented binary?",
+                workload,
+                cli_format_path(bolt_dir.display())
+            ))
+            .into());
+        }
+    }
+
+    bolt_optimize(
+        ctx,
+        BoltOptimizeArgs {
+            command: CargoCommand::Build,
+            with_pgo: true,
+            profile_weights,
+            allow_llvm_mismatch: args.allow_llvm_mismatch,
+            bolt_args: args.bolt_args,
+            profiles_dir: None,
+            replace: false,
+            output_dir: None,
+            run: false,
+            cargo_args: args.cargo_args,
+        },
+    )?;
+
+    log::info!("PGO+BOLT pipeline finished {}.", "successfully".green());
+
+    Ok(())
+}
+
+/// Runs a workload on PGO-instrumented `artifacts` and makes sure that it has produced profiles.
+/// Returns the weight of the profiles of the workload, if it has one.
+pub(crate) fn run_pgo_workload(
+    workload: &Workload,
+    index: usize,
+    artifacts: &[BuiltArtifact],
+    pgo_dir: &Path,
+) -> anyhow::Result<Option<(String, u32)>> {
+    // Profiles of each workload are stored in a separate subdirectory, so that they can be
+    // weighted when they are merged.
+    let directory = workload
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("workload-{index}"));
+
+    let workload_dir = pgo_dir.join(&directory);
+    let profile_count = gather_files_with_extension(&workload_dir, "profraw").len();
+    let profile_file = workload_dir.join("%m_%p.profraw");
+    run_workload(
+        workload,
+        artifacts,
+        &[(
+            "LLVM_PROFILE_FILE".to_string(),
+            profile_file.display().to_string(),
+        )],
+    )?;
+
+    if gather_files_with_extension(&workload_dir, "profraw").len() <= profile_count {
+        return Err(PgoError::NoProfiles(format!(
+            "Workload `{}` did not produce any PGO profiles at {}. Does it execute the instrumented binary?",
+            workload,
+            cli_format_path(workload_dir.display())
+        ))
+        .into());
+    }
+    Ok(workload.weight.map(|weight| (directory, weight)))
+}
+
+/// Returns workloads passed on the command line, or workloads from the configuration if no
+/// workloads were passed.
+pub(crate) fn resolve_workloads(
+    ctx: &CargoContext,
+    workloads: Vec<Workload>,
+) -> anyhow::Result<Vec<Workload>> {
+    if !workloads.is_empty() {
+        return Ok(workloads);
+    }
+    match &ctx.config().workloads {
+        Some(workloads) if !workloads.is_empty() => Ok(workloads
+            .iter()
+            .map(|workload| workload.to_workload())
+            .collect()),
+        _ => Err(anyhow!(
+            "No workloads were specified. Pass them with `--workload` or configure them in `[package.metadata.pgo]`."
+        )),
+    }
+}
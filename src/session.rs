@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use crate::build::{BuiltArtifact, CargoCommand};
+use crate::pgo::instrument::PgoInstrumentArgs;
+use crate::pgo::missing::MissingProfileReportFormat;
+use crate::pgo::optimize::{PgoOptimizeArgs, PgoOptimizeOutput};
+use crate::pipeline::run_pgo_workload;
+use crate::workload::Workload;
+use crate::workspace::{CargoContext, get_cargo_ctx_for_manifest};
+
+/// A PGO workflow driven from Rust code, without executing the `cargo pgo` command.
+///
+/// ```no_run
+/// use cargo_pgo::session::PgoSession;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let output = PgoSession::new("Cargo.toml")
+///     .cargo_args(["--bin", "server"])
+///     .instrument()?
+///     .run_workload(&"{bin} --benchmark".parse()?)?
+///     .optimize()?;
+/// for artifact in &output.artifacts {
+///     println!("{}: {}", artifact.name, artifact.path.display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PgoSession {
+    manifest_path: PathBuf,
+    cargo_args: Vec<String>,
+    profiles_dir: Option<PathBuf>,
+    keep_profiles: bool,
+    quiet: bool,
+}
+
+impl PgoSession {
+    /// Creates a session for the crate or workspace with the given `Cargo.toml`.
+    pub fn new<P: Into<PathBuf>>(manifest_path: P) -> Self {
+        Self {
+            manifest_path: manifest_path.into(),
+            cargo_args: vec![],
+            profiles_dir: None,
+            keep_profiles: false,
+            quiet: false,
+        }
+    }
+
+    /// Additional arguments that will be passed to the executed `cargo` commands.
+    pub fn cargo_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cargo_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Override the PGO profile path.
+    pub fn profiles_dir<P: Into<PathBuf>>(mut self, profiles_dir: P) -> Self {
+        self.profiles_dir = Some(profiles_dir.into());
+        self
+    }
+
+    /// Do not remove profiles that were gathered during previous runs.
+    pub fn keep_profiles(mut self, keep_profiles: bool) -> Self {
+        self.keep_profiles = keep_profiles;
+        self
+    }
+
+    /// Do not print the output of Cargo and the build summary to stdout. Logs are still emitted
+    /// using the `log` crate, so their output is controlled by the logger of the application.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Performs a PGO-instrumented build.
+    pub fn instrument(&self) -> anyhow::Result<InstrumentedBuild> {
+        let ctx = self.get_ctx()?;
+        let artifacts = crate::pgo::instrument::instrument(
+            &ctx,
+            PgoInstrumentArgs {
+                command: CargoCommand::Build,
+                keep_profiles: self.keep_profiles,
+                profiles_dir: None,
+                quiet: self.quiet,
+                cargo_args: self.resolve_cargo_args(&ctx),
+            },
+        )?;
+        let profiles_dir = ctx.get_pgo_directory()?;
+        Ok(InstrumentedBuild {
+            session: self.clone(),
+            ctx,
+            artifacts,
+            profiles_dir,
+            workload_count: 0,
+            profile_weights: vec![],
+        })
+    }
+
+    /// Performs a PGO-optimized build with the profiles gathered before.
+    pub fn optimize(&self) -> anyhow::Result<PgoOptimizeOutput> {
+        let ctx = self.get_ctx()?;
+        self.optimize_with(&ctx, vec![])
+    }
+
+    fn optimize_with(
+        &self,
+        ctx: &CargoContext,
+        profile_weights: Vec<(String, u32)>,
+    ) -> anyhow::Result<PgoOptimizeOutput> {
+        crate::pgo::optimize::optimize(
+            ctx,
+            PgoOptimizeArgs {
+                command: CargoCommand::Build,
+                profiles_dir: None,
+                missing_profile_report: None,
+                missing_profile_report_format: MissingProfileReportFormat::Text,
+                profile_weights,
+                profile_file: None,
+                require_fresh_profiles: false,
+                cs: false,
+                cs_linker: "clang".to_string(),
+                allow_llvm_mismatch: false,
+                quiet: self.quiet,
+                cargo_args: self.resolve_cargo_args(ctx),
+            },
+        )
+    }
+
+    fn get_ctx(&self) -> anyhow::Result<CargoContext> {
+        get_cargo_ctx_for_manifest(
+            Some(&self.manifest_path),
+            &self.cargo_args,
+            self.profiles_dir.clone(),
+            None,
+        )
+    }
+
+    /// Returns the Cargo arguments (or the configured default arguments), together with the path
+    /// to the manifest of the session.
+    fn resolve_cargo_args(&self, ctx: &CargoContext) -> Vec<String> {
+        let mut args = vec![
+            "--manifest-path".to_string(),
+            self.manifest_path.display().to_string(),
+        ];
+        args.extend(ctx.resolve_cargo_args(self.cargo_args.clone()));
+        args
+    }
+}
+
+/// Result of a PGO-instrumented build, on which workloads can be executed to gather profiles.
+#[derive(Debug)]
+pub struct InstrumentedBuild {
+    session: PgoSession,
+    ctx: CargoContext,
+    artifacts: Vec<BuiltArtifact>,
+    profiles_dir: PathBuf,
+    workload_count: usize,
+    profile_weights: Vec<(String, u32)>,
+}
+
+impl InstrumentedBuild {
+    /// Instrumented executables.
+    pub fn artifacts(&self) -> &[BuiltArtifact] {
+        &self.artifacts
+    }
+
+    /// Directory where the gathered PGO profiles are stored.
+    pub fn profiles_dir(&self) -> &Path {
+        &self.profiles_dir
+    }
+
+    /// Executes `workload` on the instrumented executables and makes sure that it has produced
+    /// PGO profiles.
+    pub fn run_workload(mut self, workload: &Workload) -> anyhow::Result<Self> {
+        if let Some(weight) = run_pgo_workload(
+            workload,
+            self.workload_count,
+            &self.artifacts,
+            &self.profiles_dir,
+        )? {
+            self.profile_weights.push(weight);
+        }
+        self.workload_count += 1;
+        Ok(self)
+    }
+
+    /// Performs a PGO-optimized build with the profiles gathered by the executed workloads.
+    pub fn optimize(self) -> anyhow::Result<PgoOptimizeOutput> {
+        self.session.optimize_with(&self.ctx, self.profile_weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::PgoSession;
+
+    #[test]
+    fn builder_options() {
+        let session = PgoSession::new("foo/Cargo.toml")
+            .cargo_args(["--bin", "bar"])
+            .profiles_dir("/tmp/profiles")
+            .keep_profiles(true)
+            .quiet(true);
+        assert_eq!(session.manifest_path.to_str(), Some("foo/Cargo.toml"));
+        assert_eq!(session.cargo_args, vec!["--bin", "bar"]);
+        assert_eq!(
+            session.profiles_dir.as_deref().and_then(|dir| dir.to_str()),
+            Some("/tmp/profiles")
+        );
+        assert!(session.keep_profiles);
+        assert!(session.quiet);
+    }
+}
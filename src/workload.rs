@@ -0,0 +1,281 @@
+use crate::build::BuiltArtifact;
+use crate::output::child_stdout;
+use anyhow::anyhow;
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// A command that is executed to gather profiles from an instrumented binary.
+///
+/// The program and its arguments can contain the `{bin}` placeholder, which is replaced by the
+/// path of the (only) built binary, or `{bin:<name>}`, which is replaced by the path of the binary
+/// with the given target name.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    /// Name of the workload, used to store its profiles in a separate directory.
+    pub name: Option<String>,
+    /// Weight of the profiles gathered by this workload when they are merged.
+    pub weight: Option<u32>,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Additional environment variables set for the executed command.
+    pub env: BTreeMap<String, String>,
+    /// File that will be passed to the standard input of the executed command.
+    pub stdin: Option<PathBuf>,
+    /// Working directory of the executed command.
+    pub cwd: Option<PathBuf>,
+    /// How many times should the command be executed.
+    pub repetitions: u32,
+}
+
+impl FromStr for Workload {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = shellwords::split(value)
+            .map_err(|error| anyhow!("Could not parse workload `{value}`: {error:?}"))?
+            .into_iter();
+        let program = parts
+            .next()
+            .ok_or_else(|| anyhow!("Workload command cannot be empty"))?;
+        Ok(Workload {
+            name: None,
+            weight: None,
+            program,
+            args: parts.collect(),
+            env: Default::default(),
+            stdin: None,
+            cwd: None,
+            repetitions: 1,
+        })
+    }
+}
+
+impl Display for Workload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Workload {
+    /// Creates a command that will execute the workload, with placeholders replaced by paths
+    /// of the provided `artifacts`.
+    pub fn to_command(&self, artifacts: &[BuiltArtifact]) -> anyhow::Result<Command> {
+        let mut command = Command::new(substitute_artifacts(&self.program, artifacts)?);
+        for arg in &self.args {
+            command.arg(substitute_artifacts(arg, artifacts)?);
+        }
+        command.envs(&self.env);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        Ok(command)
+    }
+}
+
+/// Executes the workload and makes sure that it has finished successfully.
+pub fn run_workload(
+    workload: &Workload,
+    artifacts: &[BuiltArtifact],
+    env: &[(String, String)],
+) -> anyhow::Result<()> {
+    let mut command = workload.to_command(artifacts)?;
+    for (key, value) in env {
+        command.env(key, value);
+    }
+
+    for repetition in 0..workload.repetitions {
+        if workload.repetitions > 1 {
+            log::info!(
+                "Running workload {} ({}/{}).",
+                workload.to_string().blue(),
+                repetition + 1,
+                workload.repetitions
+            );
+        } else {
+            log::info!("Running workload {}.", workload.to_string().blue());
+        }
+        if let Some(stdin) = &workload.stdin {
+            let file = File::open(stdin).map_err(|error| {
+                anyhow!(
+                    "Cannot open stdin file {} of workload `{}`: {}",
+                    stdin.display(),
+                    workload,
+                    error
+                )
+            })?;
+            command.stdin(Stdio::from(file));
+        }
+        log::debug!("Executing workload command: {:?}", command);
+        command.stdout(child_stdout());
+
+        let status = command
+            .status()
+            .map_err(|error| anyhow!("Cannot execute workload `{}`: {}", workload, error))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "Workload `{}` has {} ({})",
+                workload,
+                "failed".red(),
+                status
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `{bin}` and `{bin:<name>}` placeholders in `value` with paths of `artifacts`.
+/// Any other text (e.g. `{binary}` or an unterminated `{bin`) is kept as it is.
+fn substitute_artifacts(value: &str, artifacts: &[BuiltArtifact]) -> anyhow::Result<String> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("{bin") {
+        result.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let Some((name, length)) = parse_placeholder(placeholder) else {
+            // Not a placeholder, keep the brace and continue after it
+            result.push('{');
+            rest = &placeholder[1..];
+            continue;
+        };
+        let path = match name {
+            None => match artifacts {
+                [artifact] => &artifact.path,
+                [] => return Err(anyhow!("No binary was built for the `{{bin}}` placeholder")),
+                _ => {
+                    return Err(anyhow!(
+                        "Multiple binaries were built ({}), use `{{bin:<name>}}` to select one of them",
+                        artifact_names(artifacts)
+                    ));
+                }
+            },
+            Some(name) => {
+                &artifacts
+                    .iter()
+                    .find(|artifact| artifact.name == name)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Binary `{name}` was not built, available binaries: {}",
+                            artifact_names(artifacts)
+                        )
+                    })?
+                    .path
+            }
+        };
+        result.push_str(&path.display().to_string());
+        rest = &placeholder[length..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Parses a `{bin}` or `{bin:<name>}` placeholder at the start of `value`.
+/// Returns the binary name (if any) and the length of the placeholder.
+fn parse_placeholder(value: &str) -> Option<(Option<&str>, usize)> {
+    if value.starts_with("{bin}") {
+        return Some((None, "{bin}".len()));
+    }
+    let name = value.strip_prefix("{bin:")?;
+    let end = name.find('}')?;
+    let name = &name[..end];
+    let is_target_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    is_target_name.then_some((Some(name), "{bin:".len() + end + 1))
+}
+
+fn artifact_names(artifacts: &[BuiltArtifact]) -> String {
+    artifacts
+        .iter()
+        .map(|artifact| artifact.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build::BuiltArtifact;
+    use crate::workload::{Workload, substitute_artifacts};
+    use std::path::PathBuf;
+
+    fn artifact(name: &str) -> BuiltArtifact {
+        BuiltArtifact {
+            name: name.to_string(),
+            path: PathBuf::from(format!("/target/{name}")),
+        }
+    }
+
+    #[test]
+    fn parse_workload() {
+        let workload: Workload = "{bin} --input 'a b.txt'".parse().unwrap();
+        assert_eq!(workload.program, "{bin}");
+        assert_eq!(
+            workload.args,
+            vec!["--input".to_string(), "a b.txt".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_workload_empty() {
+        assert!("".parse::<Workload>().is_err());
+    }
+
+    #[test]
+    fn substitute_single_binary() {
+        assert_eq!(
+            substitute_artifacts("{bin}", &[artifact("foo")]).unwrap(),
+            "/target/foo"
+        );
+    }
+
+    #[test]
+    fn substitute_named_binary() {
+        assert_eq!(
+            substitute_artifacts("--path={bin:bar}", &[artifact("foo"), artifact("bar")]).unwrap(),
+            "--path=/target/bar"
+        );
+    }
+
+    #[test]
+    fn substitute_ambiguous_binary() {
+        assert!(substitute_artifacts("{bin}", &[artifact("foo"), artifact("bar")]).is_err());
+    }
+
+    #[test]
+    fn substitute_missing_binary() {
+        assert!(substitute_artifacts("{bin:baz}", &[artifact("foo")]).is_err());
+    }
+
+    #[test]
+    fn substitute_keep_other_braces() {
+        assert_eq!(
+            substitute_artifacts("{binary}", &[artifact("foo")]).unwrap(),
+            "{binary}"
+        );
+    }
+
+    #[test]
+    fn substitute_keep_unterminated_braces() {
+        assert_eq!(
+            substitute_artifacts("{binary: 1}", &[artifact("foo")]).unwrap(),
+            "{binary: 1}"
+        );
+        assert_eq!(
+            substitute_artifacts("{bin", &[artifact("foo")]).unwrap(),
+            "{bin"
+        );
+        assert_eq!(
+            substitute_artifacts("{bin: {bin}}", &[artifact("foo")]).unwrap(),
+            "{bin: /target/foo}"
+        );
+    }
+}
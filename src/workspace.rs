@@ -1,11 +1,27 @@
 use crate::build::parse_cargo_args;
+use crate::config::{PgoConfig, load_config};
 use crate::ensure_directory;
+use crate::pgo::optimize::ProfileWeights;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
+/// A member package of the Cargo workspace.
+#[derive(Debug, Clone)]
+pub struct WorkspacePackage {
+    pub name: String,
+    pub version: String,
+    /// Directory that contains the manifest of the package.
+    pub root: PathBuf,
+}
+
+#[derive(Debug)]
 pub struct CargoContext {
     target_directory: PathBuf,
     pgo_profiles_dir: Option<PathBuf>,
     bolt_profiles_dir: Option<PathBuf>,
+    workspace_root: PathBuf,
+    packages: Vec<WorkspacePackage>,
+    config: PgoConfig,
 }
 
 impl CargoContext {
@@ -13,7 +29,7 @@ impl CargoContext {
         if let Some(profiles_dir) = &self.pgo_profiles_dir {
             Ok(profiles_dir.clone())
         } else {
-            self.get_target_directory(Path::new("pgo-profiles"))
+            ensured_directory(self.pgo_directory())
         }
     }
 
@@ -21,38 +37,245 @@ impl CargoContext {
         if let Some(profiles_dir) = &self.bolt_profiles_dir {
             Ok(profiles_dir.clone())
         } else {
-            self.get_target_directory(Path::new("bolt-profiles"))
+            ensured_directory(self.bolt_directory())
+        }
+    }
+
+    pub fn workspace_root(&self) -> &Path {
+        &self.workspace_root
+    }
+
+    pub fn target_directory(&self) -> &Path {
+        &self.target_directory
+    }
+
+    pub fn packages(&self) -> &[WorkspacePackage] {
+        &self.packages
+    }
+
+    /// Returns the current git commit of the workspace, if it is stored in a git repository.
+    pub fn git_commit(&self) -> Option<String> {
+        let output = Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&self.workspace_root)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let commit = String::from_utf8(output.stdout).ok()?;
+        Some(commit.trim().to_string())
+    }
+
+    /// Returns the directory where context-sensitive PGO profiles are stored. If the PGO profile
+    /// directory is overridden, the profiles are stored next to it, in a directory with the `-cs`
+    /// suffix. They cannot be stored inside of it, because they would be merged together with the
+    /// PGO profiles.
+    pub fn get_cs_pgo_directory(&self) -> anyhow::Result<PathBuf> {
+        ensured_directory(self.cs_pgo_directory()?)
+    }
+
+    /// Returns the directory where binaries and perf data for sampling-based PGO are stored.
+    pub fn get_sample_directory(&self) -> anyhow::Result<PathBuf> {
+        ensured_directory(self.sample_directory())
+    }
+
+    /// Returns the directory where binaries and results of `cargo pgo compare` are stored.
+    pub fn get_compare_directory(&self) -> anyhow::Result<PathBuf> {
+        ensured_directory(self.compare_directory())
+    }
+
+    /// Returns all directories in which `cargo-pgo` stores profiles and other artifacts, without
+    /// creating them.
+    pub(crate) fn artifact_directories(&self) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(vec![
+            self.pgo_directory(),
+            self.cs_pgo_directory()?,
+            self.sample_directory(),
+            self.compare_directory(),
+            self.bolt_directory(),
+        ])
+    }
+
+    /// Returns the configuration loaded from `pgo.toml` or `Cargo.toml`.
+    pub fn config(&self) -> &PgoConfig {
+        &self.config
+    }
+
+    /// Returns `cargo_args` passed on the command line, or the default Cargo arguments from the
+    /// configuration if no arguments were passed.
+    pub fn resolve_cargo_args(&self, cargo_args: Vec<String>) -> Vec<String> {
+        match (cargo_args.is_empty(), &self.config.cargo_args) {
+            (true, Some(config_args)) => config_args.clone(),
+            _ => cargo_args,
         }
     }
 
-    fn get_target_directory(&self, path: &Path) -> anyhow::Result<PathBuf> {
-        let directory = self.target_directory.join(path);
-        ensure_directory(&directory)?;
-        Ok(directory)
+    /// Returns profile weights from the configuration, overridden by `weights` passed on the
+    /// command line.
+    pub fn resolve_profile_weights(&self, weights: Vec<(String, u32)>) -> ProfileWeights {
+        let mut resolved = self.config.profile_weights.clone().unwrap_or_default();
+        resolved.extend(weights);
+        resolved
+    }
+
+    /// Creates a context for a workspace with the given target directory, without querying Cargo.
+    #[cfg(test)]
+    pub(crate) fn for_target_directory(target_directory: &Path, config: PgoConfig) -> Self {
+        Self {
+            target_directory: target_directory.to_path_buf(),
+            pgo_profiles_dir: config.profiles_dir.clone(),
+            bolt_profiles_dir: config.bolt_profiles_dir.clone(),
+            workspace_root: target_directory.to_path_buf(),
+            packages: vec![],
+            config,
+        }
+    }
+
+    fn pgo_directory(&self) -> PathBuf {
+        match &self.pgo_profiles_dir {
+            Some(profiles_dir) => profiles_dir.clone(),
+            None => self.target_directory.join("pgo-profiles"),
+        }
+    }
+
+    fn cs_pgo_directory(&self) -> anyhow::Result<PathBuf> {
+        match &self.pgo_profiles_dir {
+            Some(profiles_dir) => cs_pgo_directory(profiles_dir),
+            None => Ok(self.target_directory.join("cs-pgo-profiles")),
+        }
+    }
+
+    fn sample_directory(&self) -> PathBuf {
+        self.target_directory.join("pgo-sample-profiles")
+    }
+
+    fn compare_directory(&self) -> PathBuf {
+        self.target_directory.join("pgo-compare")
+    }
+
+    fn bolt_directory(&self) -> PathBuf {
+        match &self.bolt_profiles_dir {
+            Some(profiles_dir) => profiles_dir.clone(),
+            None => self.target_directory.join("bolt-profiles"),
+        }
     }
 }
 
+fn ensured_directory(directory: PathBuf) -> anyhow::Result<PathBuf> {
+    ensure_directory(&directory)?;
+    Ok(directory)
+}
+
 /// Finds Cargo metadata from the current directory.
 pub fn get_cargo_ctx(
     cargo_args: &[String],
     pgo_profiles_dir: Option<PathBuf>,
     bolt_profiles_dir: Option<PathBuf>,
 ) -> anyhow::Result<CargoContext> {
-    let cargo_args = parse_cargo_args(cargo_args.to_vec());
-    let target_directory = match cargo_args.target_dir {
-        Some(dir) => dir,
-        None => {
-            let cmd = cargo_metadata::MetadataCommand::new();
-            let metadata = cmd
-                .exec()
-                .map_err(|error| anyhow::anyhow!("Cannot get cargo metadata: {:?}", error))?;
-            metadata.target_directory.into_std_path_buf()
-        }
+    get_cargo_ctx_for_manifest(None, cargo_args, pgo_profiles_dir, bolt_profiles_dir)
+}
+
+/// Finds Cargo metadata of the given manifest, or of the current directory if no manifest
+/// is passed.
+pub(crate) fn get_cargo_ctx_for_manifest(
+    manifest_path: Option<&Path>,
+    cargo_args: &[String],
+    pgo_profiles_dir: Option<PathBuf>,
+    bolt_profiles_dir: Option<PathBuf>,
+) -> anyhow::Result<CargoContext> {
+    let mut cmd = cargo_metadata::MetadataCommand::new();
+    if let Some(manifest_path) = manifest_path {
+        cmd.manifest_path(manifest_path);
+    }
+    let metadata = cmd
+        .exec()
+        .map_err(|error| anyhow::anyhow!("Cannot get cargo metadata: {:?}", error))?;
+    let config = load_config(&metadata)?;
+    let packages = metadata
+        .workspace_packages()
+        .iter()
+        .map(|package| WorkspacePackage {
+            name: package.name.to_string(),
+            version: package.version.to_string(),
+            root: package
+                .manifest_path
+                .parent()
+                .map(|root| root.to_path_buf().into_std_path_buf())
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    let mut ctx = CargoContext {
+        workspace_root: metadata.workspace_root.clone().into_std_path_buf(),
+        packages,
+        target_directory: metadata.target_directory.into_std_path_buf(),
+        pgo_profiles_dir: pgo_profiles_dir.or_else(|| config.profiles_dir.clone()),
+        bolt_profiles_dir: bolt_profiles_dir.or_else(|| config.bolt_profiles_dir.clone()),
+        config,
     };
 
-    Ok(CargoContext {
-        target_directory,
-        pgo_profiles_dir,
-        bolt_profiles_dir,
-    })
+    let cargo_args = parse_cargo_args(ctx.resolve_cargo_args(cargo_args.to_vec()));
+    if let Some(target_dir) = cargo_args.target_dir {
+        ctx.target_directory = target_dir;
+    }
+    Ok(ctx)
+}
+
+/// Returns the directory for context-sensitive profiles that belongs to the PGO profile
+/// directory `pgo_dir`.
+fn cs_pgo_directory(pgo_dir: &Path) -> anyhow::Result<PathBuf> {
+    // Paths like `.` or `..` do not have a file name, so they have to be resolved first.
+    // Otherwise, the directory would end up inside of the PGO profile directory.
+    let pgo_dir = match pgo_dir.file_name() {
+        Some(_) => pgo_dir.to_path_buf(),
+        None => pgo_dir
+            .canonicalize()
+            .unwrap_or_else(|_| pgo_dir.to_path_buf()),
+    };
+    let mut name = pgo_dir
+        .file_name()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cannot store context-sensitive PGO profiles next to the PGO profile directory {}. \
+Use a directory with a name for `--profiles-dir`.",
+                pgo_dir.display()
+            )
+        })?
+        .to_os_string();
+    name.push("-cs");
+    Ok(pgo_dir.with_file_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::workspace::cs_pgo_directory;
+
+    #[test]
+    fn cs_directory_next_to_pgo_directory() {
+        assert_eq!(
+            cs_pgo_directory(Path::new("/tmp/profiles")).unwrap(),
+            Path::new("/tmp/profiles-cs")
+        );
+        assert_eq!(
+            cs_pgo_directory(Path::new("/tmp/profiles/")).unwrap(),
+            Path::new("/tmp/profiles-cs")
+        );
+    }
+
+    #[test]
+    fn cs_directory_next_to_resolved_pgo_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let pgo_dir = dir.path().join("profiles");
+        std::fs::create_dir_all(pgo_dir.join("nested")).unwrap();
+
+        let expected = dir.path().canonicalize().unwrap().join("profiles-cs");
+        assert_eq!(
+            cs_pgo_directory(&pgo_dir.join("nested").join("..")).unwrap(),
+            expected
+        );
+        assert!(cs_pgo_directory(Path::new("/")).is_err());
+    }
 }
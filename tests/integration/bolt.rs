@@ -1,4 +1,5 @@
 use crate::utils::{get_dir_files, init_cargo_project, run_command};
+use cargo_pgo::get_default_target;
 
 use crate::utils::OutputExt;
 
@@ -49,6 +50,135 @@ fn test_bolt_optimize() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn test_bolt_record_optimize() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["bolt", "record"])?.assert_ok();
+    assert!(!get_dir_files(&project.default_bolt_profile_dir().join("foo"))?.is_empty());
+    project.run(&["bolt", "optimize"])?.assert_ok();
+    run_command(project.bolt_optimized_binary())?;
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_bolt_instrument_test() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["bolt", "build", "test"])?.assert_ok();
+    assert!(!get_dir_files(&project.default_bolt_profile_dir().join("foo-bin"))?.is_empty());
+    project.run(&["bolt", "optimize", "test"])?.assert_ok();
+    project
+        .run(&["bolt", "optimize", "test", "--run"])?
+        .assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_bolt_optimize_run_requires_harness() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["bolt", "optimize", "--run"])?
+        .assert_error()
+        .assert_stderr_contains("`--run` can only be used with the `test` and `bench` commands");
+
+    Ok(())
+}
+
+#[test]
+fn test_bolt_instrument_run_unsupported() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["bolt", "build", "run"])?
+        .assert_error()
+        .assert_stderr_contains("cannot be used with BOLT");
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_bolt_run() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["bolt", "run", "--", "--", "foo"])?
+        .assert_ok()
+        .assert_stderr_contains("has written 1 BOLT profile");
+    project.run(&["bolt", "optimize"])?.assert_ok();
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_bolt_run_multiple_binaries() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file("src/bin/bar.rs", "fn main() {}");
+
+    project
+        .run(&["bolt", "run"])?
+        .assert_error()
+        .assert_stderr_contains("use `--bin` to select one of them");
+    project
+        .run(&["bolt", "run", "--bin", "foo", "--", "--", "foo"])?
+        .assert_ok()
+        .assert_stderr_contains("has written 1 BOLT profile");
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_bolt_shared_library() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+
+    project.make_cdylib();
+
+    project.run(&["bolt", "build"])?.assert_ok();
+    let library = project
+        .path("target")
+        .join(get_default_target()?)
+        .join("release");
+    assert!(library.join("libfoo-bolt-instrumented.so").is_file());
+    project.run(&["bolt", "optimize"])?.assert_ok();
+    assert!(library.join("libfoo-bolt-optimized.so").is_file());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_bolt_optimize_replace() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["bolt", "build"])?.assert_ok();
+    run_command(project.bolt_instrumented_binary())?;
+    project
+        .run(&["bolt", "optimize", "--replace", "--output-dir", "dist"])?
+        .assert_ok();
+
+    let binary = project.main_binary();
+    assert!(binary.with_file_name("foo.pre-bolt").is_file());
+    assert!(project.bolt_optimized_binary().is_file());
+    assert!(project.path("dist/foo").is_file());
+    run_command(binary)?;
+
+    // Running the optimization again has to process the original binary
+    project.run(&["bolt", "optimize", "--replace"])?.assert_ok();
+    // Instrumentation has to process the original binary as well
+    project.run(&["bolt", "build"])?.assert_ok();
+    assert!(project.bolt_optimized_binary().is_file());
+
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn test_bolt_pgo_optimize() -> anyhow::Result<()> {
@@ -147,3 +277,89 @@ fn test_bolt_optimize_bolt_args() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn test_bolt_optimize_bolt_preset() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["bolt", "build"])?.assert_ok();
+    run_command(project.bolt_instrumented_binary())?;
+
+    project
+        .run(&[
+            "bolt",
+            "optimize",
+            "--bolt-preset",
+            "conservative",
+            "--bolt-extra-args",
+            "-print-profile-stats",
+        ])?
+        .assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_bolt_preset_conflicts_with_bolt_args() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&[
+            "bolt",
+            "optimize",
+            "--bolt-preset",
+            "size",
+            "--bolt-args",
+            "-update-debug-sections",
+        ])?
+        .assert_error()
+        .assert_stderr_contains("cannot be used with");
+
+    Ok(())
+}
+
+#[test]
+fn test_bolt_instrument_stripped_profile() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+
+    let manifest = std::fs::read_to_string(project.path("Cargo.toml"))?;
+    project.file(
+        "Cargo.toml",
+        &format!("{manifest}\n[profile.release]\nstrip = true\n"),
+    );
+
+    project
+        .run(&["bolt", "build"])?
+        .assert_error()
+        .assert_stderr_contains("Cargo profile `release` strips symbols");
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_bolt_optimize_stripped_binary() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .cmd(&["bolt", "optimize"])
+        .env("RUSTFLAGS", "-Cstrip=symbols")
+        .run()?
+        .assert_error()
+        .assert_stderr_contains("does not contain a symbol table");
+
+    Ok(())
+}
+
+#[test]
+fn test_bolt_profile_weight_requires_pgo() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["bolt", "build", "--profile-weight", "api=2"])?
+        .assert_error()
+        .assert_stderr_contains("--with-pgo");
+
+    Ok(())
+}
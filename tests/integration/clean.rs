@@ -28,3 +28,18 @@ fn test_clean_bolt_dir() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_clean_does_not_create_directories() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+    let profiles_dir = project.path("profiles");
+
+    project
+        .run(&["clean", "--profiles-dir", profiles_dir.to_str().unwrap()])?
+        .assert_ok();
+    assert!(!profiles_dir.exists());
+    assert!(!project.path("profiles-cs").exists());
+    assert!(!project.default_sample_profile_dir().exists());
+
+    Ok(())
+}
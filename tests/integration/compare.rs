@@ -0,0 +1,91 @@
+use crate::utils::{init_cargo_project, parse_json_events, run_command};
+
+use crate::utils::OutputExt;
+
+#[test]
+fn test_compare() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let output = project
+        .run(&[
+            "compare",
+            "--workload",
+            "{bin}",
+            "--runs",
+            "2",
+            "--warmup",
+            "0",
+        ])?
+        .assert_ok();
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("baseline"));
+    assert!(stdout.contains("pgo"));
+
+    let results_path = project.path("target/pgo-compare/results.json");
+    let results: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(results_path)?)?;
+    let variants = results[0]["variants"].as_array().unwrap();
+    assert_eq!(variants.len(), 2);
+    assert_eq!(variants[1]["wall_times"].as_array().unwrap().len(), 2);
+
+    project
+        .run(&["compare", "--workload", "{bin}", "--runs", "1", "--reuse"])?
+        .assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_compare_reuse_without_binaries() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["compare", "--workload", "{bin}", "--reuse"])?
+        .assert_error()
+        .assert_stderr_contains("without `--reuse` first");
+
+    Ok(())
+}
+
+#[test]
+fn test_compare_bolt_without_optimized_binary() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    project
+        .run(&["compare", "--workload", "{bin}", "--bolt"])?
+        .assert_error()
+        .assert_stderr_contains("cargo pgo bolt optimize");
+
+    Ok(())
+}
+
+#[test]
+fn test_compare_json_output() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let output = project
+        .run(&[
+            "compare",
+            "--workload",
+            "{bin}",
+            "--runs",
+            "1",
+            "--warmup",
+            "0",
+            "--output",
+            "json",
+        ])?
+        .assert_ok();
+    parse_json_events(&output.stdout)?;
+    assert!(String::from_utf8(output.stderr)?.contains("Results of workload"));
+
+    Ok(())
+}
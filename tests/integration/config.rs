@@ -0,0 +1,122 @@
+use crate::utils::{get_dir_files, init_cargo_project, run_command};
+
+use crate::utils::OutputExt;
+
+#[test]
+fn test_config_profiles_dir() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "pgo.toml",
+        r#"
+profiles-dir = "custom-profiles"
+"#,
+    );
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    assert!(!get_dir_files(&project.path("custom-profiles"))?.is_empty());
+    project.run(&["optimize"])?.assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_config_cli_overrides_profiles_dir() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "pgo.toml",
+        r#"
+profiles-dir = "custom-profiles"
+"#,
+    );
+
+    project
+        .run(&["build", "--profiles-dir", "cli-profiles"])?
+        .assert_ok();
+    assert!(project.path("cli-profiles").is_dir());
+    assert!(!project.path("custom-profiles").is_dir());
+
+    Ok(())
+}
+
+#[test]
+fn test_config_package_metadata_workloads() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "src/main.rs",
+        r#"
+use std::io::BufRead;
+
+fn main() {
+    let line = std::io::stdin().lock().lines().next().unwrap().unwrap();
+    assert_eq!(line, "input");
+    assert_eq!(std::env::args().nth(1).unwrap(), "arg");
+    assert_eq!(std::env::var("WORKLOAD").unwrap(), "1");
+}
+"#,
+    );
+    project.file("input.txt", "input\n");
+    let manifest = std::fs::read_to_string(project.path("Cargo.toml"))?;
+    project.file(
+        "Cargo.toml",
+        &format!(
+            r#"{manifest}
+[package.metadata.pgo]
+cargo-args = ["--bin", "foo"]
+
+[[package.metadata.pgo.workloads]]
+binary = "foo"
+args = ["arg"]
+env = {{ WORKLOAD = "1" }}
+stdin = "input.txt"
+repetitions = 2
+"#
+        ),
+    );
+
+    project.run(&["pipeline"])?.assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_config_pipeline_without_workloads() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["pipeline"])?
+        .assert_error()
+        .assert_stderr_contains("No workloads were specified");
+
+    Ok(())
+}
+
+#[test]
+fn test_config_invalid() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file("pgo.toml", "unknown-key = 1");
+
+    project
+        .run(&["build"])?
+        .assert_error()
+        .assert_stderr_contains("Cannot parse");
+
+    Ok(())
+}
+
+#[test]
+fn test_config_info() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "pgo.toml",
+        r#"
+cargo-args = ["--bin", "foo"]
+"#,
+    );
+
+    let output = project.run(&["info"])?;
+    assert!(output.stdout().contains(r#"cargo-args = ["--bin", "foo"]"#));
+
+    Ok(())
+}
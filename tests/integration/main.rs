@@ -1,4 +1,10 @@
 mod bolt;
 mod clean;
+mod compare;
+mod config;
 mod pgo;
+mod pipeline;
+mod profile;
+mod sample;
+mod session;
 mod utils;
@@ -1,4 +1,4 @@
-use crate::utils::{get_dir_files, init_cargo_project, run_command};
+use crate::utils::{get_dir_files, init_cargo_project, parse_json_events, run_command};
 use cargo_pgo::get_default_target;
 use tempfile::TempDir;
 
@@ -29,7 +29,20 @@ fn test_instrument_run_instrumented_binary() -> anyhow::Result<()> {
 #[test]
 fn test_optimize_no_profile() -> anyhow::Result<()> {
     let project = init_cargo_project()?;
-    project.run(&["optimize"])?.assert_error();
+    let output = project.run(&["optimize"])?.assert_error();
+    assert_eq!(output.status.code(), Some(4));
+
+    Ok(())
+}
+
+#[test]
+fn test_build_failure_exit_code() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file("src/main.rs", "fn main() { foo }");
+
+    let output = project.run(&["build"])?.assert_error();
+    assert_eq!(output.status.code(), Some(6));
+    output.assert_stderr_contains("Cargo finished with an error");
 
     Ok(())
 }
@@ -233,7 +246,7 @@ fn test_respect_target_dir() -> anyhow::Result<()> {
 
 #[test]
 fn test_respect_profile() -> anyhow::Result<()> {
-    if !(rustc_version::version()? >= semver::Version::new(1, 57, 0)) {
+    if rustc_version::version()? < semver::Version::new(1, 57, 0) {
         println!("Skipping test_respect_profile because of too old rustc");
         return Ok(());
     }
@@ -320,7 +333,7 @@ fn test_respect_existing_rustflags() -> anyhow::Result<()> {
 /// This only works for Rust 1.63+.
 #[test]
 fn test_override_build_rustflags_from_config() -> anyhow::Result<()> {
-    if !(rustc_version::version()? >= semver::Version::new(1, 63, 0)) {
+    if rustc_version::version()? < semver::Version::new(1, 63, 0) {
         return Ok(());
     }
 
@@ -344,7 +357,7 @@ rustflags = ["-Ctarget-cpu=native"]
 
 #[test]
 fn test_respect_existing_target_rustflags_from_config() -> anyhow::Result<()> {
-    if !(rustc_version::version()? >= semver::Version::new(1, 63, 0)) {
+    if rustc_version::version()? < semver::Version::new(1, 63, 0) {
         return Ok(());
     }
 
@@ -408,3 +421,302 @@ fn test_change_profile_directory() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_missing_profile_report() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    project.file(
+        "src/main.rs",
+        r#"
+mod inner {
+    #[inline(never)]
+    pub fn added_function(x: usize) -> usize {
+        x * 7 + x / 3
+    }
+}
+
+fn main() {
+    let count = std::env::args().count();
+    if count > 5 {
+        println!("{}", inner::added_function(count));
+    }
+}
+"#,
+    );
+
+    let report = project.path("report.json");
+    let output = project
+        .run(&[
+            "optimize",
+            "--missing-profile-report",
+            report.to_str().unwrap(),
+            "--missing-profile-report-format",
+            "json",
+        ])?
+        .assert_ok();
+    output.assert_stderr_contains("foo::inner");
+    output.assert_stderr_contains("% of all functions");
+
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(report)?)?;
+    let krate = report["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|krate| krate["name"] == "foo")
+        .unwrap();
+    assert_eq!(krate["modules"][0]["name"], "foo::inner");
+    assert_eq!(
+        krate["modules"][0]["functions"][0],
+        "foo::inner::added_function"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_profile_weights() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+    project.run(&["build"])?.assert_ok();
+
+    for workload in ["api", "batch"] {
+        let status = std::process::Command::new(project.main_binary())
+            .env(
+                "LLVM_PROFILE_FILE",
+                project
+                    .default_pgo_profile_dir()
+                    .join(workload)
+                    .join("%m_%p.profraw"),
+            )
+            .status()?;
+        assert!(status.success());
+    }
+
+    project
+        .run(&["optimize", "--profile-weight", "api=4"])?
+        .assert_ok()
+        .assert_stderr_contains("merged with the following weights");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_invalid_profile_weight() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+    project
+        .run(&["optimize", "--profile-weight", "api"])?
+        .assert_error()
+        .assert_stderr_contains("<subdirectory>=<weight>");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_profile_file() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+    project.run(&["optimize"])?.assert_ok();
+
+    let merged = get_dir_files(&project.default_pgo_profile_dir())?
+        .into_iter()
+        .find(|file| file.extension().is_some_and(|ext| ext == "profdata"))
+        .unwrap();
+    let profile = project.path("committed.profdata");
+    std::fs::copy(merged, &profile)?;
+    std::fs::remove_dir_all(project.default_pgo_profile_dir())?;
+
+    project
+        .run(&["optimize", "--profile-file", profile.to_str().unwrap()])?
+        .assert_ok();
+    run_command(project.main_binary())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_profile_file_missing() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["optimize", "--profile-file", "missing.profdata"])?
+        .assert_error()
+        .assert_stderr_contains("does not exist");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_already_merged_profile() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+    project.run(&["optimize"])?.assert_ok();
+
+    for file in get_dir_files(&project.default_pgo_profile_dir())? {
+        if file.extension().is_some_and(|ext| ext == "profraw") {
+            std::fs::remove_file(file)?;
+        }
+    }
+
+    project
+        .run(&["optimize"])?
+        .assert_ok()
+        .assert_stderr_contains("using already merged profile");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_stale_profiles() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let manifest = std::fs::read_to_string(project.path("Cargo.toml"))?;
+    project.file(
+        "Cargo.toml",
+        &manifest.replace(r#"version = "0.1.0""#, r#"version = "0.2.0""#),
+    );
+
+    project
+        .run(&["optimize", "--require-fresh-profiles"])?
+        .assert_error()
+        .assert_stderr_contains("crate `foo` changed from version 0.1.0 to 0.2.0");
+    project
+        .run(&["optimize"])?
+        .assert_ok()
+        .assert_stderr_contains("The profiles might be stale");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_changed_sources() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    project.file("src/main.rs", r#"fn main() { println!("Hello, PGO!"); }"#);
+
+    project
+        .run(&["optimize", "--require-fresh-profiles"])?
+        .assert_error()
+        .assert_stderr_contains("sources of the workspace have changed");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_fresh_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    project
+        .run(&["optimize", "--require-fresh-profiles"])?
+        .assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_cs_build_without_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["cs-build"])?
+        .assert_error()
+        .assert_stderr_contains("No profile files were found");
+
+    Ok(())
+}
+
+#[test]
+fn test_optimize_cs_without_cs_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    project
+        .run(&["optimize", "--cs"])?
+        .assert_error()
+        .assert_stderr_contains("cs-pgo-profiles");
+
+    Ok(())
+}
+
+// Requires `clang` and `lld` that use the same LLVM version as `rustc`.
+#[test]
+#[ignore]
+fn test_cs_build_optimize() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+    project.run(&["cs-build"])?.assert_ok();
+    run_command(project.main_binary())?;
+    project.run(&["optimize", "--cs"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_instrument_shared_library() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+
+    project.make_cdylib();
+
+    project
+        .run(&["build"])?
+        .assert_ok()
+        .assert_stderr_contains("PGO-instrumented library foo built successfully");
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    let output = project.run(&["build", "--output", "json"])?.assert_ok();
+    let events = parse_json_events(&output.stdout)?;
+    assert_eq!(events[0]["event"], "profile-dir");
+    let instrumented = events
+        .iter()
+        .find(|event| event["event"] == "instrumented")
+        .unwrap();
+    assert_eq!(instrumented["target"], "foo");
+    assert_eq!(
+        instrumented["path"].as_str().unwrap(),
+        project.main_binary().to_str().unwrap()
+    );
+
+    run_command(project.main_binary())?;
+
+    let output = project.run(&["optimize", "--output", "json"])?.assert_ok();
+    let events = parse_json_events(&output.stdout)?;
+    let merged = events
+        .iter()
+        .find(|event| event["event"] == "merged-profile")
+        .unwrap();
+    assert!(
+        merged["path"]
+            .as_str()
+            .unwrap()
+            .contains(merged["hash"].as_str().unwrap())
+    );
+    assert!(events.iter().any(|event| event["event"] == "optimized"));
+
+    Ok(())
+}
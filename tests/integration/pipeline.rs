@@ -0,0 +1,154 @@
+use crate::utils::{get_dir_files, init_cargo_project, parse_json_events, run_command};
+
+use crate::utils::OutputExt;
+
+#[test]
+fn test_pipeline() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["pipeline", "--workload", "{bin}"])?
+        .assert_ok();
+    run_command(project.main_binary())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_multiple_workloads() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "src/main.rs",
+        r#"
+fn main() {
+    let arg = std::env::args().nth(1).unwrap();
+    assert!(arg == "a" || arg == "b");
+}
+"#,
+    );
+
+    project
+        .run(&[
+            "pipeline",
+            "--workload",
+            "{bin:foo} a",
+            "--workload",
+            "{bin} b",
+        ])?
+        .assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_failing_workload() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "src/main.rs",
+        r#"
+fn main() {
+    std::process::exit(1);
+}
+"#,
+    );
+
+    project
+        .run(&["pipeline", "--workload", "{bin}"])?
+        .assert_error()
+        .assert_stderr_contains("has failed");
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_workload_without_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["pipeline", "--workload", "cargo --version"])?
+        .assert_error()
+        .assert_stderr_contains("did not produce any PGO profiles");
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_pipeline_bolt() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["pipeline", "--bolt", "--workload", "{bin}"])?
+        .assert_ok();
+    run_command(project.bolt_optimized_binary())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_weighted_workloads() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "pgo.toml",
+        r#"
+[[workloads]]
+name = "api"
+weight = 4
+
+[[workloads]]
+name = "batch"
+"#,
+    );
+
+    project
+        .run(&["pipeline"])?
+        .assert_ok()
+        .assert_stderr_contains("merged with the following weights");
+    assert!(project.default_pgo_profile_dir().join("api").is_dir());
+    assert!(project.default_pgo_profile_dir().join("batch").is_dir());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_pipeline_bolt_weighted_workloads() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "pgo.toml",
+        r#"
+[[workloads]]
+name = "api"
+weight = 4
+
+[[workloads]]
+name = "batch"
+"#,
+    );
+
+    project.run(&["pipeline", "--bolt"])?.assert_ok();
+
+    // The BOLT builds have to use the same (weighted) PGO profile as the PGO-optimized build
+    let merged_profiles: Vec<_> = get_dir_files(&project.default_pgo_profile_dir())?
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "profdata"))
+        .collect();
+    assert_eq!(merged_profiles.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_pipeline_json_output() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    // The workload prints `Hello, world!`, which must not end up in stdout
+    let output = project
+        .run(&["pipeline", "--workload", "{bin}", "--output", "json"])?
+        .assert_ok();
+    let events = parse_json_events(&output.stdout)?;
+    assert!(events.iter().any(|event| event["event"] == "optimized"));
+    assert!(String::from_utf8(output.stderr)?.contains("Hello, world!"));
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use crate::utils::{Cmd, init_cargo_project, run_command};
+
+use crate::utils::OutputExt;
+
+#[test]
+fn test_profile_show() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let output = project.run(&["profile", "show"])?.assert_ok();
+    assert!(output.stdout().contains("foo::main"));
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_show_json_crate_filter() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let output = project
+        .run(&["profile", "show", "--json", "--crate", "foo"])?
+        .assert_ok();
+    let output: serde_json::Value = serde_json::from_str(&output.stdout())?;
+    let functions = output["functions"].as_array().unwrap();
+    assert!(!functions.is_empty());
+    assert!(functions.iter().all(|function| function["crate"] == "foo"));
+    assert!(
+        functions
+            .iter()
+            .any(|function| function["name"] == "foo::main" && function["entry_count"] == 1)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_show_no_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project
+        .run(&["profile", "show"])?
+        .assert_error()
+        .assert_stderr_contains("No profile files were found");
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_diff() -> anyhow::Result<()> {
+    let mut project = init_cargo_project()?;
+    project.file(
+        "src/main.rs",
+        r#"
+#[inline(never)]
+fn extra_work(count: usize) -> usize {
+    (0..count).map(|x| x * 3).sum()
+}
+
+fn main() {
+    let count = std::env::args().count();
+    if count > 1 {
+        println!("{}", extra_work(count * 100));
+    }
+}
+"#,
+    );
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+    let base = project.path("base");
+    std::fs::rename(project.default_pgo_profile_dir(), &base)?;
+
+    project.run(&["build"])?.assert_ok();
+    Cmd::default()
+        .args(&[project.main_binary().to_str().unwrap(), "arg"])
+        .run()?
+        .assert_ok();
+
+    let output = project
+        .run(&[
+            "profile",
+            "diff",
+            "--json",
+            base.to_str().unwrap(),
+            project.default_pgo_profile_dir().to_str().unwrap(),
+        ])?
+        .assert_ok();
+    let output: serde_json::Value = serde_json::from_str(&output.stdout())?;
+    assert!(
+        output["appeared"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|function| function["name"] == "foo::extra_work")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_export_import() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let bundle = project.path("profile.tar.gz");
+    project
+        .run(&["profile", "export", bundle.to_str().unwrap()])?
+        .assert_ok();
+
+    let imported = project.path("imported-profiles");
+    project
+        .run(&[
+            "profile",
+            "import",
+            bundle.to_str().unwrap(),
+            "--profiles-dir",
+            imported.to_str().unwrap(),
+        ])?
+        .assert_ok();
+    project
+        .run(&["optimize", "--profiles-dir", imported.to_str().unwrap()])?
+        .assert_ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_import_warns_about_raw_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let bundle = project.path("profile.tar.gz");
+    project
+        .run(&["profile", "export", bundle.to_str().unwrap()])?
+        .assert_ok();
+    project
+        .run(&["profile", "import", bundle.to_str().unwrap()])?
+        .assert_ok()
+        .assert_stderr_contains("contains 1 `.profraw` file");
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_import_llvm_mismatch() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    project.run(&["build"])?.assert_ok();
+    run_command(project.main_binary())?;
+
+    let bundle = project.path("profile.tar.gz");
+    project
+        .run(&["profile", "export", bundle.to_str().unwrap()])?
+        .assert_ok();
+
+    // Recreate the bundle with a manifest from a different LLVM version
+    let mut archive =
+        tar::Archive::new(flate2::read::GzDecoder::new(std::fs::File::open(&bundle)?));
+    let extracted = project.path("extracted");
+    archive.unpack(&extracted)?;
+    let manifest_path = extracted.join("manifest.json");
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)?;
+    manifest["llvm_version"] = "1.0".into();
+    std::fs::write(&manifest_path, serde_json::to_string(&manifest)?)?;
+
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        std::fs::File::create(&bundle)?,
+        flate2::Compression::default(),
+    ));
+    builder.append_path_with_name(&manifest_path, "manifest.json")?;
+    builder.append_path_with_name(extracted.join("profile.profdata"), "profile.profdata")?;
+    builder.into_inner()?.finish()?;
+
+    let output = project
+        .run(&["profile", "import", bundle.to_str().unwrap()])?
+        .assert_error();
+    output.assert_stderr_contains("was created with LLVM 1.0");
+    assert_eq!(output.status.code(), Some(8));
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::utils::{OutputExt, get_dir_files, init_cargo_project};
+use rustc_version::Channel;
+
+#[test]
+fn test_sample_build_stores_binary() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+    let output = project.run(&["sample", "build"])?.assert_ok();
+
+    // The copy has to keep the file name of the recorded binary
+    let binary_name = project.main_binary().file_name().unwrap().to_owned();
+    let binary = project
+        .default_sample_profile_dir()
+        .join("foo")
+        .join(&binary_name);
+    assert!(binary.is_file());
+    output.assert_stderr_contains(&format!("-- {}", project.main_binary().display()));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sample_optimize_custom_converter() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut project = init_cargo_project()?;
+    // Stand-in for `llvm-profgen`, which writes a fixed sample profile
+    project.file(
+        "converter.sh",
+        r#"#!/bin/sh
+for arg in "$@"; do
+    case "$arg" in
+        --binary=*) printf '%s' "${arg#--binary=}" > binary.txt ;;
+        --output=*) printf 'main:100:1\n 1: 100\n' > "${arg#--output=}" ;;
+    esac
+done
+"#,
+    );
+    let converter = project.path("converter.sh");
+    std::fs::set_permissions(&converter, std::fs::Permissions::from_mode(0o755))?;
+
+    project.run(&["sample", "build"])?.assert_ok();
+    std::fs::write(
+        project
+            .default_sample_profile_dir()
+            .join("foo")
+            .join("perf.data"),
+        "",
+    )?;
+
+    let output = project.run(&[
+        "sample",
+        "optimize",
+        "--converter",
+        converter.to_str().unwrap(),
+    ])?;
+    if let Channel::Nightly = rustc_version::version_meta()?.channel {
+        output.assert_ok();
+        // The binary passed to the converter has the same file name as the recorded binary
+        let binary = std::fs::read_to_string(project.path("binary.txt"))?;
+        assert_eq!(
+            std::path::Path::new(&binary).file_name(),
+            project.main_binary().file_name()
+        );
+        let profiles = get_dir_files(&project.default_sample_profile_dir())?;
+        assert!(
+            profiles
+                .iter()
+                .any(|path| { path.extension().and_then(|ext| ext.to_str()) == Some("prof") })
+        );
+    } else {
+        output
+            .assert_error()
+            .assert_stderr_contains("requires a nightly compiler");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sample_optimize_requires_binary() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+    project
+        .run(&["sample", "optimize", "--perf-data", "perf.data"])?
+        .assert_error()
+        .assert_stderr_contains("--binary");
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use cargo_pgo::session::PgoSession;
+
+use crate::utils::{init_cargo_project, run_command};
+
+#[test]
+fn test_session() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    let instrumented = PgoSession::new(project.path("Cargo.toml")).instrument()?;
+    assert_eq!(instrumented.artifacts().len(), 1);
+    assert_eq!(
+        instrumented.profiles_dir(),
+        project.default_pgo_profile_dir()
+    );
+
+    let output = instrumented.run_workload(&"{bin}".parse()?)?.optimize()?;
+    assert_eq!(output.artifacts.len(), 1);
+    assert_eq!(output.artifacts[0].name, "foo");
+    assert_eq!(output.artifacts[0].path, project.main_binary());
+    assert!(output.profile.path.is_file());
+    assert!(
+        output
+            .profile
+            .path
+            .starts_with(project.default_pgo_profile_dir())
+    );
+    assert_eq!(output.profile.file_count, 1);
+    assert!(output.profile.total_size > 0);
+    assert!(
+        output
+            .profile
+            .path
+            .to_string_lossy()
+            .contains(&output.profile.hash)
+    );
+    run_command(project.main_binary())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_session_workload_without_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    let error = PgoSession::new(project.path("Cargo.toml"))
+        .instrument()?
+        .run_workload(&"true".parse()?)
+        .err()
+        .unwrap();
+    assert!(
+        error
+            .to_string()
+            .contains("did not produce any PGO profiles")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_session_optimize_without_profiles() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    assert!(
+        PgoSession::new(project.path("Cargo.toml"))
+            .optimize()
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_session_quiet() -> anyhow::Result<()> {
+    let project = init_cargo_project()?;
+
+    let output = PgoSession::new(project.path("Cargo.toml"))
+        .quiet(true)
+        .instrument()?
+        .run_workload(&"{bin}".parse()?)?
+        .optimize()?;
+    assert_eq!(output.artifacts.len(), 1);
+
+    Ok(())
+}
@@ -62,6 +62,16 @@ impl CargoProject {
         self
     }
 
+    /// Turns the project into a `cdylib` crate with a single exported function.
+    pub fn make_cdylib(&mut self) -> &mut Self {
+        let manifest = std::fs::read_to_string(self.path("Cargo.toml")).unwrap();
+        self.file(
+            "Cargo.toml",
+            &format!("{manifest}\n[lib]\ncrate-type = [\"cdylib\"]\n"),
+        )
+        .file("src/lib.rs", "pub extern \"C\" fn foo() {}")
+    }
+
     pub fn main_binary(&self) -> PathBuf {
         self.dir
             .join("target")
@@ -90,6 +100,10 @@ impl CargoProject {
         self.path("target/pgo-profiles")
     }
 
+    pub fn default_sample_profile_dir(&self) -> PathBuf {
+        self.path("target/pgo-sample-profiles")
+    }
+
     pub fn default_bolt_profile_dir(&self) -> PathBuf {
         self.path("target/bolt-profiles")
     }
@@ -253,3 +267,11 @@ pub fn get_dir_files(directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
 
     Ok(files)
 }
+
+/// Parses each line of `stdout` as a JSON event.
+pub fn parse_json_events(stdout: &[u8]) -> anyhow::Result<Vec<serde_json::Value>> {
+    Ok(String::from_utf8(stdout.to_vec())?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?)
+}